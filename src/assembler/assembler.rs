@@ -1,27 +1,66 @@
-use crate::instructions::{
-    Error, Instructions, Label, Marker, Resolver, Symbol, CURRENTINSTRUCTION, DEFLABEL, DEFSYMBOL,
-    NEXTINSTRUCTION,
+use super::{
+    listing::{Listing, ListingLine, MapEntry, MapKind},
+    object::{Object, ObjectSymbol, Relocation, RelocationTarget, Section},
+};
+use crate::{
+    instructions::{
+        anonymous_reference, is_anonymous_label, is_local_label, Diagnostic, Error, Expression,
+        Instructions, Label, Marker, Reference, Register, Resolver, SafeInstruction, SectionKind,
+        Severity, SourceLocation, Symbol, ALIGN, ASCII, CALL, CURRENTINSTRUCTION, DATA, DEFLABEL,
+        DEFSYMBOL, FILL, JMP, JR, NEXTINSTRUCTION, ORG, RET, SECTION, WORD,
+    },
+    USER_CODE_START,
 };
 
 use std::{
-    any::{Any, TypeId},
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     rc::Rc,
 };
 
+// Computer::run plants a jump back to the code region here
+const WRAP_AROUND_JUMP: u16 = 0xFEFE;
+
 #[derive(Clone)]
 pub struct Assembler {
     reserved_symbols: ReservedSymbols,
     labels: HashMap<String, u16>,
     symbols: HashMap<String, u16>,
+    anonymous: HashMap<String, Vec<AnonymousLabel>>,
+    // the global label that local labels belong to, and the index of the
+    // instruction being placed or emitted
+    scope: String,
+    index: usize,
+    // where each named label is defined, and the definitions looked up while
+    // assembling, shared with the clones handed out as resolvers
+    definitions: HashMap<String, usize>,
+    used: Rc<RefCell<HashSet<usize>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// an anonymous label like 1:, the same name may be defined many times
+#[derive(Clone)]
+struct AnonymousLabel {
+    index: usize,
+    address: u16,
+    section: SectionKind,
 }
 
 impl Resolver for Assembler {
     fn label_resolver(&self, label: &Label) -> Result<u16, Error> {
-        if let Some(v) = self.labels.get(&label.name) {
+        if let Some(anonymous) = self.anonymous_label(&label.name) {
+            self.used.borrow_mut().insert(anonymous.index);
+            return Ok(anonymous.address);
+        }
+
+        let name = self.label_name(&label.name);
+        if let Some(v) = self.labels.get(&name) {
+            if let Some(index) = self.definitions.get(&name) {
+                self.used.borrow_mut().insert(*index);
+            }
             Ok(*v)
         } else {
-            Err(Error::UnknownLabel(label.name.clone()))
+            Err(Error::UnknownLabel(name))
         }
     }
 
@@ -40,178 +79,549 @@ impl Assembler {
             reserved_symbols: ReservedSymbols::new(),
             labels: HashMap::new(),
             symbols: HashMap::new(),
+            anonymous: HashMap::new(),
+            scope: String::new(),
+            index: 0,
+            definitions: HashMap::new(),
+            used: Rc::new(RefCell::new(HashSet::new())),
+            diagnostics: Vec::new(),
         }
     }
 
+    // label addresses from the last process or string call, local labels are
+    // kept as global.local
+    pub fn labels(&self) -> &HashMap<String, u16> {
+        &self.labels
+    }
+
+    // errors and warnings from the last process call, warnings stay here
+    // when assembly succeeds
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // assembles in one pass over everything, all errors come back together
+    // as Error::Assembly with the warnings found alongside them
     pub fn process(
         &mut self,
         code_start_offset: u16,
         instructions: Option<Instructions>,
     ) -> Result<Vec<u16>, Error> {
-        self.labels = HashMap::new();
-        self.symbols = HashMap::new();
+        self.reset();
+        let instructions = instructions.ok_or(Error::NoInstructions)?;
+        let mut report = Report::new(&instructions);
 
         let mut position: u16 = 0;
+        let mut pending = Vec::new();
 
         //calculate labels and symbols
-        for instruction in instructions.as_ref().unwrap().instructions.iter() {
-            position += instruction.size();
-
-            if instruction.as_any().type_id() == TypeId::of::<DEFLABEL>() {
-                match instruction.as_any().downcast_ref::<DEFLABEL>() {
-                    Some(lable) => {
-                        if self.labels.contains_key(&lable.name) {
-                            return Err(Error::LabelExist(lable.name.clone()));
-                        }
+        for (index, instruction) in instructions.instructions.iter().enumerate() {
+            self.enter(index, instruction);
+            let address = position.wrapping_add(code_start_offset);
+            position = position.wrapping_add(
+                instruction
+                    .placed_size(address)
+                    .unwrap_or_else(|e| report.error(index, e, 0)),
+            );
 
-                        self.labels
-                            .insert(lable.name.clone(), position + code_start_offset);
-                    }
-                    None => return Err(Error::DowncastError),
+            if let Some(label) = instruction.as_any().downcast_ref::<DEFLABEL>() {
+                let address = position.wrapping_add(code_start_offset);
+                if let Err(e) = self.define_label(&label.name, address, SectionKind::Code) {
+                    report.error(index, e, ());
                 }
             }
-            if instruction.as_any().type_id() == TypeId::of::<DEFSYMBOL>() {
-                match instruction.as_any().downcast_ref::<DEFSYMBOL>() {
-                    Some(symbol) => {
-                        if self.symbols.contains_key(&symbol.name) {
-                            return Err(Error::SymbolExist(symbol.name.clone()));
-                        }
-
-                        if self.reserved_symbols.is_reserved_symbol(&symbol.name) {
-                            return Err(Error::SymbolReserved(symbol.name.clone()));
-                        }
-
-                        self.symbols.insert(symbol.name.clone(), symbol.value);
-                    }
-                    None => return Err(Error::DowncastError),
-                };
+            if let Some(symbol) = instruction.as_any().downcast_ref::<DEFSYMBOL>() {
+                if let Err(e) = self.add_symbol(symbol, &mut pending) {
+                    report.error(index, e, ());
+                }
             }
         }
+        for (index, e) in self.resolve_symbols(pending, false) {
+            report.error(index, e, ());
+        }
 
         let mut emitted = Vec::new();
-        let mut i = 0;
         position = 0;
-        for instruction in instructions.clone().unwrap().instructions {
-            if instruction.as_any().type_id() == TypeId::of::<DEFLABEL>()
-                || instruction.as_any().type_id() == TypeId::of::<DEFSYMBOL>()
-            {
+        for (index, instruction) in instructions.instructions.iter().enumerate() {
+            self.enter(index, instruction);
+            if instruction.as_any().is::<DEFLABEL>() || instruction.as_any().is::<DEFSYMBOL>() {
                 continue;
             }
 
+            let address = position.wrapping_add(code_start_offset);
+            let size = match instruction.placed_size(address) {
+                Ok(size) => size,
+                // already reported in the first pass
+                Err(_) => continue,
+            };
+            self.symbols.insert(CURRENTINSTRUCTION.to_string(), address);
             self.symbols
-                .insert(CURRENTINSTRUCTION.to_string(), position + code_start_offset);
-            self.symbols.insert(
-                NEXTINSTRUCTION.to_string(),
-                get_next_executable_instruction_loc(
-                    *self.symbols.get(CURRENTINSTRUCTION).unwrap(),
-                    i,
-                    instructions.clone().unwrap(),
+                .insert(NEXTINSTRUCTION.to_string(), address.wrapping_add(size));
+
+            match instruction.emit(Some(Rc::new(self.clone()))) {
+                Ok(mut words) => emitted.append(&mut words),
+                Err(e) => report.error(index, e, ()),
+            }
+            if size > 0 {
+                self.check_placement(&mut report, index, address, size);
+            }
+            self.check_data(&mut report, index, instruction);
+
+            position = position.wrapping_add(size);
+        }
+
+        self.check_labels(&mut report);
+        check_unreachable(&mut report);
+        check_calls(&mut report);
+
+        self.diagnostics = report.finish();
+        match self
+            .diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+        {
+            true => Err(Error::Assembly(self.diagnostics.clone())),
+            false => Ok(emitted),
+        }
+    }
+
+    // words in the reserved area below the code region, or in the jump back
+    // to it that Computer::run plants at 0xFEFE
+    fn check_placement(&self, report: &mut Report, index: usize, address: u16, size: u16) {
+        let end = address as u32 + size as u32;
+        if (address as u32) < USER_CODE_START as u32 && !report.warned(Check::Reserved) {
+            report.warning(
+                index,
+                Check::Reserved,
+                format!(
+                    "0x{:04X} is in the reserved area 0x0000-0x{:04X}",
+                    address,
+                    USER_CODE_START - 1
                 ),
+                format!("assemble at 0x{:04X} or above", USER_CODE_START),
             );
+        }
+        if end > WRAP_AROUND_JUMP as u32 && !report.warned(Check::WrapAround) {
+            report.warning(
+                index,
+                Check::WrapAround,
+                format!(
+                    "the program runs past 0x{:04X} into the jump back to 0x{:04X}",
+                    WRAP_AROUND_JUMP, USER_CODE_START
+                ),
+                "make the program smaller or start it lower".to_string(),
+            );
+        }
+    }
 
-            emitted.append(&mut instruction.emit(Some(Rc::new(self.clone())))?);
-
-            position += instruction.size();
-            i += 1;
+    // DATA values that are negative only fit once they wrap around
+    fn check_data(&self, report: &mut Report, index: usize, instruction: &SafeInstruction) {
+        let expression = match instruction.as_any().downcast_ref::<DATA<Expression>>() {
+            Some(data) => data.data(),
+            None => return,
+        };
+        if let Ok(value) = expression.value(Some(Rc::new(self.clone()))) {
+            if value < 0 {
+                report.warning(
+                    index,
+                    Check::Data,
+                    format!(
+                        "'{}' = {} does not fit in an unsigned word and is stored as 0x{:04X}",
+                        expression, value, value as u16
+                    ),
+                    format!("write 0x{:04X} if that is the value meant", value as u16),
+                );
+            }
         }
+    }
 
-        Ok(emitted)
+    // labels nothing jumps to or refers to
+    fn check_labels(&self, report: &mut Report) {
+        let used = self.used.borrow();
+        let mut unused: Vec<(usize, String)> = self
+            .definitions
+            .iter()
+            .map(|(name, index)| (*index, name.clone()))
+            .chain(self.anonymous.iter().flat_map(|(name, labels)| {
+                labels.iter().map(move |label| (label.index, name.clone()))
+            }))
+            .filter(|(index, _)| !used.contains(index))
+            .collect();
+        unused.sort();
+        for (index, name) in unused {
+            report.warning(
+                index,
+                Check::UnusedLabel,
+                format!("label '{}' is never used", name),
+                "remove it, or refer to it from an instruction".to_string(),
+            );
+        }
     }
 
+    // the aligned listing, see Listing
     pub fn string(
         &mut self,
         code_start_offset: u16,
         instructions: Option<Instructions>,
     ) -> Result<String, Error> {
-        self.labels = HashMap::new();
-        self.symbols = HashMap::new();
+        Ok(self.listing(code_start_offset, instructions)?.to_string())
+    }
 
-        let mut position: u16 = 0;
+    // every line with its address and words, and the labels and symbols
+    pub fn listing(
+        &mut self,
+        code_start_offset: u16,
+        instructions: Option<Instructions>,
+    ) -> Result<Listing, Error> {
+        let mut emitted = self
+            .process(code_start_offset, instructions.clone())?
+            .into_iter();
 
-        //calculate labels and symbols
-        for instruction in instructions.as_ref().unwrap().instructions.iter() {
-            position += instruction.size();
-
-            if instruction.as_any().type_id() == TypeId::of::<DEFLABEL>() {
-                match instruction.as_any().downcast_ref::<DEFLABEL>() {
-                    Some(lable) => {
-                        self.labels
-                            .insert(lable.name.clone(), position + code_start_offset);
+        let mut lines = Vec::new();
+        let mut symbols = Vec::new();
+        let mut address = code_start_offset;
+        for (index, instruction) in instructions
+            .ok_or(Error::NoInstructions)?
+            .instructions
+            .iter()
+            .enumerate()
+        {
+            self.enter(index, instruction);
+            if let Some(label) = instruction.as_any().downcast_ref::<DEFLABEL>() {
+                lines.push(ListingLine {
+                    address,
+                    words: Vec::new(),
+                    source: format!("{}:", label),
+                });
+                symbols.push(MapEntry {
+                    name: self.label_name(&label.name),
+                    value: address,
+                    kind: MapKind::Label,
+                });
+                continue;
+            }
+            if let Some(symbol) = instruction.as_any().downcast_ref::<DEFSYMBOL>() {
+                symbols.push(MapEntry {
+                    name: symbol.name.clone(),
+                    value: self.symbols[&symbol.name],
+                    kind: MapKind::Symbol,
+                });
+            }
+
+            let size = instruction.placed_size(address)?;
+            lines.push(ListingLine {
+                address,
+                words: emitted.by_ref().take(size as usize).collect(),
+                source: instruction.to_string(),
+            });
+            address = address.wrapping_add(size);
+        }
+        symbols.sort_by(|a, b| a.value.cmp(&b.value).then(a.name.cmp(&b.name)));
+
+        Ok(Listing { lines, symbols })
+    }
+
+    // assemble into a relocatable object, every section starts at 0 and
+    // labels that are not defined here are left for the linker
+    pub fn object(&mut self, instructions: Option<Instructions>) -> Result<Object, Error> {
+        self.reset();
+        let instructions = instructions.ok_or(Error::NoInstructions)?;
+        // where it went wrong, as process reports it
+        let not_relocatable = |index: usize, instruction: &SafeInstruction| {
+            let diagnostic = Diagnostic::from(Error::NotRelocatable(instruction.to_string()))
+                .at(instructions.location(index).cloned());
+            Error::Assembly(vec![diagnostic])
+        };
+
+        let mut section = SectionKind::Code;
+        let mut positions = [0u16; 2];
+        let mut label_sections = HashMap::new();
+        let mut local_labels = HashSet::new();
+        let mut pending = Vec::new();
+
+        //calculate labels and symbols, relative to their section
+        for (index, instruction) in instructions.instructions.iter().enumerate() {
+            self.enter(index, instruction);
+            if let Some(s) = instruction.as_any().downcast_ref::<SECTION>() {
+                section = s.kind;
+                continue;
+            }
+            // sections are placed by the linker, so padding to an address
+            // would be wrong once linked
+            if instruction.as_any().is::<ORG>() || instruction.as_any().is::<ALIGN>() {
+                return Err(not_relocatable(index, instruction));
+            }
+            positions[section as usize] += instruction.size();
+
+            if let Some(label) = instruction.as_any().downcast_ref::<DEFLABEL>() {
+                if let Some(name) =
+                    self.define_label(&label.name, positions[section as usize], section)?
+                {
+                    if is_local_label(&label.name) {
+                        local_labels.insert(name.clone());
                     }
-                    None => return Err(Error::DowncastError),
+                    label_sections.insert(name, section);
                 }
             }
-            if instruction.as_any().type_id() == TypeId::of::<DEFSYMBOL>() {
-                match instruction.as_any().downcast_ref::<DEFSYMBOL>() {
-                    Some(symbol) => {
-                        self.symbols.insert(symbol.name.clone(), symbol.value);
-                    }
-                    None => return Err(Error::DowncastError),
-                };
+            if let Some(symbol) = instruction.as_any().downcast_ref::<DEFSYMBOL>() {
+                self.add_symbol(symbol, &mut pending)?;
             }
         }
+        if let Some((_, e)) = self.resolve_symbols(pending, true).into_iter().next() {
+            return Err(e);
+        }
 
-        let mut result = String::new();
-        let mut i = 0;
-        position = 0;
-        for instruction in instructions.clone().unwrap().instructions.iter() {
-            if instruction.as_any().type_id() == TypeId::of::<DEFLABEL>() {
-                match instruction.as_any().downcast_ref::<DEFLABEL>() {
-                    Some(symbol) => {
-                        result += "\n";
-                        result += symbol.to_string().as_str();
-                        result += ":";
+        // local labels are only reachable from this object, so keep them out
+        // of the linker's namespace
+        let mut symbols: Vec<ObjectSymbol> = self
+            .labels
+            .iter()
+            .filter(|(name, _)| !local_labels.contains(*name))
+            .map(|(name, value)| ObjectSymbol {
+                name: name.clone(),
+                section: Some(label_sections[name]),
+                value: *value,
+            })
+            .chain(self.symbols.iter().map(|(name, value)| ObjectSymbol {
+                name: name.clone(),
+                section: None,
+                value: *value,
+            }))
+            .collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        let defined_symbols = self.symbols.clone();
+
+        let mut words = [Vec::new(), Vec::new()];
+        let mut relocations = Vec::new();
+        section = SectionKind::Code;
+        for (index, instruction) in instructions.instructions.iter().enumerate() {
+            self.enter(index, instruction);
+            if let Some(s) = instruction.as_any().downcast_ref::<SECTION>() {
+                section = s.kind;
+                continue;
+            }
+            let position = words[section as usize].len() as u16;
+
+            for (offset, reference) in instruction.references() {
+                let target = match reference {
+                    Reference::Label(name) => match self
+                        .anonymous_label(&name)
+                        .map(|label| label.section)
+                        .or_else(|| label_sections.get(&self.label_name(&name)).copied())
+                    {
+                        Some(kind) => RelocationTarget::Section(kind),
+                        // only global labels can come from another object
+                        None if is_local_label(&name) || anonymous_reference(&name).is_some() => {
+                            return Err(Error::UnknownLabel(self.label_name(&name)))
+                        }
+                        None => {
+                            // resolved by the linker, emit 0 until then
+                            self.labels.insert(name.clone(), 0);
+                            RelocationTarget::Symbol(name)
+                        }
+                    },
+                    Reference::Symbol(name)
+                        if name == CURRENTINSTRUCTION || name == NEXTINSTRUCTION =>
+                    {
+                        RelocationTarget::Section(section)
                     }
-                    None => return Err(Error::DowncastError),
-                };
-            } else if instruction.as_any().type_id() == TypeId::of::<DEFSYMBOL>() {
-                match instruction.as_any().downcast_ref::<DEFSYMBOL>() {
-                    Some(symbol) => {
-                        result += symbol.to_string().as_str();
+                    Reference::Symbol(name) if defined_symbols.contains_key(&name) => continue,
+                    Reference::Symbol(name) => {
+                        self.symbols.insert(name.clone(), 0);
+                        RelocationTarget::Symbol(name)
                     }
-                    None => return Err(Error::DowncastError),
+                    // only symbols defined here have their final value
+                    Reference::Fixed(names)
+                        if names.iter().all(|name| {
+                            matches!(name, Reference::Symbol(name)
+                                if name != CURRENTINSTRUCTION
+                                    && name != NEXTINSTRUCTION
+                                    && defined_symbols.contains_key(name))
+                        }) =>
+                    {
+                        continue
+                    }
+                    Reference::Fixed(_) => return Err(not_relocatable(index, instruction)),
                 };
-            } else {
-                self.symbols
-                    .insert(CURRENTINSTRUCTION.to_string(), position + code_start_offset);
-                self.symbols.insert(
-                    NEXTINSTRUCTION.to_string(),
-                    get_next_executable_instruction_loc(
-                        *self.symbols.get(CURRENTINSTRUCTION).unwrap(),
-                        i,
-                        instructions.clone().unwrap(),
-                    ),
-                );
-                result += "\t";
-                result += format!("0x{:>04X}:\t", position + code_start_offset)
-                    .to_string()
-                    .as_str();
-
-                let emit = instruction.emit(Some(Rc::new(self.clone())))?;
-                result += "{";
-                for i in 0..instruction.size() {
-                    result += format!("{}", emit[i as usize]).to_string().as_str();
-                    if i < instruction.size() - 1 {
-                        result += " ";
+                relocations.push(Relocation {
+                    section,
+                    offset: position + offset,
+                    target,
+                });
+            }
+
+            self.symbols
+                .insert(CURRENTINSTRUCTION.to_string(), position);
+            self.symbols
+                .insert(NEXTINSTRUCTION.to_string(), position + instruction.size());
+
+            words[section as usize].append(&mut instruction.emit(Some(Rc::new(self.clone())))?);
+        }
+
+        let [code, data] = words;
+        Ok(Object {
+            sections: vec![
+                Section {
+                    kind: SectionKind::Code,
+                    words: code,
+                },
+                Section {
+                    kind: SectionKind::Data,
+                    words: data,
+                },
+            ],
+            symbols,
+            relocations,
+        })
+    }
+
+    // symbols defined by an expression wait in pending until every label is
+    // placed
+    fn add_symbol<'a>(
+        &mut self,
+        symbol: &'a DEFSYMBOL,
+        pending: &mut Vec<Pending<'a>>,
+    ) -> Result<(), Error> {
+        if self.symbols.contains_key(&symbol.name)
+            || pending.iter().any(|p| p.symbol.name == symbol.name)
+        {
+            return Err(Error::SymbolExist(symbol.name.clone()));
+        }
+        if self.reserved_symbols.is_reserved_symbol(&symbol.name) {
+            return Err(Error::SymbolReserved(symbol.name.clone()));
+        }
+
+        match symbol.expression {
+            Some(_) => pending.push(Pending {
+                symbol,
+                scope: self.scope.clone(),
+                index: self.index,
+            }),
+            None => {
+                self.symbols.insert(symbol.name.clone(), symbol.value);
+            }
+        }
+        Ok(())
+    }
+
+    // expressions may use symbols defined after them, so they are worked out
+    // in rounds until none are left or a round makes no progress. In an
+    // object labels are section relative, so symbols may not use them. Gives
+    // the symbols that could not be worked out, by where they were defined
+    fn resolve_symbols(&mut self, mut pending: Vec<Pending>, object: bool) -> Vec<(usize, Error)> {
+        loop {
+            let mut base = self.clone();
+            if object {
+                base.labels = HashMap::new();
+                base.anonymous = HashMap::new();
+            }
+
+            let mut errors = Vec::new();
+            let count = pending.len();
+            pending.retain(|p| {
+                // labels in the expression are looked up from where it was
+                // defined
+                let mut resolver = base.clone();
+                resolver.scope = p.scope.clone();
+                resolver.index = p.index;
+
+                let expression = p.symbol.expression.as_ref().unwrap();
+                match expression.evaluate(Some(Rc::new(resolver))) {
+                    Ok(value) => {
+                        self.symbols.insert(p.symbol.name.clone(), value);
+                        false
+                    }
+                    Err(e) => {
+                        errors.push((p.index, e));
+                        true
                     }
                 }
-                result += "}";
-                // BUG: Output format
-                match instruction.size() {
-                    4 => result += "\t",
-                    2 => result += "\t".repeat(2).as_str(),
-                    1 => result += "\t".repeat(3).as_str(),
-                    _ => result += "\t",
-                }
-                result += instruction.to_string().as_str();
-                position += instruction.size();
+            });
+            if pending.is_empty() || pending.len() == count {
+                return errors;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.labels = HashMap::new();
+        self.symbols = HashMap::new();
+        self.anonymous = HashMap::new();
+        self.scope = String::new();
+        self.index = 0;
+        self.definitions = HashMap::new();
+        self.used = Rc::new(RefCell::new(HashSet::new()));
+        self.diagnostics = Vec::new();
+    }
+
+    // moves to the instruction at index, a global label starts a new scope
+    // for local labels and every pass starts outside of one
+    fn enter(&mut self, index: usize, instruction: &SafeInstruction) {
+        self.index = index;
+        if index == 0 {
+            self.scope = String::new();
+        }
+        if let Some(label) = instruction.as_any().downcast_ref::<DEFLABEL>() {
+            if !is_local_label(&label.name) && !is_anonymous_label(&label.name) {
+                self.scope = label.name.clone();
             }
-            i += 1;
-            result += "\n";
+        }
+    }
+
+    // the name a label is kept under, local labels get their scope in front
+    fn label_name(&self, name: &str) -> String {
+        if is_local_label(name) {
+            format!("{}{}", self.scope, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    // gives the name the label is kept under, anonymous labels have none
+    fn define_label(
+        &mut self,
+        name: &str,
+        address: u16,
+        section: SectionKind,
+    ) -> Result<Option<String>, Error> {
+        if is_anonymous_label(name) {
+            self.anonymous
+                .entry(name.to_string())
+                .or_default()
+                .push(AnonymousLabel {
+                    index: self.index,
+                    address,
+                    section,
+                });
+            return Ok(None);
         }
 
-        Ok(result)
+        let name = self.label_name(name);
+        if self.labels.contains_key(&name) {
+            return Err(Error::LabelExist(name));
+        }
+        self.labels.insert(name.clone(), address);
+        self.definitions.insert(name.clone(), self.index);
+        Ok(Some(name))
     }
+
+    // the definition 1b or 1f points to from the current instruction
+    fn anonymous_label(&self, name: &str) -> Option<&AnonymousLabel> {
+        let (name, forward) = anonymous_reference(name)?;
+        let labels = self.anonymous.get(name)?;
+        if forward {
+            labels.iter().find(|label| label.index > self.index)
+        } else {
+            labels.iter().rev().find(|label| label.index < self.index)
+        }
+    }
+}
+
+// a symbol defined by an expression, with where it was defined
+struct Pending<'a> {
+    symbol: &'a DEFSYMBOL,
+    scope: String,
+    index: usize,
 }
 
 #[derive(Clone)]
@@ -227,30 +637,127 @@ impl ReservedSymbols {
     }
 }
 
-fn get_next_executable_instruction_loc(
-    current_offset: u16,
-    current_instr_index: usize,
-    instructions: Instructions,
-) -> u16 {
-    // if at the end then just return the location outside the loop
-    if current_instr_index == instructions.instructions.len() {
-        return current_offset + instructions.instructions[current_instr_index].size();
+// the diagnostics found while assembling, by the instruction they are about
+struct Report<'a> {
+    instructions: &'a Instructions,
+    diagnostics: Vec<(usize, Check, Diagnostic)>,
+}
+
+// which check a diagnostic came from, some only warn once
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Check {
+    Error,
+    UnusedLabel,
+    Unreachable,
+    Data,
+    ClobberedR3,
+    Reserved,
+    WrapAround,
+}
+
+impl<'a> Report<'a> {
+    fn new(instructions: &'a Instructions) -> Self {
+        Self {
+            instructions,
+            diagnostics: Vec::new(),
+        }
     }
 
-    let mut next_instruction_pos = 0;
-    for i in current_instr_index..instructions.instructions.len() {
-        let instruction = &instructions.instructions[i];
-        if instruction.type_id() == TypeId::of::<DEFLABEL>()
-            || instruction.type_id() == TypeId::of::<DEFSYMBOL>()
-        {
+    // records the error and gives back fallback so assembling can go on
+    fn error<T>(&mut self, index: usize, error: Error, fallback: T) -> T {
+        let diagnostic = Diagnostic::from(error).at(self.location(index));
+        self.diagnostics.push((index, Check::Error, diagnostic));
+        fallback
+    }
+
+    fn warning(&mut self, index: usize, kind: Check, message: String, suggestion: String) {
+        let diagnostic = Diagnostic::warning(message)
+            .suggest(suggestion)
+            .at(self.location(index));
+        self.diagnostics.push((index, kind, diagnostic));
+    }
+
+    fn warned(&self, kind: Check) -> bool {
+        self.diagnostics.iter().any(|(_, k, _)| *k == kind)
+    }
+
+    fn location(&self, index: usize) -> Option<SourceLocation> {
+        self.instructions.location(index).cloned()
+    }
+
+    // in source order
+    fn finish(mut self) -> Vec<Diagnostic> {
+        self.diagnostics.sort_by_key(|(index, _, _)| *index);
+        self.diagnostics.into_iter().map(|(_, _, d)| d).collect()
+    }
+}
+
+// instructions that are executed, rather than data or assembler bookkeeping
+fn is_code(instruction: &SafeInstruction) -> bool {
+    let any = instruction.as_any();
+    !(any.is::<DEFLABEL>()
+        || any.is::<DEFSYMBOL>()
+        || any.is::<SECTION>()
+        || any.is::<WORD>()
+        || any.is::<FILL>()
+        || any.is::<ASCII>()
+        || any.is::<ORG>()
+        || any.is::<ALIGN>())
+}
+
+// nothing can reach code after a JMP, JR or RET until the next label
+fn check_unreachable(report: &mut Report) {
+    let mut unreachable = false;
+    for (index, instruction) in report.instructions.instructions.iter().enumerate() {
+        let any = instruction.as_any();
+        if any.is::<DEFLABEL>() || any.is::<SECTION>() {
+            unreachable = false;
+        } else if unreachable && is_code(instruction) {
+            report.warning(
+                index,
+                Check::Unreachable,
+                format!("'{}' can never be reached", instruction),
+                "put a label in front of it, or remove it".to_string(),
+            );
+            unreachable = false;
+        }
+        if any.is::<JMP>() || any.is::<JR>() || any.is::<RET>() {
+            unreachable = true;
+        }
+    }
+}
+
+// CALL uses R3 as scratch, so R3 read after the CALL before anything
+// writes it has lost its value
+fn check_calls(report: &mut Report) {
+    let instructions = &report.instructions.instructions;
+    let mut clobbered = Vec::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        if !instruction.as_any().is::<CALL>() {
             continue;
-        } else {
-            if current_instr_index == i {
-                next_instruction_pos += instruction.size();
-            } else {
+        }
+        for next in &instructions[index + 1..] {
+            if next.reads().contains(&Register::REG3) {
+                clobbered.push((index, instruction.to_string(), next.to_string()));
+                break;
+            }
+            let any = next.as_any();
+            if next.writes().contains(&Register::REG3)
+                || any.is::<JMP>()
+                || any.is::<JR>()
+                || any.is::<RET>()
+                || (!is_code(next) && !any.is::<DEFLABEL>() && !any.is::<DEFSYMBOL>())
+            {
                 break;
             }
         }
     }
-    return current_offset + next_instruction_pos;
+    for (index, call, read) in clobbered {
+        report.warning(
+            index,
+            Check::ClobberedR3,
+            format!("'{}' overwrites R3, but '{}' reads it after", call, read),
+            "keep the value in R0-R2, or store it before the CALL".to_string(),
+        );
+    }
 }
@@ -0,0 +1,48 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+// raw binaries are the assembled words back to back, little endian, with no
+// header, the origin they were assembled for has to be known by the reader
+pub fn read_binary(path: &Path) -> Result<Vec<u16>, Error> {
+    words_from_bytes(&fs::read(path)?)
+}
+
+pub fn write_binary(path: &Path, words: &[u16]) -> Result<(), Error> {
+    fs::write(path, bytes_from_words(words))
+}
+
+pub fn words_from_bytes(bytes: &[u8]) -> Result<Vec<u16>, Error> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("odd number of bytes ({}) in a binary", bytes.len()),
+        ));
+    }
+    Ok(bytes
+        .chunks(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect())
+}
+
+pub fn bytes_from_words(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_words() {
+        let words = vec![0x0020, 0x0500, 0xFFFF];
+        let bytes = bytes_from_words(&words);
+        assert_eq!(bytes, vec![0x20, 0x00, 0x00, 0x05, 0xFF, 0xFF]);
+        assert_eq!(words_from_bytes(&bytes).unwrap(), words);
+
+        let err = words_from_bytes(&bytes[..5]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,467 @@
+use crate::instructions::{
+    Error, IOMode, Instruction, Instructions, Label, Number, Register, Resolver, SafeInstruction,
+    Symbol, ADD, AND, CALL, CLF, CMP, DATA, DEFLABEL, DI, EI, GETSP, HALT, HCALL, HPOP, HPUSH,
+    HRET, IN, INT, IRET, JMP, JMPF, JR, LOAD, NOT, OR, OUT, POP, PUSH, RET, SETIV, SETSP, SHL, SHR,
+    STORE, XOR,
+};
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    rc::Rc,
+};
+
+// Disassembler - turns machine words back into Instructions
+// jump targets get a synthesized label unless a name is known for them
+#[derive(Default)]
+pub struct Disassembler {
+    labels: HashMap<u16, String>,
+}
+
+struct Decoded {
+    address: u16,
+    words: Vec<u16>,
+    instruction: SafeInstruction,
+    // where a jump or call goes
+    target: Option<u16>,
+}
+
+impl Disassembler {
+    pub fn new() -> Self {
+        Self {
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn add_label(&mut self, name: &str, address: u16) {
+        self.labels.insert(address, name.to_string());
+    }
+
+    pub fn disassemble(&mut self, start: u16, words: &[u16]) -> Instructions {
+        let decoded = self.decode(start, words);
+        let mut instructions = Instructions::new();
+
+        // a jump outside the words or into the middle of an instruction has
+        // no label to assemble against, it was most likely data
+        let placed: HashSet<u16> = decoded.iter().map(|d| d.address).collect();
+        for d in decoded {
+            if let Some(name) = self.labels.get(&d.address) {
+                instructions.add(vec![Rc::new(DEFLABEL::new(name))]);
+            }
+            match d.target {
+                Some(target) if !placed.contains(&target) => instructions.add(
+                    d.words
+                        .iter()
+                        .map(|w| Rc::new(RawWord::new(*w)) as SafeInstruction)
+                        .collect(),
+                ),
+                _ => instructions.add(vec![d.instruction]),
+            }
+        }
+
+        instructions
+    }
+
+    pub fn listing(&mut self, start: u16, words: &[u16]) -> String {
+        let decoded = self.decode(start, words);
+        decoded.iter().map(|d| self.render(d, "")).collect()
+    }
+
+    // listing of `context` instructions either side of the one holding
+    // address, which is marked with "=>"
+    pub fn listing_around(
+        &mut self,
+        start: u16,
+        words: &[u16],
+        address: u16,
+        context: usize,
+    ) -> String {
+        let decoded = self.decode(start, words);
+        let found = decoded
+            .iter()
+            .position(|d| address.wrapping_sub(d.address) < d.words.len() as u16);
+
+        match found {
+            Some(i) => decoded[i.saturating_sub(context)..decoded.len().min(i + context + 1)]
+                .iter()
+                .map(|d| {
+                    self.render(
+                        d,
+                        if d.address == decoded[i].address {
+                            "=>"
+                        } else {
+                            ""
+                        },
+                    )
+                })
+                .collect(),
+            None => String::new(),
+        }
+    }
+
+    fn render(&self, d: &Decoded, marker: &str) -> String {
+        let mut result = String::new();
+        if let Some(name) = self.labels.get(&d.address) {
+            result += "\n";
+            result += name;
+            result += ":\n";
+        }
+
+        let words: Vec<String> = d.words.iter().map(|w| w.to_string()).collect();
+        result += format!("{}\t0x{:>04X}:\t{{{}}}", marker, d.address, words.join(" ")).as_str();
+        match d.words.len() {
+            4 => result += "\t",
+            2 => result += "\t".repeat(2).as_str(),
+            1 => result += "\t".repeat(3).as_str(),
+            _ => result += "\t",
+        }
+        result += d.instruction.to_string().as_str();
+        result += "\n";
+        result
+    }
+
+    // only a target that starts one of the decoded instructions gets a
+    // synthesized label, anywhere else there would be no place to define it
+    fn decode(&mut self, start: u16, words: &[u16]) -> Vec<Decoded> {
+        let placed = self
+            .decode_placed(start, words, &HashSet::new())
+            .iter()
+            .map(|d| d.address)
+            .collect();
+        self.decode_placed(start, words, &placed)
+    }
+
+    fn decode_placed(&mut self, start: u16, words: &[u16], placed: &HashSet<u16>) -> Vec<Decoded> {
+        let mut decoded = Vec::new();
+        let mut i = 0;
+
+        while i < words.len() {
+            let address = start.wrapping_add(i as u16);
+            let word = words[i];
+            let operand = words.get(i + 1).copied();
+            // the stack instructions all expand from DATA R3
+            let stack = match word {
+                0x0023 => decode_stack(address, &words[i..]),
+                _ => None,
+            };
+
+            let mut target = None;
+            let (instruction, size): (SafeInstruction, usize) = match (word, operand) {
+                _ if stack.is_some() => match stack.unwrap() {
+                    (_, size, Some(routine)) => {
+                        target = Some(routine);
+                        (Rc::new(CALL::new(self.label_for(routine, placed))), size)
+                    }
+                    (instruction, size, None) => (instruction, size),
+                },
+                (0x0020..=0x0023, Some(value)) => {
+                    (Rc::new(DATA::new(register(word), Number::new(value))), 2)
+                }
+                (0x0040, Some(address)) => {
+                    target = Some(address);
+                    (Rc::new(JMP::new(self.label_for(address, placed))), 2)
+                }
+                (0x0051..=0x005F, Some(address)) => {
+                    target = Some(address);
+                    (
+                        Rc::new(JMPF::new(jump_flags(word), self.label_for(address, placed))),
+                        2,
+                    )
+                }
+                _ => match decode_single(word) {
+                    Some(instruction) => (instruction, 1),
+                    None => (Rc::new(RawWord::new(word)), 1),
+                },
+            };
+
+            decoded.push(Decoded {
+                address,
+                words: words[i..i + size].to_vec(),
+                instruction,
+                target,
+            });
+            i += size;
+        }
+
+        decoded
+    }
+
+    // a name given with add_label is kept wherever the target is
+    fn label_for(&mut self, address: u16, placed: &HashSet<u16>) -> Label {
+        if !placed.contains(&address) && !self.labels.contains_key(&address) {
+            return Label::new(&format!("0x{:04X}", address));
+        }
+        let name = self
+            .labels
+            .entry(address)
+            .or_insert_with(|| format!("L_{:04X}", address));
+        Label::new(name)
+    }
+}
+
+fn register(bits: u16) -> Register {
+    match bits & 0x3 {
+        0 => Register::REG0,
+        1 => Register::REG1,
+        2 => Register::REG2,
+        _ => Register::REG3,
+    }
+}
+
+fn jump_flags(word: u16) -> Vec<String> {
+    let mut flags = Vec::new();
+    for (bit, flag) in [(0x8, "C"), (0x4, "A"), (0x2, "E"), (0x1, "Z")] {
+        if word & bit != 0 {
+            flags.push(flag.to_string());
+        }
+    }
+    flags
+}
+
+// CALL, RET, PUSH or POP when words start with one of their expansions, with
+// its size and where a CALL goes
+fn decode_stack(address: u16, words: &[u16]) -> Option<(SafeInstruction, usize, Option<u16>)> {
+    let call = CALL::new(Label::new("routine"));
+    let routine = words.get(call.references()[1].0 as usize).copied();
+    let resolver = Rc::new(StackResolver {
+        routine: routine.unwrap_or(0),
+        next: address.wrapping_add(call.size()),
+    });
+
+    let mut candidates: Vec<(SafeInstruction, Option<u16>)> =
+        vec![(Rc::new(call), routine), (Rc::new(RET::new()), None)];
+    for r in [Register::REG0, Register::REG1, Register::REG2] {
+        candidates.push((Rc::new(PUSH::new(r)), None));
+        candidates.push((Rc::new(POP::new(r)), None));
+    }
+
+    candidates.into_iter().find_map(|(instruction, target)| {
+        let size = instruction.size() as usize;
+        let emitted = instruction.emit(Some(resolver.clone())).ok()?;
+        (words.get(..size)? == emitted.as_slice()).then_some((instruction, size, target))
+    })
+}
+
+// resolves what a CALL at some address to some routine needs
+#[derive(Clone)]
+struct StackResolver {
+    routine: u16,
+    next: u16,
+}
+
+impl Resolver for StackResolver {
+    fn label_resolver(&self, _: &Label) -> Result<u16, Error> {
+        Ok(self.routine)
+    }
+
+    fn symbol_resolver(&self, _: &Symbol) -> Result<u16, Error> {
+        Ok(self.next)
+    }
+}
+
+// single word instructions, None for words the assembler never emits
+fn decode_single(word: u16) -> Option<SafeInstruction> {
+    let a = register(word >> 2);
+    let b = register(word);
+
+    let instruction: SafeInstruction = match word {
+        0x0000..=0x000F => Rc::new(LOAD::new(a, b)),
+        0x0010..=0x001F => Rc::new(STORE::new(a, b)),
+        0x0030..=0x0033 => Rc::new(JR::new(b)),
+        0x0060 => Rc::new(CLF::new()),
+        0x0070..=0x0073 => Rc::new(IN::new(IOMode::DataMode, b)),
+        0x0074..=0x0077 => Rc::new(IN::new(IOMode::AddressMode, b)),
+        0x0078..=0x007B => Rc::new(OUT::new(IOMode::DataMode, b)),
+        0x007C..=0x007F => Rc::new(OUT::new(IOMode::AddressMode, b)),
+        0x0080..=0x008F => Rc::new(ADD::new(a, b)),
+        0x0090..=0x009F if a == b => Rc::new(SHL::new(a)),
+        0x00A0..=0x00AF if a == b => Rc::new(SHR::new(a)),
+        0x00B0..=0x00BF if a == b => Rc::new(NOT::new(a)),
+        0x00C0..=0x00CF => Rc::new(AND::new(a, b)),
+        0x00D0..=0x00DF => Rc::new(OR::new(a, b)),
+        0x00E0..=0x00EF => Rc::new(XOR::new(a, b)),
+        0x00F0..=0x00FF => Rc::new(CMP::new(a, b)),
+        0x0100..=0x0103 => Rc::new(HPUSH::new(b)),
+        0x0110..=0x0113 => Rc::new(HPOP::new(b)),
+        0x0120..=0x0123 => Rc::new(HCALL::new(b)),
+        0x0130 => Rc::new(HRET::new()),
+        0x0140..=0x0143 => Rc::new(SETSP::new(b)),
+        0x0150..=0x0153 => Rc::new(GETSP::new(b)),
+        0x0160 => Rc::new(IRET::new()),
+        0x0164 => Rc::new(EI::new()),
+        0x0168 => Rc::new(DI::new()),
+        0x0170..=0x0173 => Rc::new(SETIV::new(b)),
+        0x0178 => Rc::new(INT::new()),
+        0x0180 => Rc::new(HALT::new()),
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+// RawWord - a word that does not decode to an instruction, emitted as is
+struct RawWord {
+    value: u16,
+}
+
+impl RawWord {
+    fn new(value: u16) -> Self {
+        Self { value }
+    }
+}
+
+impl Display for RawWord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ".word 0x{:>04X}", self.value)
+    }
+}
+
+impl Instruction for RawWord {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![self.value])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::generator::get_instructions;
+    use crate::USER_CODE_START;
+
+    #[test]
+    fn test_disassembler_round_trip() {
+        for program in ["ascii", "brush", "text_writer", "me"] {
+            let bin = Assembler::new()
+                .process(USER_CODE_START, get_instructions(program))
+                .unwrap();
+
+            let instructions = Disassembler::new().disassemble(USER_CODE_START, &bin);
+
+            assert_eq!(
+                Assembler::new()
+                    .process(USER_CODE_START, Some(instructions))
+                    .unwrap(),
+                bin
+            );
+        }
+    }
+
+    #[test]
+    fn test_disassembler_instructions() {
+        let mut bin = vec![
+            0x0021, 0x0005, // DATA R1, 0x0005
+            0x0087, // ADD R1, R3
+            0x009A, // SHL R2
+            0x0096, // not a SHL, the assembler never emits it
+            0x005A, 0x0500, // JMPCE
+            0x0023, 0x0403, 0x001E, 0x0023, 0x0402, 0x000F, 0x0022, 0xFFFF, 0x0060, 0x008B, 0x0022,
+            0x0402, 0x001B, 0x0022, 0x051C, 0x001E, 0x0022, 0x0403, 0x000A, 0x0040,
+            0x0500, // CALL
+            0x007D, // OUT Addr, R1
+            0x0102, // HPUSH R2
+            0x0104, // not an HPUSH, the assembler never emits it
+            0x0130, // HRET
+            0x0173, // SETIV R3
+            0x0164, // EI
+            0x0178, // INT
+            0x0160, // IRET
+            0x0180, // HALT
+        ];
+        bin.append(&mut PUSH::new(Register::REG1).emit(None).unwrap());
+        bin.append(&mut POP::new(Register::REG2).emit(None).unwrap());
+        bin.append(&mut RET::new().emit(None).unwrap());
+
+        let mut disassembler = Disassembler::new();
+        disassembler.add_label("main", 0x0500);
+        let lines: Vec<String> = disassembler
+            .disassemble(USER_CODE_START, &bin)
+            .instructions
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "main",
+                "DATA R1, 0x0005",
+                "ADD R1, R3",
+                "SHL R2",
+                ".word 0x0096",
+                "JMPCE main",
+                "CALL main",
+                "OUT Addr, R1",
+                "HPUSH R2",
+                ".word 0x0104",
+                "HRET",
+                "SETIV R3",
+                "EI",
+                "INT",
+                "IRET",
+                "HALT",
+                "PUSH R1",
+                "POP R2",
+                "RET",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassembler_unplaced_targets() {
+        let bin = vec![
+            0x0040, 0x0600, // JMP past the end
+            0x0040, 0x0501, // JMP into its own operand
+            0x0060, // CLF
+        ];
+
+        // the jumps stay raw words, there is no label to assemble them against
+        let instructions = Disassembler::new().disassemble(USER_CODE_START, &bin);
+        assert_eq!(
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap(),
+            bin
+        );
+
+        let mut disassembler = Disassembler::new();
+        disassembler.add_label("far", 0x0600);
+        assert_eq!(
+            disassembler.listing(USER_CODE_START, &bin),
+            "\t0x0500:\t{64 1536}\t\tJMP far\n\t0x0502:\t{64 1281}\t\tJMP 0x0501\n\t0x0504:\t{96}\t\t\tCLF\n"
+        );
+    }
+
+    #[test]
+    fn test_disassembler_listing() {
+        let bin = vec![0x0060, 0x0040, 0x0600];
+
+        assert_eq!(
+            Disassembler::new().listing(USER_CODE_START, &bin),
+            "\t0x0500:\t{96}\t\t\tCLF\n\t0x0501:\t{64 1536}\t\tJMP 0x0600\n"
+        );
+    }
+
+    #[test]
+    fn test_disassembler_listing_around() {
+        let bin = vec![0x0060, 0x0040, 0x0600, 0x0087, 0x009A];
+
+        assert_eq!(
+            Disassembler::new().listing_around(USER_CODE_START, &bin, 0x0502, 1),
+            "\t0x0500:\t{96}\t\t\tCLF\n=>\t0x0501:\t{64 1536}\t\tJMP 0x0600\n\t0x0503:\t{135}\t\t\tADD R1, R3\n"
+        );
+        assert_eq!(
+            Disassembler::new().listing_around(USER_CODE_START, &bin, 0x0600, 1),
+            ""
+        );
+    }
+}
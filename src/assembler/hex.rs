@@ -0,0 +1,280 @@
+use thiserror::Error;
+
+const LOGISIM_HEADER: &str = "v2.0 raw";
+// data bytes in one Intel HEX record
+const RECORD_LEN: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum HexError {
+    #[error("line {0}: {1}")]
+    Parse(usize, String),
+
+    #[error("Missing the '{}' header of a Logisim image", LOGISIM_HEADER)]
+    MissingHeader,
+
+    #[error("Intel HEX has no end of file record")]
+    MissingEnd,
+
+    #[error("Image runs past 0xFFFF")]
+    TooLarge,
+}
+
+// whether text looks like a Logisim image or Intel HEX, anything else is
+// taken to be a raw binary
+pub fn is_logisim_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(LOGISIM_HEADER.as_bytes())
+}
+
+pub fn is_intel_hex(bytes: &[u8]) -> bool {
+    bytes.starts_with(b":")
+}
+
+// Logisim v2.0 raw image - hex words from address 0, runs of one value are
+// written N*value, so the reserved area below origin stays short
+pub fn logisim_image(origin: u16, words: &[u16]) -> String {
+    let mut values = vec![(origin as usize, 0)];
+    for word in words.iter() {
+        match values.last_mut() {
+            Some((count, value)) if value == word => *count += 1,
+            _ => values.push((1, *word)),
+        }
+    }
+
+    let mut result = String::from(LOGISIM_HEADER);
+    for (i, (count, value)) in values.iter().filter(|(count, _)| *count > 0).enumerate() {
+        result += if i % 8 == 0 { "\n" } else { " " };
+        result += &match count {
+            1 => format!("{:x}", value),
+            _ => format!("{}*{:x}", count, value),
+        };
+    }
+    result + "\n"
+}
+
+// every word from address 0, comments start with #
+pub fn parse_logisim_image(text: &str) -> Result<(u16, Vec<u16>), HexError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == LOGISIM_HEADER => {}
+        _ => return Err(HexError::MissingHeader),
+    }
+
+    let mut words = Vec::new();
+    for (i, line) in lines {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (count, value) = match token.split_once('*') {
+                Some((count, value)) => (
+                    count.parse::<usize>().map_err(|_| {
+                        HexError::Parse(i + 1, format!("invalid count '{}'", token))
+                    })?,
+                    value,
+                ),
+                None => (1, token),
+            };
+            let value = u16::from_str_radix(value, 16)
+                .map_err(|_| HexError::Parse(i + 1, format!("invalid value '{}'", token)))?;
+            match words.len().checked_add(count) {
+                Some(len) if len <= 0x10000 => {}
+                Some(_) => return Err(HexError::TooLarge),
+                None => return Err(HexError::Parse(i + 1, format!("invalid count '{}'", token))),
+            }
+            words.extend(std::iter::repeat_n(value, count));
+        }
+    }
+    Ok((0, words))
+}
+
+// Intel HEX with byte addresses, twice the word address, and the words little
+// endian like the raw binaries, above 0x7FFF it needs extended linear address
+// records
+pub fn intel_hex(origin: u16, words: &[u16]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let mut result = String::new();
+    let mut upper = 0;
+    let mut address = origin as usize * 2;
+    let mut rest = &bytes[..];
+
+    while !rest.is_empty() {
+        if address >> 16 != upper {
+            upper = address >> 16;
+            result += &record(0, 0x04, &(upper as u16).to_be_bytes());
+        }
+        // a record may not run over a 64K boundary
+        let len = RECORD_LEN.min(rest.len()).min(0x10000 - (address & 0xFFFF));
+        result += &record(address as u16, 0x00, &rest[..len]);
+        address += len;
+        rest = &rest[len..];
+    }
+    result + &record(0, 0x01, &[])
+}
+
+fn record(address: u16, kind: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_add(*b))
+        .wrapping_neg();
+
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(":{}{:02X}\n", hex, checksum)
+}
+
+// the words from the lowest address in the file, gaps are filled with 0
+pub fn parse_intel_hex(text: &str) -> Result<(u16, Vec<u16>), HexError> {
+    let mut data: Vec<(usize, u8)> = Vec::new();
+    let mut upper = 0;
+    let mut ended = false;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| HexError::Parse(i + 1, message.to_string());
+
+        let hex = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("expected ':'"))?;
+        if !hex.is_ascii() {
+            return Err(error("invalid hex digit"));
+        }
+        if hex.len() % 2 != 0 || hex.len() < 10 {
+            return Err(error("record is too short"));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|j| u8::from_str_radix(&hex[j..j + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error("invalid hex digit"))?;
+        if bytes.len() != bytes[0] as usize + 5 {
+            return Err(error("record length does not match"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(error("bad checksum"));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let payload = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => {
+                for (j, b) in payload.iter().enumerate() {
+                    data.push((upper + address + j, *b));
+                }
+            }
+            0x01 => {
+                ended = true;
+                break;
+            }
+            0x02 if payload.len() == 2 => {
+                upper = (u16::from_be_bytes([payload[0], payload[1]]) as usize) << 4;
+            }
+            0x04 if payload.len() == 2 => {
+                upper = (u16::from_be_bytes([payload[0], payload[1]]) as usize) << 16;
+            }
+            0x03 | 0x05 => {}
+            kind => return Err(error(&format!("unsupported record type {:02X}", kind))),
+        }
+    }
+    if !ended {
+        return Err(HexError::MissingEnd);
+    }
+
+    let start = data.iter().map(|(a, _)| a / 2).min().unwrap_or(0);
+    let end = data.iter().map(|(a, _)| a / 2 + 1).max().unwrap_or(0);
+    if end > 0x10000 {
+        return Err(HexError::TooLarge);
+    }
+    let mut words = vec![0u16; end - start];
+    for (address, b) in data {
+        let word = &mut words[address / 2 - start];
+        match address % 2 {
+            0 => *word = (*word & 0xFF00) | b as u16,
+            _ => *word = (*word & 0x00FF) | (b as u16) << 8,
+        }
+    }
+    Ok((start as u16, words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_logisim() {
+        let words = vec![0x0020, 0x0000, 0x0000, 0x0040, 0x0500];
+        let text = logisim_image(0x0500, &words);
+        assert_eq!(text, "v2.0 raw\n1280*0 20 2*0 40 500\n");
+        assert!(is_logisim_image(text.as_bytes()));
+
+        let (start, parsed) = parse_logisim_image(&text).unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(&parsed[0x0500..], &words[..]);
+        assert!(parsed[..0x0500].iter().all(|w| *w == 0));
+
+        assert_eq!(
+            parse_logisim_image("v2.0 raw\n# comment\n3*1f g")
+                .unwrap_err()
+                .to_string(),
+            "line 3: invalid value 'g'"
+        );
+        assert_eq!(
+            parse_logisim_image(&format!("v2.0 raw\n1 {}*0", usize::MAX))
+                .unwrap_err()
+                .to_string(),
+            format!("line 2: invalid count '{}*0'", usize::MAX)
+        );
+        assert!(matches!(
+            parse_logisim_image("v2.0 raw\n65536*0 1"),
+            Err(HexError::TooLarge)
+        ));
+        assert!(matches!(
+            parse_logisim_image("20 40"),
+            Err(HexError::MissingHeader)
+        ));
+    }
+
+    #[test]
+    fn test_hex_intel() {
+        let words: Vec<u16> = (0..10).collect();
+        let text = intel_hex(0x0500, &words);
+        assert_eq!(
+            text,
+            "\
+:100A000000000100020003000400050006000700CA
+:040A100008000900D1
+:00000001FF
+"
+        );
+        assert!(is_intel_hex(text.as_bytes()));
+        assert_eq!(parse_intel_hex(&text).unwrap(), (0x0500, words));
+
+        // byte addresses pass 0xFFFF from word 0x8000
+        let text = intel_hex(0xFEFD, &[0x1234, 0x5678]);
+        assert!(text.starts_with(":020000040001F9\n"));
+        assert_eq!(
+            parse_intel_hex(&text).unwrap(),
+            (0xFEFD, vec![0x1234, 0x5678])
+        );
+
+        assert_eq!(
+            parse_intel_hex(":040A100008000900D2\n")
+                .unwrap_err()
+                .to_string(),
+            "line 1: bad checksum"
+        );
+        assert_eq!(
+            parse_intel_hex(":0000000\u{e9}1\n")
+                .unwrap_err()
+                .to_string(),
+            "line 1: invalid hex digit"
+        );
+        assert!(matches!(
+            parse_intel_hex(":040A100008000900D1\n"),
+            Err(HexError::MissingEnd)
+        ));
+    }
+}
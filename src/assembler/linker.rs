@@ -0,0 +1,398 @@
+use super::object::{Object, RelocationTarget};
+use crate::instructions::{SectionKind, STACK_BOTTOM, STACK_POINTER, STACK_SCRATCH, STACK_TOP};
+use std::fmt::Display;
+use thiserror::Error;
+
+// MemoryRegion - an area of RAM, programs may only be linked into the ones
+// that are not reserved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub name: &'static str,
+    pub start: u16,
+    pub end: u16,
+    pub reserved: bool,
+}
+
+const fn region(name: &'static str, start: u16, end: u16, reserved: bool) -> MemoryRegion {
+    MemoryRegion {
+        name,
+        start,
+        end,
+        reserved,
+    }
+}
+
+pub const MEMORY_MAP: [MemoryRegion; 8] = [
+    region("ASCII table", 0x0000, 0x03FF, true),
+    region("pen position", 0x0400, 0x0400, true),
+    region("keycode register", 0x0401, 0x0401, true),
+    region("stack pointer", STACK_POINTER, STACK_SCRATCH, true),
+    region("stack", STACK_BOTTOM, STACK_TOP - 1, true),
+    region("user code", 0x0500, 0xFEFD, false),
+    region("jump back to user code", 0xFEFE, 0xFEFF, true),
+    region("temporary variables", 0xFF00, 0xFFFF, true),
+];
+
+#[derive(Debug, Error)]
+pub enum LinkError {
+    #[error("Origin 0x{origin:04X} is in the {region} region")]
+    ReservedRegion { origin: u16, region: &'static str },
+
+    #[error("{module}: no room for the {len} word {section} section in the memory map")]
+    NoRoom {
+        module: String,
+        section: SectionKind,
+        len: u32,
+    },
+
+    #[error("{module}: undefined symbol '{name}'")]
+    UndefinedSymbol { module: String, name: String },
+
+    #[error("symbol '{name}' is defined in both {first} and {second}")]
+    AmbiguousSymbol {
+        name: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("{module}: relocation at offset 0x{offset:04X} is outside the {section} section")]
+    BadRelocation {
+        module: String,
+        section: SectionKind,
+        offset: u16,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedSection {
+    pub module: String,
+    pub kind: SectionKind,
+    pub start: u16,
+    pub len: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedSymbol {
+    pub name: String,
+    pub module: String,
+    // absolute symbols keep their value, labels get their final address
+    pub absolute: bool,
+    pub value: u16,
+}
+
+// Image - linked words ready for Computer::load_to_ram at origin, the code
+// of the first module is the entry point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    pub sections: Vec<PlacedSection>,
+    pub symbols: Vec<LinkedSymbol>,
+}
+
+impl Image {
+    // the map file, where every section went and the value of every symbol
+    pub fn map(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Image 0x{:04X}, {} words", self.origin, self.words.len())?;
+
+        writeln!(f, "\nSections")?;
+        for section in self.sections.iter() {
+            writeln!(
+                f,
+                "  0x{:04X} - 0x{:04X}  {:<4}  {}",
+                section.start,
+                section.start + (section.len - 1),
+                section.kind,
+                section.module
+            )?;
+        }
+
+        writeln!(f, "\nSymbols")?;
+        for symbol in self.symbols.iter() {
+            writeln!(
+                f,
+                "  0x{:04X}  {:<8}  {}  {}",
+                symbol.value,
+                if symbol.absolute { "absolute" } else { "label" },
+                symbol.name,
+                symbol.module
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Linker - places the sections of several objects one after another in the
+// regions of MEMORY_MAP that are not reserved, all code first and then all
+// data, and patches their relocations
+#[derive(Default)]
+pub struct Linker {
+    modules: Vec<(String, Object)>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self {
+            modules: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, module: &str, object: Object) {
+        self.modules.push((module.to_string(), object));
+    }
+
+    pub fn link(&self, origin: u16) -> Result<Image, LinkError> {
+        check_origin(origin)?;
+
+        let mut bases = vec![[0u16; 2]; self.modules.len()];
+        let mut sections = Vec::new();
+        let mut words: Vec<u16> = Vec::new();
+        let mut address = origin as u32;
+
+        for kind in [SectionKind::Code, SectionKind::Data] {
+            for (i, (module, object)) in self.modules.iter().enumerate() {
+                let section = match object.section(kind) {
+                    Some(section) if !section.words.is_empty() => section,
+                    _ => continue,
+                };
+                let len = section.words.len() as u32;
+                address = place(address, len).ok_or_else(|| LinkError::NoRoom {
+                    module: module.clone(),
+                    section: kind,
+                    len,
+                })?;
+                bases[i][kind as usize] = address as u16;
+                sections.push(PlacedSection {
+                    module: module.clone(),
+                    kind,
+                    start: address as u16,
+                    len: len as u16,
+                });
+                // whatever the section skipped over stays 0
+                words.resize((address - origin as u32) as usize, 0);
+                words.extend(section.words.iter());
+                address += len;
+            }
+        }
+
+        // symbols share one namespace, a label is defined by one module but
+        // a shared header may define the same constant in several
+        let mut symbols: Vec<LinkedSymbol> = Vec::new();
+        for (i, (module, object)) in self.modules.iter().enumerate() {
+            for symbol in object.symbols.iter() {
+                let linked = LinkedSymbol {
+                    name: symbol.name.clone(),
+                    module: module.clone(),
+                    absolute: symbol.section.is_none(),
+                    value: match symbol.section {
+                        Some(kind) => bases[i][kind as usize].wrapping_add(symbol.value),
+                        None => symbol.value,
+                    },
+                };
+                match symbols.iter().find(|s| s.name == symbol.name) {
+                    Some(first)
+                        if first.absolute && linked.absolute && first.value == linked.value => {}
+                    Some(first) => {
+                        return Err(LinkError::AmbiguousSymbol {
+                            name: symbol.name.clone(),
+                            first: first.module.clone(),
+                            second: module.clone(),
+                        })
+                    }
+                    None => symbols.push(linked),
+                }
+            }
+        }
+
+        for (i, (module, object)) in self.modules.iter().enumerate() {
+            for relocation in object.relocations.iter() {
+                let len = object
+                    .section(relocation.section)
+                    .map_or(0, |s| s.words.len());
+                if relocation.offset as usize >= len {
+                    return Err(LinkError::BadRelocation {
+                        module: module.clone(),
+                        section: relocation.section,
+                        offset: relocation.offset,
+                    });
+                }
+
+                let value = match &relocation.target {
+                    RelocationTarget::Section(kind) => bases[i][*kind as usize],
+                    RelocationTarget::Symbol(name) => resolve(&symbols, module, name)?,
+                };
+                let index =
+                    (bases[i][relocation.section as usize] - origin + relocation.offset) as usize;
+                words[index] = words[index].wrapping_add(value);
+            }
+        }
+
+        Ok(Image {
+            origin,
+            words,
+            sections,
+            symbols,
+        })
+    }
+}
+
+fn resolve(symbols: &[LinkedSymbol], module: &str, name: &str) -> Result<u16, LinkError> {
+    symbols
+        .iter()
+        .find(|symbol| symbol.name == name)
+        .map(|symbol| symbol.value)
+        .ok_or_else(|| LinkError::UndefinedSymbol {
+            module: module.to_string(),
+            name: name.to_string(),
+        })
+}
+
+// the first address from address on where len words fit in one region that is
+// not reserved, a section never straddles two regions
+fn place(address: u32, len: u32) -> Option<u32> {
+    MEMORY_MAP
+        .iter()
+        .filter(|region| !region.reserved)
+        .find_map(|region| {
+            let start = address.max(region.start as u32);
+            (start + len <= region.end as u32 + 1).then_some(start)
+        })
+}
+
+fn check_origin(origin: u16) -> Result<(), LinkError> {
+    match MEMORY_MAP
+        .iter()
+        .find(|region| region.start <= origin && origin <= region.end)
+    {
+        Some(region) if region.reserved => Err(LinkError::ReservedRegion {
+            origin,
+            region: region.name,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{AsmParser, Assembler};
+    use crate::USER_CODE_START;
+
+    fn object(source: &str) -> Object {
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        Assembler::new().object(Some(instructions)).unwrap()
+    }
+
+    #[test]
+    fn test_linker_link() {
+        let mut linker = Linker::new();
+        linker.add(
+            "main.obj",
+            object(
+                "
+            main:
+                CALL double
+            .data
+            value:
+                JMP main
+            ",
+            ),
+        );
+        linker.add(
+            "lib.obj",
+            object(
+                "
+            %ONE = 0x0001
+            double:
+                DATA R0, %ONE
+                JR R3
+            ",
+            ),
+        );
+
+        let image = linker.link(USER_CODE_START).unwrap();
+        assert_eq!(
+            image.words,
+            vec![
+                0x0023, 0x0403, 0x001E, 0x0023, 0x0402, 0x000F, 0x0022, 0xFFFF, 0x0060, 0x008B,
+                0x0022, 0x0402, 0x001B, 0x0022, 0x0515, 0x001E, 0x0022, 0x0403, 0x000A, 0x0040,
+                0x0515, // main.obj code
+                0x0020, 0x0001, 0x0033, // lib.obj code
+                0x0040, 0x0500, // main.obj data
+            ]
+        );
+        assert_eq!(
+            image.sections.iter().map(|s| s.start).collect::<Vec<_>>(),
+            vec![0x0500, 0x0515, 0x0518]
+        );
+
+        let map = image.map();
+        assert!(map.contains("  0x0515  label     double  lib.obj\n"));
+        assert!(map.contains("  0x0001  absolute  ONE  lib.obj\n"));
+        assert!(map.contains("  0x0518 - 0x0519  data  main.obj\n"));
+    }
+
+    #[test]
+    fn test_linker_errors() {
+        let mut linker = Linker::new();
+        linker.add("main.obj", object("JMP missing"));
+        assert_eq!(
+            linker.link(USER_CODE_START).unwrap_err().to_string(),
+            "main.obj: undefined symbol 'missing'"
+        );
+
+        linker.add("a.obj", object("missing: CLF"));
+        linker.add("b.obj", object("CLF\nmissing: CLF"));
+        assert_eq!(
+            linker.link(USER_CODE_START).unwrap_err().to_string(),
+            "symbol 'missing' is defined in both a.obj and b.obj"
+        );
+
+        let mut linker = Linker::new();
+        linker.add("a.obj", object("%ONE = 1\nCLF"));
+        linker.add("b.obj", object("%ONE = 2\nCLF"));
+        assert_eq!(
+            linker.link(USER_CODE_START).unwrap_err().to_string(),
+            "symbol 'ONE' is defined in both a.obj and b.obj"
+        );
+
+        // a shared header defines the same constants in every module, and
+        // local labels stay inside their object
+        let mut linker = Linker::new();
+        linker.add("a.obj", object("%ONE = 1\nstart:\n.loop: JMP .loop"));
+        linker.add("b.obj", object("%ONE = 1\nstart2:\n.loop: JMP .loop"));
+        let image = linker.link(USER_CODE_START).unwrap();
+        assert_eq!(
+            image
+                .symbols
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["ONE", "start", "start2"]
+        );
+
+        let mut linker = Linker::new();
+        linker.add("main.obj", object("CLF"));
+        assert_eq!(
+            linker.link(0x0401).unwrap_err().to_string(),
+            "Origin 0x0401 is in the keycode register region"
+        );
+        assert_eq!(
+            linker.link(0xFEFE).unwrap_err().to_string(),
+            "Origin 0xFEFE is in the jump back to user code region"
+        );
+        assert!(linker.link(0xFEFD).is_ok());
+
+        linker.add("lib.obj", object("CLF"));
+        assert_eq!(
+            linker.link(0xFEFD).unwrap_err().to_string(),
+            "lib.obj: no room for the 1 word code section in the memory map"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt::Display;
+
+// widest hex column, four words to a row
+const WORDS_PER_ROW: usize = 4;
+const WORDS_WIDTH: usize = 19;
+
+// ListingLine - one source line, labels and symbol definitions have no words
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    pub address: u16,
+    pub words: Vec<u16>,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind {
+    Label,
+    Symbol,
+}
+
+impl Display for MapKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapKind::Label => write!(f, "label"),
+            MapKind::Symbol => write!(f, "symbol"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapEntry {
+    pub name: String,
+    pub value: u16,
+    pub kind: MapKind,
+}
+
+// Listing - the output of Assembler::listing, printed with Display it is the
+// aligned listing, map and json give the symbol map and a form for tools
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+    pub symbols: Vec<MapEntry>,
+}
+
+impl Listing {
+    pub fn map(&self) -> String {
+        let width = self.symbols.iter().map(|s| s.name.len()).max().unwrap_or(0);
+        self.symbols
+            .iter()
+            .map(|s| format!("{:<width$}  0x{:04X}  {}\n", s.name, s.value, s.kind))
+            .collect()
+    }
+
+    pub fn json(&self) -> String {
+        let lines: Vec<String> = self
+            .lines
+            .iter()
+            .map(|line| {
+                let words: Vec<String> = line.words.iter().map(|w| w.to_string()).collect();
+                format!(
+                    "{{\"address\":{},\"words\":[{}],\"source\":{}}}",
+                    line.address,
+                    words.join(","),
+                    json_string(&line.source)
+                )
+            })
+            .collect();
+        let symbols: Vec<String> = self
+            .symbols
+            .iter()
+            .map(|s| {
+                format!(
+                    "{{\"name\":{},\"value\":{},\"kind\":\"{}\"}}",
+                    json_string(&s.name),
+                    s.value,
+                    s.kind
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"lines\":[{}],\"symbols\":[{}]}}\n",
+            lines.join(","),
+            symbols.join(",")
+        )
+    }
+}
+
+impl Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in self.lines.iter() {
+            let words: Vec<String> = line.words.iter().map(|w| format!("{:04X}", w)).collect();
+            let mut rows = words.chunks(WORDS_PER_ROW);
+            // instructions are indented under their labels
+            let indent = if line.words.is_empty() { "" } else { "    " };
+            writeln!(
+                f,
+                "0x{:04X}  {:<WORDS_WIDTH$}  {}{}",
+                line.address,
+                rows.next().map(|row| row.join(" ")).unwrap_or_default(),
+                indent,
+                line.source
+            )?;
+            // longer expansions carry on underneath with their own addresses
+            for (i, row) in rows.enumerate() {
+                let address = line.address.wrapping_add(((i + 1) * WORDS_PER_ROW) as u16);
+                writeln!(f, "0x{:04X}  {}", address, row.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
+    }
+    result + "\""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{AsmParser, Assembler};
+
+    #[test]
+    fn test_listing_formats() {
+        let source = "
+            %WIDTH = 0x1E
+        start:
+            DATA R0, %WIDTH
+            CLF
+            CALL start
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let listing = Assembler::new()
+            .listing(0x0500, Some(instructions))
+            .unwrap();
+
+        assert_eq!(
+            listing.to_string(),
+            "\
+0x0500                       %WIDTH = 0x1E
+0x0500                       start:
+0x0500  0020 001E                DATA R0, %WIDTH
+0x0502  0060                     CLF
+0x0503  0023 0403 001E 0023      CALL start
+0x0507  0402 000F 0022 FFFF
+0x050B  0060 008B 0022 0402
+0x050F  001B 0022 0518 001E
+0x0513  0022 0403 000A 0040
+0x0517  0500
+"
+        );
+        assert_eq!(
+            listing.map(),
+            "WIDTH  0x001E  symbol\nstart  0x0500  label\n"
+        );
+        assert_eq!(
+            listing.json(),
+            "{\"lines\":[\
+{\"address\":1280,\"words\":[],\"source\":\"%WIDTH = 0x1E\"},\
+{\"address\":1280,\"words\":[],\"source\":\"start:\"},\
+{\"address\":1280,\"words\":[32,30],\"source\":\"DATA R0, %WIDTH\"},\
+{\"address\":1282,\"words\":[96],\"source\":\"CLF\"},\
+{\"address\":1283,\"words\":[35,1027,30,35,1026,15,34,65535,96,139,34,1026,27,34,1304,30,34,1027,10,64,1280],\"source\":\"CALL start\"}],\
+\"symbols\":[\
+{\"name\":\"WIDTH\",\"value\":30,\"kind\":\"symbol\"},\
+{\"name\":\"start\",\"value\":1280,\"kind\":\"label\"}]}\n"
+        );
+        assert_eq!(json_string("a\"b\\\n"), "\"a\\\"b\\\\\\u000a\"");
+    }
+}
@@ -1,3 +1,20 @@
 mod assembler;
+mod binary;
+mod disassembler;
+mod hex;
+mod linker;
+mod listing;
+mod object;
+mod parser;
 
 pub use assembler::Assembler;
+pub use binary::{bytes_from_words, read_binary, words_from_bytes, write_binary};
+pub use disassembler::Disassembler;
+pub use hex::{
+    intel_hex, is_intel_hex, is_logisim_image, logisim_image, parse_intel_hex, parse_logisim_image,
+    HexError,
+};
+pub use linker::{Image, LinkError, LinkedSymbol, Linker, MemoryRegion, PlacedSection, MEMORY_MAP};
+pub use listing::{Listing, ListingLine, MapEntry, MapKind};
+pub use object::{Object, ObjectError, ObjectSymbol, Relocation, RelocationTarget, Section};
+pub use parser::AsmParser;
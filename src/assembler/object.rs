@@ -0,0 +1,474 @@
+use crate::instructions::SectionKind;
+use std::{fs, path::Path};
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"COBJ";
+const VERSION: u16 = 1;
+const ABSOLUTE: u8 = 0xFF;
+const SYMBOL_TARGET: u8 = 0xFE;
+
+#[derive(Debug, Error)]
+pub enum ObjectError {
+    #[error("Not an object file")]
+    BadMagic,
+
+    #[error("Unsupported object file version: {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("Object file ends early")]
+    Truncated,
+
+    #[error("Unknown section: {0}")]
+    UnknownSection(u8),
+
+    #[error("Symbol name is not valid UTF-8")]
+    BadName,
+
+    #[error("Symbol name is longer than 255 bytes: {0}")]
+    NameTooLong(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+// Section - words that are placed together, addresses inside it are offsets
+// from its start until it is linked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub words: Vec<u16>,
+}
+
+// ObjectSymbol - an exported label, relative to its section, or a DEFSYMBOL,
+// which has no section and keeps its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub section: Option<SectionKind>,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelocationTarget {
+    // add the address the section is linked at
+    Section(SectionKind),
+    // add the value of a symbol from this or another object
+    Symbol(String),
+}
+
+// Relocation - a word at offset in section that holds an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: SectionKind,
+    pub offset: u16,
+    pub target: RelocationTarget,
+}
+
+// Object - the output of Assembler::object. Version 1 layout, all little
+// endian, names are a u8 length and UTF-8 bytes:
+//   "COBJ", version u16,
+//   section count u16, per section: kind u8, length u16, words,
+//   symbol count u16, per symbol: name, section u8 (0xFF absolute), value u16,
+//   relocation count u16, per relocation: section u8, offset u16,
+//     target u8 (a section, or 0xFE followed by a symbol name)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<ObjectSymbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Object {
+    pub fn section(&self, kind: SectionKind) -> Option<&Section> {
+        self.sections.iter().find(|s| s.kind == kind)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&ObjectSymbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ObjectError> {
+        Ok(fs::write(path, self.to_bytes()?)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ObjectError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    // whether bytes start like an object file rather than a raw binary
+    pub fn is_object(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    // fails on a name too long for its u8 length
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ObjectError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+
+        bytes.extend((self.sections.len() as u16).to_le_bytes());
+        for section in self.sections.iter() {
+            bytes.push(section.kind as u8);
+            bytes.extend((section.words.len() as u16).to_le_bytes());
+            for word in section.words.iter() {
+                bytes.extend(word.to_le_bytes());
+            }
+        }
+
+        bytes.extend((self.symbols.len() as u16).to_le_bytes());
+        for symbol in self.symbols.iter() {
+            write_name(&mut bytes, &symbol.name)?;
+            bytes.push(symbol.section.map(|s| s as u8).unwrap_or(ABSOLUTE));
+            bytes.extend(symbol.value.to_le_bytes());
+        }
+
+        bytes.extend((self.relocations.len() as u16).to_le_bytes());
+        for relocation in self.relocations.iter() {
+            bytes.push(relocation.section as u8);
+            bytes.extend(relocation.offset.to_le_bytes());
+            match &relocation.target {
+                RelocationTarget::Section(kind) => bytes.push(*kind as u8),
+                RelocationTarget::Symbol(name) => {
+                    bytes.push(SYMBOL_TARGET);
+                    write_name(&mut bytes, name)?;
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ObjectError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(ObjectError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(ObjectError::UnsupportedVersion(version));
+        }
+
+        let mut sections = Vec::new();
+        for _ in 0..reader.u16()? {
+            let kind = reader.section()?;
+            let len = reader.u16()?;
+            let words = (0..len).map(|_| reader.u16()).collect::<Result<_, _>>()?;
+            sections.push(Section { kind, words });
+        }
+
+        let mut symbols = Vec::new();
+        for _ in 0..reader.u16()? {
+            let name = reader.name()?;
+            let section = match reader.u8()? {
+                ABSOLUTE => None,
+                kind => Some(section_kind(kind)?),
+            };
+            let value = reader.u16()?;
+            symbols.push(ObjectSymbol {
+                name,
+                section,
+                value,
+            });
+        }
+
+        let mut relocations = Vec::new();
+        for _ in 0..reader.u16()? {
+            let section = reader.section()?;
+            let offset = reader.u16()?;
+            let target = match reader.u8()? {
+                SYMBOL_TARGET => RelocationTarget::Symbol(reader.name()?),
+                kind => RelocationTarget::Section(section_kind(kind)?),
+            };
+            relocations.push(Relocation {
+                section,
+                offset,
+                target,
+            });
+        }
+
+        Ok(Self {
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+}
+
+fn section_kind(kind: u8) -> Result<SectionKind, ObjectError> {
+    match kind {
+        0 => Ok(SectionKind::Code),
+        1 => Ok(SectionKind::Data),
+        _ => Err(ObjectError::UnknownSection(kind)),
+    }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) -> Result<(), ObjectError> {
+    let len = u8::try_from(name.len()).map_err(|_| ObjectError::NameTooLong(name.to_string()))?;
+    bytes.push(len);
+    bytes.extend(name.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ObjectError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or(ObjectError::Truncated)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ObjectError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ObjectError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn section(&mut self) -> Result<SectionKind, ObjectError> {
+        section_kind(self.u8()?)
+    }
+
+    fn name(&mut self) -> Result<String, ObjectError> {
+        let len = self.u8()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| ObjectError::BadName)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{AsmParser, Assembler};
+    use crate::generator::initialise_common_code;
+    use crate::instructions::Instructions;
+
+    #[test]
+    fn test_object_bytes() {
+        let object = Object {
+            sections: vec![
+                Section {
+                    kind: SectionKind::Code,
+                    words: vec![0x0040, 0x0002, 0x0040, 0x0000],
+                },
+                Section {
+                    kind: SectionKind::Data,
+                    words: vec![0x1234],
+                },
+            ],
+            symbols: vec![
+                ObjectSymbol {
+                    name: "loop".to_string(),
+                    section: Some(SectionKind::Code),
+                    value: 0x0002,
+                },
+                ObjectSymbol {
+                    name: "WIDTH".to_string(),
+                    section: None,
+                    value: 0x001E,
+                },
+            ],
+            relocations: vec![
+                Relocation {
+                    section: SectionKind::Code,
+                    offset: 1,
+                    target: RelocationTarget::Section(SectionKind::Code),
+                },
+                Relocation {
+                    section: SectionKind::Code,
+                    offset: 3,
+                    target: RelocationTarget::Symbol("main".to_string()),
+                },
+            ],
+        };
+
+        let bytes = object.to_bytes().unwrap();
+        assert!(Object::is_object(&bytes));
+        assert_eq!(Object::from_bytes(&bytes).unwrap(), object);
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            Object::from_bytes(&bad),
+            Err(ObjectError::BadMagic)
+        ));
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(matches!(
+            Object::from_bytes(&bad),
+            Err(ObjectError::UnsupportedVersion(2))
+        ));
+
+        let mut bad = bytes.clone();
+        bad[8] = 7;
+        assert!(matches!(
+            Object::from_bytes(&bad),
+            Err(ObjectError::UnknownSection(7))
+        ));
+
+        assert!(matches!(
+            Object::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ObjectError::Truncated)
+        ));
+
+        let mut long = object.clone();
+        long.symbols[0].name = "x".repeat(256);
+        assert!(matches!(long.to_bytes(), Err(ObjectError::NameTooLong(_))));
+        long.symbols[0].name = "x".repeat(255);
+        assert_eq!(Object::from_bytes(&long.to_bytes().unwrap()).unwrap(), long);
+    }
+
+    #[test]
+    fn test_object_assemble() {
+        let source = "
+            %WIDTH = 0x1E
+        start:
+            DATA R0, %WIDTH
+            DATA R1, %HEIGHT
+            CALL draw
+        .data
+        table:
+            JMP start
+        .code
+        loop:
+            JMPZ loop
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let object = Assembler::new().object(Some(instructions)).unwrap();
+
+        assert_eq!(
+            object.section(SectionKind::Code).unwrap().words,
+            vec![
+                0x0020, 0x001E, 0x0021, 0x0000, // DATA
+                0x0023, 0x0403, 0x001E, 0x0023, 0x0402, 0x000F, 0x0022, 0xFFFF, 0x0060, 0x008B,
+                0x0022, 0x0402, 0x001B, 0x0022, 0x0019, 0x001E, 0x0022, 0x0403, 0x000A, 0x0040,
+                0x0000, // CALL
+                0x0051, 0x0019, // JMPZ
+            ]
+        );
+        assert_eq!(
+            object.section(SectionKind::Data).unwrap().words,
+            vec![0x0040, 0x0000]
+        );
+        assert_eq!(
+            object.symbols,
+            vec![
+                ObjectSymbol {
+                    name: "WIDTH".to_string(),
+                    section: None,
+                    value: 0x001E,
+                },
+                ObjectSymbol {
+                    name: "loop".to_string(),
+                    section: Some(SectionKind::Code),
+                    value: 0x0019,
+                },
+                ObjectSymbol {
+                    name: "start".to_string(),
+                    section: Some(SectionKind::Code),
+                    value: 0x0000,
+                },
+                ObjectSymbol {
+                    name: "table".to_string(),
+                    section: Some(SectionKind::Data),
+                    value: 0x0000,
+                },
+            ]
+        );
+
+        let relocations: Vec<(SectionKind, u16, RelocationTarget)> = object
+            .relocations
+            .into_iter()
+            .map(|r| (r.section, r.offset, r.target))
+            .collect();
+        assert_eq!(
+            relocations,
+            vec![
+                (
+                    SectionKind::Code,
+                    3,
+                    RelocationTarget::Symbol("HEIGHT".to_string())
+                ),
+                (
+                    SectionKind::Code,
+                    18,
+                    RelocationTarget::Section(SectionKind::Code)
+                ),
+                (
+                    SectionKind::Code,
+                    24,
+                    RelocationTarget::Symbol("draw".to_string())
+                ),
+                (
+                    SectionKind::Data,
+                    1,
+                    RelocationTarget::Section(SectionKind::Code)
+                ),
+                (
+                    SectionKind::Code,
+                    26,
+                    RelocationTarget::Section(SectionKind::Code)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_not_relocatable() {
+        for (source, line) in [
+            ("target: DATA R0, hi(target)", "DATA R0, hi(target)"),
+            ("target: DATA R0, target * 2", "DATA R0, target * 0x0002"),
+            (
+                "target: .word 1, target - target",
+                ".word 0x0001, target - target",
+            ),
+            (".fill 2, lo(%EXTERNAL)", ".fill 0x0002, lo(%EXTERNAL)"),
+        ] {
+            let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+            assert_eq!(
+                Assembler::new()
+                    .object(Some(instructions))
+                    .unwrap_err()
+                    .to_string(),
+                format!(
+                    "test.asm:1:{}: error: '{}' needs an absolute address and cannot go in an \
+                     object\n  help: assemble without --object to fix its address",
+                    source.find(&line[..4]).unwrap() + 1,
+                    line
+                )
+            );
+        }
+
+        // symbols defined in the object already have their final value
+        let instructions = AsmParser::new()
+            .parse("test.asm", "%TEMP = 0xFF00\nDATA R0, hi(%TEMP) * 2")
+            .unwrap();
+        let object = Assembler::new().object(Some(instructions)).unwrap();
+        assert_eq!(
+            object.section(SectionKind::Code).unwrap().words,
+            vec![0x0020, 0x01FE]
+        );
+        assert!(object.relocations.is_empty());
+    }
+
+    #[test]
+    fn test_object_common_code() {
+        // the shared routines jump to the program's main label
+        let mut instructions = Instructions::new();
+        instructions.add(initialise_common_code());
+        let object = Assembler::new().object(Some(instructions)).unwrap();
+
+        assert!(object.symbol("main").is_none());
+        assert!(object.symbol("ROUTINE-io-pollKeyboard").is_some());
+        assert!(object
+            .relocations
+            .iter()
+            .any(|r| r.target == RelocationTarget::Symbol("main".to_string())));
+    }
+}
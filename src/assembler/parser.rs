@@ -0,0 +1,1773 @@
+use crate::instructions::{
+    anonymous_reference, is_anonymous_label, Error, Expression, Function, IOMode, Instructions,
+    Label, Node, Number, Operator, Register, SafeInstruction, SectionKind, SourceLocation, Symbol,
+    ADD, ALIGN, AND, ASCII, CALL, CLF, CMP, CMPI, DATA, DEC, DEFLABEL, DEFSYMBOL, DI, DIV, EI,
+    FILL, GETSP, HALT, HCALL, HPOP, HPUSH, HRET, IN, INC, INT, IRET, JMP, JMPF, JR, LOAD, MOV, MUL,
+    NEG, NOT, OR, ORG, OUT, POP, PUSH, RET, SECTION, SETIV, SETSP, SHL, SHR, STORE, SUB, WORD, XOR,
+};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+// expansions inside expansions, deeper than this is taken as recursion
+const MAX_MACRO_DEPTH: usize = 64;
+
+// AsmParser - reads assembly source written in the same syntax the
+// instructions print with Display and turns it into Instructions
+//
+//  %LINE-WIDTH = 0x1E      ; symbol definition
+//  main:                   // label definition
+//  .loop:                  // local label, main.loop until the next global one
+//  1:  JMP 1b              // anonymous label, 1b and 1f are the nearest 1:
+//                          // before and after
+//      DATA R0, %LINE-WIDTH
+//      DATA R1, (%LINE-WIDTH << 3) + 'A'  // worked out at assembly time, with
+//                              // + - * / << >> & | ~, brackets, hi() and lo()
+//      JMPE main
+//  .data                   // following lines go to the data section
+//  table: .word 0x0001, main   // raw words, also .fill count, value,
+//      .string "hi\n"          // .ascii without the 0, .org address, .align n
+//  .include "font.asm"     // searched next to this file, then the include paths
+//  .macro select r         // \r is replaced by the argument, labels defined
+//      DATA \r, 0x0007     // in the body are renamed for every expansion and
+//      OUT Addr, \r        // are local labels of the caller
+//  .endm
+#[derive(Default)]
+pub struct AsmParser {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    // the files being parsed, outermost first, to catch include cycles
+    includes: Vec<PathBuf>,
+}
+
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+}
+
+// SourceLine - the tokens of one line and where it came from
+#[derive(Clone)]
+struct SourceLine {
+    file: String,
+    number: usize,
+    end_column: usize,
+    tokens: Vec<Token>,
+}
+
+impl AsmParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_include_path(&mut self, path: &Path) {
+        self.include_paths.push(path.to_path_buf());
+    }
+
+    pub fn parse_file(&mut self, path: &Path) -> Result<Instructions, Error> {
+        let file = path.display().to_string();
+        let source = fs::read_to_string(path).map_err(|e| Error::Io(file.clone(), e))?;
+        let mut instructions = Instructions::new();
+        // only a file read from disk can be part of an include cycle
+        self.includes = fs::canonicalize(path).into_iter().collect();
+        self.parse_source(&file, &source, &mut instructions)?;
+        Ok(instructions)
+    }
+
+    // file only names the source in errors, it is not read
+    pub fn parse(&mut self, file: &str, source: &str) -> Result<Instructions, Error> {
+        let mut instructions = Instructions::new();
+        self.includes.clear();
+        self.parse_source(file, source, &mut instructions)?;
+        Ok(instructions)
+    }
+
+    fn parse_source(
+        &mut self,
+        file: &str,
+        source: &str,
+        instructions: &mut Instructions,
+    ) -> Result<(), Error> {
+        let mut definition: Option<(String, SourceLocation, Macro)> = None;
+
+        for (i, text) in source.lines().enumerate() {
+            let line = SourceLine {
+                file: file.to_string(),
+                number: i + 1,
+                end_column: text.chars().count() + 1,
+                tokens: tokenize(file, i + 1, text)?,
+            };
+            let directive = match line.tokens.first() {
+                Some(Token {
+                    kind: TokenKind::Word(w),
+                    ..
+                }) => w.to_uppercase(),
+                _ => String::new(),
+            };
+
+            if let Some((_, _, body)) = definition.as_mut() {
+                match directive.as_str() {
+                    ".ENDM" => {
+                        line.reader().directive_end()?;
+                        let (name, _, body) = definition.take().unwrap();
+                        for body_line in body.body.iter() {
+                            body_line.check_params(&body.params)?;
+                        }
+                        self.macros.insert(name, body);
+                    }
+                    ".MACRO" => return line.reader().error(1, "nested .macro".to_string()),
+                    _ => body.body.push(line),
+                }
+                continue;
+            }
+
+            match directive.as_str() {
+                ".MACRO" => definition = Some(line.reader().macro_header()?),
+                ".ENDM" => return line.reader().error(1, ".endm without .macro".to_string()),
+                ".INCLUDE" => {
+                    let (name, column) = line.reader().include()?;
+                    self.include(&line, column, &name, instructions)?;
+                }
+                _ => {
+                    line.check_params(&[])?;
+                    self.parse_line(&line, 0, instructions)?
+                }
+            }
+        }
+
+        match definition {
+            Some((name, location, _)) => Err(Error::Parse(
+                location,
+                format!("macro '{}' has no .endm", name),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // an instruction or a macro call, either may follow a label
+    fn parse_line(
+        &mut self,
+        line: &SourceLine,
+        depth: usize,
+        instructions: &mut Instructions,
+    ) -> Result<(), Error> {
+        let labelled = line.tokens.get(1).map(|t| &t.kind) == Some(&TokenKind::Colon);
+        let start = if labelled { 2 } else { 0 };
+        let (name, column) = match line.tokens.get(start) {
+            Some(Token {
+                kind: TokenKind::Word(name),
+                column,
+            }) if self.macros.contains_key(name) => (name.clone(), *column),
+            _ => {
+                for (instruction, column) in line.reader().parse()? {
+                    instructions.add_at(instruction, line.location(column));
+                }
+                return Ok(());
+            }
+        };
+
+        let location = SourceLocation::new(&line.file, line.number, column);
+        if depth >= MAX_MACRO_DEPTH {
+            return Err(Error::Parse(
+                location,
+                format!("macro '{}' expands itself too deeply", name),
+            ));
+        }
+        let mut reader = line.reader();
+        if labelled {
+            let (label, column) = reader.label_definition()?;
+            instructions.add_at(label, line.location(column));
+        }
+        let args = reader.arguments()?;
+        self.expand(&name, &location, args, depth, instructions)
+    }
+
+    fn expand(
+        &mut self,
+        name: &str,
+        location: &SourceLocation,
+        args: Vec<Vec<Token>>,
+        depth: usize,
+        instructions: &mut Instructions,
+    ) -> Result<(), Error> {
+        let definition = self.macros[name].clone();
+        if args.len() != definition.params.len() {
+            return Err(Error::Parse(
+                location.clone(),
+                format!(
+                    "macro '{}' takes {} argument{}, found {}",
+                    name,
+                    definition.params.len(),
+                    match definition.params.len() {
+                        1 => "",
+                        _ => "s",
+                    },
+                    args.len()
+                ),
+            ));
+        }
+
+        // anonymous labels can be defined again anyway
+        self.expansions += 1;
+        let labels: Vec<String> = definition
+            .body
+            .iter()
+            .filter(|line| line.tokens.get(1).map(|t| &t.kind) == Some(&TokenKind::Colon))
+            .filter_map(|line| match &line.tokens[0].kind {
+                TokenKind::Word(label) if !is_anonymous_label(label) => Some(label.clone()),
+                _ => None,
+            })
+            .collect();
+
+        for body in definition.body.iter() {
+            let mut tokens = Vec::new();
+            for token in body.tokens.iter() {
+                match &token.kind {
+                    TokenKind::Param(param) => {
+                        let i = definition.params.iter().position(|p| p == param).unwrap();
+                        tokens.extend(args[i].iter().map(|arg| Token {
+                            kind: arg.kind.clone(),
+                            column: token.column,
+                        }));
+                    }
+                    // every label of the body is local to the caller's scope,
+                    // so an expansion does not start a new one
+                    TokenKind::Word(w) if labels.contains(w) => tokens.push(Token {
+                        kind: TokenKind::Word(format!(
+                            ".{}.{}.{}",
+                            name,
+                            self.expansions,
+                            w.trim_start_matches('.')
+                        )),
+                        column: token.column,
+                    }),
+                    _ => tokens.push(token.clone()),
+                }
+            }
+
+            let line = SourceLine {
+                tokens,
+                ..body.clone()
+            };
+            self.parse_line(&line, depth + 1, instructions)
+                .map_err(|e| note(e, &format!("in macro '{}' expanded at {}", name, location)))?;
+        }
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        line: &SourceLine,
+        column: usize,
+        name: &str,
+        instructions: &mut Instructions,
+    ) -> Result<(), Error> {
+        let location = SourceLocation::new(&line.file, line.number, column);
+        let path = Path::new(&line.file)
+            .parent()
+            .map(|dir| dir.join(name))
+            .into_iter()
+            .chain(self.include_paths.iter().map(|dir| dir.join(name)))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                Error::Parse(
+                    location.clone(),
+                    format!("cannot find '{}' in the include path", name),
+                )
+            })?;
+
+        let file = path.display().to_string();
+        let canonical = fs::canonicalize(&path).map_err(|e| Error::Io(file.clone(), e))?;
+        if self.includes.contains(&canonical) {
+            let cycle: Vec<String> = self
+                .includes
+                .iter()
+                .skip_while(|p| **p != canonical)
+                .chain([&canonical])
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(Error::Parse(
+                location,
+                format!("include cycle {}", cycle.join(" -> ")),
+            ));
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| Error::Io(file.clone(), e))?;
+        self.includes.push(canonical);
+        let result = self
+            .parse_source(&file, &source, instructions)
+            .map_err(|e| note(e, &format!("included from {}", location)));
+        self.includes.pop();
+        result
+    }
+}
+
+// errors from a macro body or an included file keep their own location and
+// say how they were reached
+fn note(error: Error, context: &str) -> Error {
+    match error {
+        Error::Parse(location, message) => {
+            Error::Parse(location, format!("{} ({})", message, context))
+        }
+        e => e,
+    }
+}
+
+impl SourceLine {
+    fn check_params(&self, params: &[String]) -> Result<(), Error> {
+        for token in self.tokens.iter() {
+            match &token.kind {
+                TokenKind::Param(param) if !params.contains(param) => {
+                    return self.reader().error(
+                        token.column,
+                        format!("unknown macro parameter {}", token.kind.describe()),
+                    )
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn location(&self, column: usize) -> SourceLocation {
+        SourceLocation::new(&self.file, self.number, column)
+    }
+
+    fn reader(&self) -> Line<'_> {
+        Line {
+            file: &self.file,
+            number: self.number,
+            end_column: self.end_column,
+            tokens: self.tokens.clone(),
+            position: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Symbol(String),
+    // \name inside a macro body
+    Param(String),
+    String(String),
+    Char(char),
+    Operator(String),
+    Comma,
+    Colon,
+    Equals,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+impl TokenKind {
+    fn describe(&self) -> String {
+        match self {
+            TokenKind::Word(w) => format!("'{}'", w),
+            TokenKind::Symbol(s) => format!("'%{}'", s),
+            TokenKind::Param(p) => format!("'\\{}'", p),
+            TokenKind::String(s) => format!("\"{}\"", s),
+            TokenKind::Char(c) => format!("{}", Node::Char(*c)),
+            TokenKind::Operator(o) => format!("'{}'", o),
+            TokenKind::Comma => "','".to_string(),
+            TokenKind::Colon => "':'".to_string(),
+            TokenKind::Equals => "'='".to_string(),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+fn tokenize(file: &str, line: usize, source: &str) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ';' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+            break;
+        } else if c == ',' || c == ':' || c == '=' {
+            tokens.push(Token {
+                kind: match c {
+                    ',' => TokenKind::Comma,
+                    ':' => TokenKind::Colon,
+                    _ => TokenKind::Equals,
+                },
+                column,
+            });
+            i += 1;
+        } else if c == '<' || c == '>' {
+            if chars.get(i + 1) != Some(&c) {
+                return Err(Error::Parse(
+                    SourceLocation::new(file, line, column),
+                    format!("unexpected character '{}'", c),
+                ));
+            }
+            tokens.push(Token {
+                kind: TokenKind::Operator(format!("{}{}", c, c)),
+                column,
+            });
+            i += 2;
+        } else if "+-*/&|~()".contains(c) {
+            tokens.push(Token {
+                kind: TokenKind::Operator(c.to_string()),
+                column,
+            });
+            i += 1;
+        } else if c == '\'' {
+            let (value, len) = match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some('\\'), Some(e)) => match escape(*e) {
+                    Some(value) => (value, 2),
+                    None => {
+                        return Err(Error::Parse(
+                            SourceLocation::new(file, line, column + 1),
+                            format!("unknown escape '\\{}'", e),
+                        ))
+                    }
+                },
+                (Some(c), _) if *c != '\'' => (*c, 1),
+                _ => (' ', 0),
+            };
+            if len == 0 || chars.get(i + 1 + len) != Some(&'\'') {
+                return Err(Error::Parse(
+                    SourceLocation::new(file, line, column),
+                    "unterminated character literal".to_string(),
+                ));
+            }
+            tokens.push(Token {
+                kind: TokenKind::Char(value),
+                column,
+            });
+            i += len + 2;
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some('"') => break,
+                    Some('\\') => {
+                        let e = chars.get(i + 1).copied().unwrap_or(' ');
+                        text.push(escape(e).ok_or_else(|| {
+                            Error::Parse(
+                                SourceLocation::new(file, line, i + 1),
+                                format!("unknown escape '\\{}'", e),
+                            )
+                        })?);
+                        i += 2;
+                    }
+                    Some(c) => {
+                        text.push(*c);
+                        i += 1;
+                    }
+                    None => {
+                        return Err(Error::Parse(
+                            SourceLocation::new(file, line, column),
+                            "unterminated string".to_string(),
+                        ))
+                    }
+                }
+            }
+            tokens.push(Token {
+                kind: TokenKind::String(text),
+                column,
+            });
+            i += 1;
+        } else if c == '%' || c == '\\' || is_word_char(c) {
+            // '-' joins words like ROUTINE-io but cannot start one
+            let start = if is_word_char(c) { i } else { i + 1 };
+            let mut end = start;
+            while end < chars.len() && is_word_char(chars[end]) {
+                end += 1;
+            }
+            let word: String = chars[start..end].iter().collect();
+
+            if c == '%' || c == '\\' {
+                if word.is_empty() {
+                    return Err(Error::Parse(
+                        SourceLocation::new(file, line, column),
+                        format!("expected a name after '{}'", c),
+                    ));
+                }
+                tokens.push(Token {
+                    kind: match c {
+                        '%' => TokenKind::Symbol(word),
+                        _ => TokenKind::Param(word),
+                    },
+                    column,
+                });
+            } else {
+                tokens.push(Token {
+                    kind: TokenKind::Word(word),
+                    column,
+                });
+            }
+            i = end;
+        } else {
+            return Err(Error::Parse(
+                SourceLocation::new(file, line, column),
+                format!("unexpected character '{}'", c),
+            ));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// the character after a backslash in character literals and strings
+fn escape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '\\' | '\'' | '"' => Some(c),
+        _ => None,
+    }
+}
+
+fn parse_number(word: &str) -> Option<u32> {
+    if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = word.strip_prefix("0b").or_else(|| word.strip_prefix("0B")) {
+        u32::from_str_radix(bin, 2).ok()
+    } else {
+        word.parse::<u32>().ok()
+    }
+}
+
+struct Line<'a> {
+    file: &'a str,
+    number: usize,
+    end_column: usize,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl<'a> Line<'a> {
+    fn location(&self, column: usize) -> SourceLocation {
+        SourceLocation::new(self.file, self.number, column)
+    }
+
+    fn error<T>(&self, column: usize, message: String) -> Result<T, Error> {
+        Err(Error::Parse(self.location(column), message))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, Error> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => self.error(self.end_column, format!("expected {}", expected)),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), Error> {
+        let token = self.next(&kind.describe())?;
+        if token.kind != kind {
+            return self.error(
+                token.column,
+                format!(
+                    "expected {}, found {}",
+                    kind.describe(),
+                    token.kind.describe()
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    fn expect_end(&self) -> Result<(), Error> {
+        match self.peek() {
+            Some(token) => self.error(
+                token.column,
+                format!("unexpected {} at end of line", token.kind.describe()),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    // nothing may follow the directive word
+    fn directive_end(&mut self) -> Result<(), Error> {
+        self.position = 1;
+        self.expect_end()
+    }
+
+    // .macro name [param, ...]
+    fn macro_header(&mut self) -> Result<(String, SourceLocation, Macro), Error> {
+        self.position = 1;
+        let token = self.next("macro name")?;
+        let name = match &token.kind {
+            TokenKind::Word(w) => w.clone(),
+            kind => {
+                return self.error(
+                    token.column,
+                    format!("expected macro name, found {}", kind.describe()),
+                )
+            }
+        };
+
+        let mut params: Vec<String> = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            if !params.is_empty() {
+                self.expect(TokenKind::Comma)?;
+            }
+            let token = self.next("parameter name")?;
+            match &token.kind {
+                TokenKind::Word(w) if !params.contains(w) => params.push(w.clone()),
+                TokenKind::Word(w) => {
+                    return self.error(token.column, format!("parameter '{}' repeats", w))
+                }
+                kind => {
+                    return self.error(
+                        token.column,
+                        format!("expected parameter name, found {}", kind.describe()),
+                    )
+                }
+            }
+        }
+
+        Ok((
+            name,
+            self.location(1),
+            Macro {
+                params,
+                body: Vec::new(),
+            },
+        ))
+    }
+
+    // .include "file"
+    fn include(&mut self) -> Result<(String, usize), Error> {
+        self.position = 1;
+        let token = self.next("file name")?;
+        match token.kind {
+            TokenKind::String(name) => {
+                self.expect_end()?;
+                Ok((name, token.column))
+            }
+            kind => self.error(
+                token.column,
+                format!("expected file name in quotes, found {}", kind.describe()),
+            ),
+        }
+    }
+
+    // the label in front of a macro call
+    fn label_definition(&mut self) -> Result<(SafeInstruction, usize), Error> {
+        let token = self.next("label")?;
+        match token.kind {
+            TokenKind::Word(name) => {
+                self.position += 1;
+                Ok((Rc::new(DEFLABEL::new(&name)), token.column))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the comma separated arguments after the macro name
+    fn arguments(&mut self) -> Result<Vec<Vec<Token>>, Error> {
+        self.next("macro name")?;
+        let mut args = Vec::new();
+        if self.peek().is_none() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            self.position += 1;
+            if token.kind != TokenKind::Comma {
+                arg.push(token);
+                continue;
+            }
+            if arg.is_empty() {
+                return self.error(token.column, "expected argument before ','".to_string());
+            }
+            args.push(arg);
+            arg = Vec::new();
+        }
+        if arg.is_empty() {
+            return self.error(self.end_column, "expected argument".to_string());
+        }
+        args.push(arg);
+        Ok(args)
+    }
+
+    // the instructions on the line, each with the column it starts at
+    fn parse(&mut self) -> Result<Vec<(SafeInstruction, usize)>, Error> {
+        let mut instructions: Vec<(SafeInstruction, usize)> = Vec::new();
+
+        let first = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Ok(instructions),
+        };
+
+        match first.kind {
+            // %NAME = value
+            TokenKind::Symbol(name) => {
+                self.position += 1;
+                self.expect(TokenKind::Equals)?;
+                let expression = self.expression()?;
+                self.expect_end()?;
+                let symbol: SafeInstruction = match expression.node {
+                    Node::Number(value) => Rc::new(DEFSYMBOL::new(&name, value)),
+                    _ => Rc::new(DEFSYMBOL::from_expression(&name, expression)),
+                };
+                instructions.push((symbol, first.column));
+                return Ok(instructions);
+            }
+            // label: [instruction]
+            TokenKind::Word(name)
+                if self.tokens.get(1).map(|t| &t.kind) == Some(&TokenKind::Colon) =>
+            {
+                self.position += 2;
+                instructions.push((Rc::new(DEFLABEL::new(&name)), first.column));
+                if self.peek().is_none() {
+                    return Ok(instructions);
+                }
+            }
+            _ => {}
+        }
+
+        let column = self.peek().unwrap().column;
+        instructions.push((self.instruction()?, column));
+        self.expect_end()?;
+
+        Ok(instructions)
+    }
+
+    fn instruction(&mut self) -> Result<SafeInstruction, Error> {
+        let token = self.next("instruction")?;
+        let mnemonic = match &token.kind {
+            TokenKind::Word(w) => w.to_uppercase(),
+            kind => {
+                return self.error(
+                    token.column,
+                    format!("expected instruction, found {}", kind.describe()),
+                )
+            }
+        };
+
+        let instruction: SafeInstruction = match mnemonic.as_str() {
+            "LD" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(LOAD::new(a, b))
+            }
+            "ST" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(STORE::new(a, b))
+            }
+            "DATA" => {
+                let register = self.register()?;
+                self.expect(TokenKind::Comma)?;
+                let expression = self.expression()?;
+                match &expression.node {
+                    Node::Number(value) => Rc::new(DATA::new(register, Number::new(*value))),
+                    Node::Symbol(name) => Rc::new(DATA::new(register, Symbol::new(name))),
+                    _ => Rc::new(DATA::new(register, expression)),
+                }
+            }
+            "JR" => Rc::new(JR::new(self.register()?)),
+            "JMP" => Rc::new(JMP::new(self.label()?)),
+            "CLF" => Rc::new(CLF::new()),
+            "IN" => {
+                let mode = self.io_mode()?;
+                self.expect(TokenKind::Comma)?;
+                Rc::new(IN::new(mode, self.register()?))
+            }
+            "OUT" => {
+                let mode = self.io_mode()?;
+                self.expect(TokenKind::Comma)?;
+                Rc::new(OUT::new(mode, self.register()?))
+            }
+            "ADD" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(ADD::new(a, b))
+            }
+            "AND" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(AND::new(a, b))
+            }
+            "OR" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(OR::new(a, b))
+            }
+            "XOR" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(XOR::new(a, b))
+            }
+            "CMP" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(CMP::new(a, b))
+            }
+            "SHL" => Rc::new(SHL::new(self.register()?)),
+            "SHR" => Rc::new(SHR::new(self.register()?)),
+            "NOT" => Rc::new(NOT::new(self.register()?)),
+            "CALL" => Rc::new(CALL::new(self.label()?)),
+            "RET" => Rc::new(RET::new()),
+            "PUSH" => Rc::new(PUSH::new(self.scratch_register()?)),
+            "POP" => Rc::new(POP::new(self.scratch_register()?)),
+            "HPUSH" => Rc::new(HPUSH::new(self.register()?)),
+            "HPOP" => Rc::new(HPOP::new(self.register()?)),
+            "HCALL" => Rc::new(HCALL::new(self.register()?)),
+            "HRET" => Rc::new(HRET::new()),
+            "SETSP" => Rc::new(SETSP::new(self.register()?)),
+            "GETSP" => Rc::new(GETSP::new(self.register()?)),
+            "IRET" => Rc::new(IRET::new()),
+            "EI" => Rc::new(EI::new()),
+            "DI" => Rc::new(DI::new()),
+            "SETIV" => Rc::new(SETIV::new(self.register()?)),
+            "INT" => Rc::new(INT::new()),
+            "HALT" => Rc::new(HALT::new()),
+            "MOV" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(MOV::new(a, b))
+            }
+            "SUB" => {
+                let (a, b) = self.two_registers()?;
+                Rc::new(SUB::new(a, b))
+            }
+            "NEG" => Rc::new(NEG::new(self.register()?)),
+            "INC" => Rc::new(INC::new(self.register()?)),
+            "DEC" => Rc::new(DEC::new(self.register()?)),
+            "MUL" => {
+                let a = self.scratch_register()?;
+                self.expect(TokenKind::Comma)?;
+                Rc::new(MUL::new(a, self.scratch_register()?))
+            }
+            "DIV" => {
+                let a = self.scratch_register()?;
+                self.expect(TokenKind::Comma)?;
+                Rc::new(DIV::new(a, self.scratch_register()?))
+            }
+            "CMPI" => {
+                let register = self.scratch_register()?;
+                self.expect(TokenKind::Comma)?;
+                let expression = self.expression()?;
+                match &expression.node {
+                    Node::Number(value) => Rc::new(CMPI::new(register, Number::new(*value))),
+                    Node::Symbol(name) => Rc::new(CMPI::new(register, Symbol::new(name))),
+                    _ => Rc::new(CMPI::new(register, expression)),
+                }
+            }
+            ".CODE" => Rc::new(SECTION::new(SectionKind::Code)),
+            ".DATA" => Rc::new(SECTION::new(SectionKind::Data)),
+            ".WORD" => {
+                let mut values = vec![self.expression()?];
+                while self.peek().is_some() {
+                    self.expect(TokenKind::Comma)?;
+                    values.push(self.expression()?);
+                }
+                Rc::new(WORD::new(values))
+            }
+            ".FILL" => {
+                let count = self.constant()?;
+                self.expect(TokenKind::Comma)?;
+                Rc::new(FILL::new(count, self.expression()?))
+            }
+            ".ASCII" | ".STRING" => {
+                let token = self.next("string")?;
+                let text = match token.kind {
+                    TokenKind::String(text) => text,
+                    kind => {
+                        return self.error(
+                            token.column,
+                            format!("expected string in quotes, found {}", kind.describe()),
+                        )
+                    }
+                };
+                if let Some(c) = text.chars().find(|c| *c as u32 > u16::MAX as u32) {
+                    return self.error(
+                        token.column,
+                        format!("character '{}' does not fit in 16 bits", c),
+                    );
+                }
+                Rc::new(ASCII::new(&text, mnemonic == ".STRING"))
+            }
+            ".ORG" => Rc::new(ORG::new(self.constant()?)),
+            ".ALIGN" => {
+                let column = self.peek().map_or(self.end_column, |t| t.column);
+                match self.constant()? {
+                    0 => return self.error(column, "alignment must be at least 1".to_string()),
+                    alignment => Rc::new(ALIGN::new(alignment)),
+                }
+            }
+            jump if jump.starts_with("JMP") => {
+                let flags = match jump_flags(&jump[3..]) {
+                    Some(flags) => flags,
+                    None => {
+                        return self
+                            .error(token.column, format!("unknown jump flags in '{}'", jump))
+                    }
+                };
+                Rc::new(JMPF::new(flags, self.label()?))
+            }
+            _ => {
+                return self.error(
+                    token.column,
+                    format!("unknown instruction {}", token.kind.describe()),
+                )
+            }
+        };
+
+        Ok(instruction)
+    }
+
+    fn two_registers(&mut self) -> Result<(Register, Register), Error> {
+        let a = self.register()?;
+        self.expect(TokenKind::Comma)?;
+        let b = self.register()?;
+        Ok((a, b))
+    }
+
+    fn register(&mut self) -> Result<Register, Error> {
+        let token = self.next("register")?;
+        if let TokenKind::Word(w) = &token.kind {
+            match w.to_uppercase().as_str() {
+                "R0" => return Ok(Register::REG0),
+                "R1" => return Ok(Register::REG1),
+                "R2" => return Ok(Register::REG2),
+                "R3" => return Ok(Register::REG3),
+                _ => {}
+            }
+        }
+        self.error(
+            token.column,
+            format!("expected register R0-R3, found {}", token.kind.describe()),
+        )
+    }
+
+    // R3 is scratch for the stack and arithmetic pseudo instructions
+    fn scratch_register(&mut self) -> Result<Register, Error> {
+        let column = self.peek().map(|t| t.column);
+        match self.register()? {
+            Register::REG3 => self.error(
+                column.unwrap(),
+                "R3 is scratch for this instruction, use R0-R2".to_string(),
+            ),
+            register => Ok(register),
+        }
+    }
+
+    fn io_mode(&mut self) -> Result<IOMode, Error> {
+        let token = self.next("io mode")?;
+        if let TokenKind::Word(w) = &token.kind {
+            match w.to_uppercase().as_str() {
+                "DATA" => return Ok(IOMode::DataMode),
+                "ADDR" => return Ok(IOMode::AddressMode),
+                _ => {}
+            }
+        }
+        self.error(
+            token.column,
+            format!(
+                "expected io mode Data or Addr, found {}",
+                token.kind.describe()
+            ),
+        )
+    }
+
+    fn label(&mut self) -> Result<Label, Error> {
+        let token = self.next("label")?;
+        match &token.kind {
+            TokenKind::Word(w) if parse_number(w).is_none() => Ok(Label::new(w)),
+            kind => self.error(
+                token.column,
+                format!("expected label, found {}", kind.describe()),
+            ),
+        }
+    }
+
+    // constant expressions are checked here, where an overflow still has
+    // a location
+    fn expression(&mut self) -> Result<Expression, Error> {
+        let column = self.peek().map_or(self.end_column, |t| t.column);
+        let expression = Expression::at(self.binary(1)?, self.location(column));
+        if expression.node.is_constant() {
+            expression.evaluate(None)?;
+        }
+        Ok(expression)
+    }
+
+    // an expression that may not use labels or symbols, for the directives
+    // whose size has to be known before anything is placed
+    fn constant(&mut self) -> Result<u16, Error> {
+        let expression = self.expression()?;
+        if !expression.node.is_constant() {
+            return Err(Error::Parse(
+                expression.location.clone().unwrap(),
+                format!("'{}' must be a constant", expression),
+            ));
+        }
+        expression.evaluate(None)
+    }
+
+    // operators of this precedence and above, left to right
+    fn binary(&mut self, precedence: u8) -> Result<Node, Error> {
+        if precedence > Operator::Mul.precedence() {
+            return self.unary();
+        }
+
+        let mut node = self.binary(precedence + 1)?;
+        while let Some(operator) = self
+            .peek()
+            .and_then(|t| binary_operator(&t.kind))
+            .filter(|o| o.precedence() == precedence)
+        {
+            self.position += 1;
+            node = Node::binary(operator, node, self.binary(precedence + 1)?);
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, Error> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Operator(o)) if o == "-" || o == "~" => {
+                let negate = o == "-";
+                self.position += 1;
+                let node = Box::new(self.unary()?);
+                Ok(if negate {
+                    Node::Negate(node)
+                } else {
+                    Node::Not(node)
+                })
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, Error> {
+        let token = self.next("number")?;
+        match &token.kind {
+            TokenKind::Word(w) if w.starts_with(|c: char| c.is_ascii_digit()) => {
+                match parse_number(w) {
+                    Some(value) if value > u16::MAX as u32 => self.error(
+                        token.column,
+                        format!("number {} does not fit in 16 bits", value),
+                    ),
+                    Some(value) => Ok(Node::Number(value as u16)),
+                    None if anonymous_reference(w).is_some() => Ok(Node::Label(w.clone())),
+                    None => self.error(token.column, format!("invalid number '{}'", w)),
+                }
+            }
+            TokenKind::Word(w) if self.peek().map(|t| &t.kind) == Some(&open()) => {
+                let function = match w.to_lowercase().as_str() {
+                    "hi" => Function::Hi,
+                    "lo" => Function::Lo,
+                    _ => return self.error(token.column, format!("unknown function '{}'", w)),
+                };
+                self.position += 1;
+                let node = self.binary(1)?;
+                self.expect(close())?;
+                Ok(Node::Call(function, Box::new(node)))
+            }
+            TokenKind::Word(w) => Ok(Node::Label(w.clone())),
+            TokenKind::Symbol(name) => Ok(Node::Symbol(name.clone())),
+            TokenKind::Char(c) => Ok(Node::Char(*c)),
+            kind if *kind == open() => {
+                let node = self.binary(1)?;
+                self.expect(close())?;
+                Ok(node)
+            }
+            kind => self.error(
+                token.column,
+                format!("expected number, found {}", kind.describe()),
+            ),
+        }
+    }
+}
+
+fn open() -> TokenKind {
+    TokenKind::Operator("(".to_string())
+}
+
+fn close() -> TokenKind {
+    TokenKind::Operator(")".to_string())
+}
+
+fn binary_operator(kind: &TokenKind) -> Option<Operator> {
+    match kind {
+        TokenKind::Operator(o) => match o.as_str() {
+            "+" => Some(Operator::Add),
+            "-" => Some(Operator::Sub),
+            "*" => Some(Operator::Mul),
+            "/" => Some(Operator::Div),
+            "<<" => Some(Operator::Shl),
+            ">>" => Some(Operator::Shr),
+            "&" => Some(Operator::And),
+            "|" => Some(Operator::Or),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// flags may be written in any order but JMPF expects them as C A E Z
+fn jump_flags(flags: &str) -> Option<Vec<String>> {
+    if flags.is_empty() {
+        return None;
+    }
+
+    let mut result = Vec::new();
+    for flag in ["C", "A", "E", "Z"] {
+        match flags.matches(flag).count() {
+            0 => {}
+            1 => result.push(flag.to_string()),
+            _ => return None,
+        }
+    }
+
+    if result.join("").len() != flags.len() {
+        return None;
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::generator::get_instructions;
+    use crate::instructions::Severity;
+    use crate::USER_CODE_START;
+
+    #[test]
+    fn test_parser_round_trip() {
+        for program in ["ascii", "brush", "text_writer", "me"] {
+            let instructions = get_instructions(program).unwrap();
+            let parsed = AsmParser::new()
+                .parse(program, &instructions.to_string())
+                .unwrap();
+
+            assert_eq!(instructions.to_string(), parsed.to_string());
+            assert_eq!(
+                Assembler::new()
+                    .process(USER_CODE_START, Some(instructions))
+                    .unwrap(),
+                Assembler::new()
+                    .process(USER_CODE_START, Some(parsed))
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_label_before_call() {
+        // a label emits no words, so it must not move the address CALL
+        // returns to
+        let assemble = |source: &str| {
+            let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap()
+        };
+
+        assert_eq!(
+            assemble("CLF\nCALL routine\nroutine:\nJMP routine"),
+            assemble("CLF\nback:\nCALL routine\nroutine:\nJMP routine")
+        );
+    }
+
+    #[test]
+    fn test_parser_syntax() {
+        let source = "
+            %LINE-WIDTH = 0x1E  ; symbol
+            start: CLF          // label and instruction on one line
+                data r1, %LINE-WIDTH
+                DATA R2, 42
+                IN Addr, R3
+                OUT Data, R0
+                JMPZEC start
+                CALL start
+                push r1
+                POP R2
+                RET
+                hpush R3
+                HPOP R0
+                HCALL R2
+                HRET
+                SETSP R1
+                GETSP R3
+                setiv R2
+                EI
+                DI
+                INT
+                IRET
+                halt
+                mov R0, R1
+                SUB R1, R2
+                NEG R0
+                INC R1
+                DEC R2
+                MUL R0, R1
+                DIV R2, R0
+                CMPI R1, %LINE-WIDTH + 1
+            .data
+            .code
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let lines: Vec<String> = instructions
+            .instructions
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "%LINE-WIDTH = 0x1E",
+                "start",
+                "CLF",
+                "DATA R1, %LINE-WIDTH",
+                "DATA R2, 0x002A",
+                "IN Addr, R3",
+                "OUT Data, R0",
+                "JMPCEZ start",
+                "CALL start",
+                "PUSH R1",
+                "POP R2",
+                "RET",
+                "HPUSH R3",
+                "HPOP R0",
+                "HCALL R2",
+                "HRET",
+                "SETSP R1",
+                "GETSP R3",
+                "SETIV R2",
+                "EI",
+                "DI",
+                "INT",
+                "IRET",
+                "HALT",
+                "MOV R0, R1",
+                "SUB R1, R2",
+                "NEG R0",
+                "INC R1",
+                "DEC R2",
+                "MUL R0, R1",
+                "DIV R2, R0",
+                "CMPI R1, %LINE-WIDTH + 0x0001",
+                ".data",
+                ".code",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_errors() {
+        let cases = vec![
+            (
+                "LD R0, R4",
+                "test.asm:1:8: expected register R0-R3, found 'R4'",
+            ),
+            ("  MOVE R0, R1", "test.asm:1:3: unknown instruction 'MOVE'"),
+            ("\nDATA R0", "test.asm:2:8: expected ','"),
+            (
+                "DATA R0, 0x10000",
+                "test.asm:1:10: number 65536 does not fit in 16 bits",
+            ),
+            ("JMPX start", "test.asm:1:1: unknown jump flags in 'JMPX'"),
+            ("CLF R0", "test.asm:1:5: unexpected 'R0' at end of line"),
+            ("%ONE = ", "test.asm:1:8: expected number"),
+            ("ADD R0 $ R1", "test.asm:1:8: unexpected character '$'"),
+            (
+                "PUSH R3",
+                "test.asm:1:6: R3 is scratch for this instruction, use R0-R2",
+            ),
+            (
+                "MUL R0, R3",
+                "test.asm:1:9: R3 is scratch for this instruction, use R0-R2",
+            ),
+        ];
+
+        for (source, message) in cases {
+            match AsmParser::new().parse("test.asm", source) {
+                Err(e) => assert_eq!(e.to_string(), message),
+                Ok(_) => panic!("expected error for '{}'", source),
+            }
+        }
+    }
+
+    fn lines(instructions: &Instructions) -> Vec<String> {
+        instructions
+            .instructions
+            .iter()
+            .map(|i| i.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parser_macros() {
+        let source = r"
+            .macro select reg, addr
+            wait:
+                DATA \reg, \addr
+                OUT Addr, \reg
+                JMPZ wait
+            .endm
+            .macro twice reg
+                select \reg, 0x0007
+                select \reg, 0x000F
+            .endm
+        start: twice R1
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        assert_eq!(
+            lines(&instructions),
+            vec![
+                "start",
+                ".select.2.wait",
+                "DATA R1, 0x0007",
+                "OUT Addr, R1",
+                "JMPZ .select.2.wait",
+                ".select.3.wait",
+                "DATA R1, 0x000F",
+                "OUT Addr, R1",
+                "JMPZ .select.3.wait",
+            ]
+        );
+
+        let cases = vec![
+            (
+                ".macro m a\n  LD \\a, R5\n.endm\nm R0",
+                "test.asm:2:10: expected register R0-R3, found 'R5' \
+                 (in macro 'm' expanded at test.asm:4:1)",
+            ),
+            (
+                ".macro m a\n  CLF\n.endm\n  m",
+                "test.asm:4:3: macro 'm' takes 1 argument, found 0",
+            ),
+            (
+                ".macro m a, b\n  CLF\n.endm\n  m R0",
+                "test.asm:4:3: macro 'm' takes 2 arguments, found 1",
+            ),
+            (
+                ".macro m\n  JR \\b\n.endm",
+                "test.asm:2:6: unknown macro parameter '\\b'",
+            ),
+            ("JR \\r", "test.asm:1:4: unknown macro parameter '\\r'"),
+            (".macro m\n  CLF", "test.asm:1:1: macro 'm' has no .endm"),
+            (".endm", "test.asm:1:1: .endm without .macro"),
+            (
+                ".macro m\n  m\n.endm\nm",
+                "test.asm:2:3: macro 'm' expands itself too deeply",
+            ),
+        ];
+        for (source, message) in cases {
+            match AsmParser::new().parse("test.asm", source) {
+                Err(e) => assert!(
+                    e.to_string().starts_with(message),
+                    "'{}' does not start with '{}'",
+                    e,
+                    message
+                ),
+                Ok(_) => panic!("expected error for '{}'", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parser_include() {
+        // a directory of its own, runs at the same time must not share files
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "test_parser_include_{}_{}",
+            std::process::id(),
+            nanos
+        ));
+        let lib = dir.join("lib");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(
+            dir.join("main.asm"),
+            ".include \"select.asm\"\nselect R2\n.include \"bad.asm\"\n",
+        )
+        .unwrap();
+        fs::write(
+            lib.join("select.asm"),
+            ".macro select r\n  OUT Addr, \\r\n.endm\n",
+        )
+        .unwrap();
+        fs::write(lib.join("bad.asm"), "CLF\nJR R7\n").unwrap();
+        fs::write(dir.join("a.asm"), ".include \"b.asm\"\n").unwrap();
+        fs::write(dir.join("b.asm"), "CLF\n.include \"a.asm\"\n").unwrap();
+
+        let main = dir.join("main.asm");
+        let err = AsmParser::new().parse_file(&main).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:1:10: cannot find 'select.asm' in the include path",
+                main.display()
+            )
+        );
+
+        let mut parser = AsmParser::new();
+        parser.add_include_path(&lib);
+        let err = parser.parse_file(&main).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:2:4: expected register R0-R3, found 'R7' (included from {}:3:10)",
+                lib.join("bad.asm").display(),
+                main.display()
+            )
+        );
+
+        fs::write(lib.join("bad.asm"), "CLF\n").unwrap();
+        let instructions = parser.parse_file(&main).unwrap();
+        assert_eq!(lines(&instructions), vec!["OUT Addr, R2", "CLF"]);
+
+        let err = AsmParser::new()
+            .parse_file(&dir.join("a.asm"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("include cycle"));
+        assert!(err.to_string().contains("a.asm -> "));
+
+        // a name given to parse is not read, so a file of that name is no cycle
+        let select = lib.join("select.asm").display().to_string();
+        let instructions = AsmParser::new()
+            .parse(&select, ".include \"select.asm\"\nselect R1\n")
+            .unwrap();
+        assert_eq!(lines(&instructions), vec!["OUT Addr, R1"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parser_expressions() {
+        let source = "
+            %BASE = 0xFF00
+            %LAST = %BASE + %COUNT - 1  ; symbols may be defined later
+            %COUNT = 8
+        start:
+            DATA R0, (%BASE | 0x23) & ~0x0F
+            DATA R1, 'A' << 3 + 2
+            DATA R2, hi(end) + lo(0x1234)
+            DATA R3, -1
+        end:
+            DATA R0, %LAST
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        assert_eq!(
+            lines(&instructions)[..6],
+            [
+                "%BASE = 0xFF00",
+                "%LAST = %BASE + %COUNT - 0x0001",
+                "%COUNT = 0x8",
+                "start",
+                "DATA R0, (%BASE | 0x0023) & ~0x000F",
+                "DATA R1, 'A' << 0x0003 + 0x0002",
+            ]
+        );
+        assert_eq!(
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap(),
+            vec![0x0020, 0xFF20, 0x0021, 0x0820, 0x0022, 0x0039, 0x0023, 0xFFFF, 0x0020, 0xFF07]
+        );
+
+        let cases = vec![
+            (
+                "DATA R0, 0x8000 * 2",
+                "test.asm:1:10: '0x8000 * 0x0002' = 65536 does not fit in 16 bits",
+            ),
+            ("DATA R0, (1 + 2", "test.asm:1:16: expected ')'"),
+            (
+                "DATA R0, 'AB'",
+                "test.asm:1:10: unterminated character literal",
+            ),
+            ("DATA R0, foo(1)", "test.asm:1:10: unknown function 'foo'"),
+            (
+                "DATA R0, 1 / 0",
+                "test.asm:1:10: division by zero in '0x0001 / 0x0000'",
+            ),
+            ("%A = 1 +", "test.asm:1:9: expected number"),
+        ];
+        for (source, message) in cases {
+            match AsmParser::new().parse("test.asm", source) {
+                Err(e) => assert_eq!(e.to_string(), message),
+                Ok(_) => panic!("expected error for '{}'", source),
+            }
+        }
+
+        // names are only known when assembling, the error keeps the location
+        let instructions = AsmParser::new()
+            .parse("test.asm", "DATA R0, end + 0xFFFF\nend:")
+            .unwrap();
+        assert_eq!(
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap_err()
+                .to_string(),
+            "test.asm:1:10: error: 'end + 0xFFFF' = 66817 does not fit in 16 bits"
+        );
+    }
+
+    #[test]
+    fn test_parser_directives() {
+        let source = r#"
+        start:
+            .word 0x0001, start + 1, 'A'
+            .fill 3, 0x00FF
+            .ascii "hi"
+            .string "a\"\n"
+            .align 8
+            .org 0x0514
+        end: .word end
+        "#;
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        assert_eq!(
+            lines(&instructions)[1..7],
+            [
+                ".word 0x0001, start + 0x0001, 'A'",
+                ".fill 0x0003, 0x00FF",
+                ".ascii \"hi\"",
+                ".string \"a\\\"\\n\"",
+                ".align 0x0008",
+                ".org 0x0514",
+            ]
+        );
+        assert_eq!(
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions.clone()))
+                .unwrap(),
+            vec![
+                0x0001, 0x0501, 0x0041, // .word
+                0x00FF, 0x00FF, 0x00FF, // .fill
+                0x0068, 0x0069, // .ascii
+                0x0061, 0x0022, 0x000A, 0x0000, // .string
+                0x0000, 0x0000, 0x0000, 0x0000, // .align
+                0x0000, 0x0000, 0x0000, 0x0000, // .org
+                0x0514,
+            ]
+        );
+        assert_eq!(
+            Assembler::new()
+                .object(Some(instructions))
+                .unwrap_err()
+                .to_string(),
+            "test.asm:7:13: error: '.align 0x0008' needs an absolute address and cannot go in \
+             an object\n  help: assemble without --object to fix its address"
+        );
+
+        let instructions = AsmParser::new().parse("test.asm", ".org 0x0400").unwrap();
+        assert_eq!(
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap_err()
+                .to_string(),
+            "test.asm:1:1: error: '.org 0x0400' is behind the current address 0x0500\n  \
+             help: .org can only move forward, raise its address"
+        );
+
+        let cases = vec![
+            (".fill %N, 0", "test.asm:1:7: '%N' must be a constant"),
+            (".align 0", "test.asm:1:8: alignment must be at least 1"),
+            (
+                ".ascii 5",
+                "test.asm:1:8: expected string in quotes, found '5'",
+            ),
+            (".string \"a\\q\"", "test.asm:1:11: unknown escape '\\q'"),
+            (".word 1 2", "test.asm:1:9: expected ',', found '2'"),
+        ];
+        for (source, message) in cases {
+            match AsmParser::new().parse("test.asm", source) {
+                Err(e) => assert_eq!(e.to_string(), message),
+                Ok(_) => panic!("expected error for '{}'", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parser_local_labels() {
+        let source = "
+        .macro wait
+        .spin: JMPZ .spin
+        .endm
+        main:
+            DATA R0, 0x0001
+        .loop:
+            JMPZ .done
+            JMP .loop
+        .done:
+            JMP 1f
+        1:  JMP 1f
+        1:  JMP 1b
+        other:
+        .loop:
+            JMP .loop
+            DATA R1, main.loop + 1
+            wait
+            wait
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let mut assembler = Assembler::new();
+        assert_eq!(
+            assembler
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap(),
+            vec![
+                0x0020, 0x0001, 0x0051, 0x0506, 0x0040, 0x0502, 0x0040, 0x0508, 0x0040, 0x050A,
+                0x0040, 0x050A, 0x0040, 0x050C, 0x0021, 0x0503, 0x0051, 0x0510, 0x0051, 0x0512,
+            ]
+        );
+        for (name, address) in [
+            ("main.loop", 0x0502),
+            ("main.done", 0x0506),
+            ("other.loop", 0x050C),
+            ("other.wait.1.spin", 0x0510),
+            ("other.wait.2.spin", 0x0512),
+        ] {
+            assert_eq!(assembler.labels()[name], address);
+        }
+
+        // a label in a macro body does not end the caller's scope
+        let source = "
+        .macro wait r
+        w:  DATA \\r, 0x0001
+            JMPZ w
+        .endm
+        main:
+        .loop:
+            wait R1
+            JMP .loop
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let mut assembler = Assembler::new();
+        assert_eq!(
+            assembler
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap(),
+            vec![0x0021, 0x0001, 0x0051, 0x0500, 0x0040, 0x0500]
+        );
+        assert_eq!(assembler.labels()["main.wait.1.w"], 0x0500);
+
+        // before the first global label there is no scope in either pass
+        let instructions = AsmParser::new()
+            .parse("test.asm", "JMP .a\n.a:\nmain: JMP main")
+            .unwrap();
+        assert_eq!(
+            Assembler::new()
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap(),
+            vec![0x0040, 0x0502, 0x0040, 0x0502]
+        );
+
+        let cases = vec![
+            ("main:\n.a:\n.a:", "Label 'main.a' already exists"),
+            ("main:\nJMP .b", "Unknown label: main.b"),
+            ("1:\nJMP 1f", "Unknown label: 1f"),
+        ];
+        for (source, message) in cases {
+            let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+            match Assembler::new().process(USER_CODE_START, Some(instructions)) {
+                Err(e) => assert!(e.to_string().contains(message), "{}", e),
+                Ok(_) => panic!("expected error for '{}'", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parser_diagnostics() {
+        let source = [
+            "main:",
+            "    DATA R3, 0x0010",
+            "    CALL sub",
+            "    ADD R3, R0",
+            "    DATA R0, -1",
+            "    JMP main",
+            "    CLF",
+            "unused:",
+            "sub: RET",
+        ]
+        .join("\n");
+        let instructions = AsmParser::new().parse("test.asm", &source).unwrap();
+        let mut assembler = Assembler::new();
+        assert!(assembler
+            .process(USER_CODE_START, Some(instructions))
+            .is_ok());
+        let warnings: Vec<(usize, Severity)> = assembler
+            .diagnostics()
+            .iter()
+            .map(|d| (d.location.as_ref().unwrap().line, d.severity))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (3, Severity::Warning),
+                (5, Severity::Warning),
+                (7, Severity::Warning),
+                (8, Severity::Warning),
+            ]
+        );
+        assert_eq!(
+            assembler.diagnostics()[3].to_string(),
+            "test.asm:8:1: warning: label 'unused' is never used\n  \
+             help: remove it, or refer to it from an instruction"
+        );
+
+        // every error comes back at once
+        let instructions = AsmParser::new()
+            .parse("test.asm", "JMP nowhere\na: JMP a\na:\nDATA R0, %N")
+            .unwrap();
+        match Assembler::new().process(USER_CODE_START, Some(instructions)) {
+            Err(Error::Assembly(diagnostics)) => assert_eq!(
+                diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Severity::Error)
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "test.asm:1:1: error: Unknown label: nowhere\n  \
+                     help: define the label or check its spelling",
+                    "test.asm:3:1: error: Label 'a' already exists, all labels should be \
+                     unique\n  help: rename one of the labels, or use a .local label",
+                    "test.asm:4:1: error: Unknown symbol: N\n  \
+                     help: define the symbol with %NAME = value",
+                ]
+            ),
+            other => panic!("expected diagnostics, got {:?}", other.map(|_| ())),
+        }
+
+        let instructions = AsmParser::new().parse("test.asm", "CLF").unwrap();
+        let mut assembler = Assembler::new();
+        assembler
+            .process(0x0400, Some(instructions.clone()))
+            .unwrap();
+        assert!(assembler.diagnostics()[0]
+            .message
+            .contains("reserved area 0x0000-0x04FF"));
+        assembler.process(0xFEFE, Some(instructions)).unwrap();
+        assert!(assembler.diagnostics()[0]
+            .message
+            .contains("runs past 0xFEFE"));
+
+        assert!(matches!(
+            Assembler::new().process(USER_CODE_START, None),
+            Err(Error::NoInstructions)
+        ));
+    }
+}
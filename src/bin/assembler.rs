@@ -1,42 +1,144 @@
 use clap::Parser;
-use computer_simulator::{get_instructions, Assembler, USER_CODE_START};
-use std::{fs::File, io::prelude::Write, path::Path};
+use computer_simulator::{
+    get_instructions, intel_hex, logisim_image, parse_address, write_binary, AsmParser, Assembler,
+    USER_CODE_START,
+};
+use std::{fmt::Display, fs, path::Path};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short = 'p', long = "program")]
-    program_name: String,
+    #[arg(short = 'p', long = "program", required_unless_present = "source_file")]
+    program_name: Option<String>,
+
+    #[arg(short = 'f', long = "file", conflicts_with = "program_name")]
+    source_file: Option<String>,
+
+    // directories searched for .include files after the including file's own
+    #[arg(short = 'I', long = "include")]
+    include_paths: Vec<String>,
 
     #[arg(short = 'o', long = "output")]
     output_file_path: String,
 
-    #[arg(short = 'r', long, default_value_t = true)]
+    // the address the binary will be loaded at, labels resolve against it
+    #[arg(long, default_value_t = USER_CODE_START, value_parser = parse_address)]
+    origin: u16,
+
+    #[arg(short = 'r', long, default_value_t = true, action = clap::ArgAction::Set)]
     render: bool,
+
+    // what -r false writes: bin (raw little endian words), ihex (Intel HEX)
+    // or logisim (a v2.0 raw image for the Logisim RAM and ROM)
+    #[arg(long, default_value = "bin", value_parser = ["bin", "ihex", "logisim"])]
+    format: String,
+
+    // aligned listing of address, words and source
+    #[arg(long = "listing")]
+    listing_file_path: Option<String>,
+
+    // labels and symbols with their values
+    #[arg(long = "map")]
+    map_file_path: Option<String>,
+
+    // the listing and the map together as json, for tools
+    #[arg(long = "json")]
+    json_file_path: Option<String>,
+
+    // write a relocatable object file for the linker instead of a binary
+    #[arg(long)]
+    object: bool,
 }
 
 fn main() {
     let args: Args = Args::parse();
 
+    let instructions = match &args.source_file {
+        Some(path) => match parser(&args.include_paths).parse_file(Path::new(path)) {
+            Ok(instructions) => Some(instructions),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => get_instructions(args.program_name.as_ref().unwrap()),
+    };
+
+    if args.listing_file_path.is_some()
+        || args.map_file_path.is_some()
+        || args.json_file_path.is_some()
+    {
+        let listing = assemble(|a| a.listing(args.origin, instructions.clone()));
+        for (path, contents) in [
+            (&args.listing_file_path, listing.to_string()),
+            (&args.map_file_path, listing.map()),
+            (&args.json_file_path, listing.json()),
+        ] {
+            if let Some(path) = path {
+                fs::write(Path::new(path), contents).unwrap_or_else(|e| {
+                    eprintln!("{}: {}", path, e);
+                    std::process::exit(1);
+                });
+            }
+        }
+    }
+
+    if args.object {
+        let object = match Assembler::new().object(instructions) {
+            Ok(object) => object,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        object
+            .save(Path::new(&args.output_file_path))
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", args.output_file_path, e);
+                std::process::exit(1);
+            });
+        return;
+    }
+
     match args.render {
-        false => File::create(Path::new(&args.output_file_path))
-            .unwrap()
-            .write_all(to_u8_slice(
-                &mut Assembler::new()
-                    .process(USER_CODE_START, get_instructions(&args.program_name))
-                    .unwrap(),
-            ))
-            .unwrap(),
-        true => println!(
-            "{}",
-            Assembler::new()
-                .string(USER_CODE_START, get_instructions(&args.program_name),)
-                .unwrap()
-        ),
+        false => {
+            let path = Path::new(&args.output_file_path);
+            let bin = assemble(|a| a.process(args.origin, instructions));
+            match args.format.as_str() {
+                "ihex" => fs::write(path, intel_hex(args.origin, &bin)),
+                "logisim" => fs::write(path, logisim_image(args.origin, &bin)),
+                _ => write_binary(path, &bin),
+            }
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", args.output_file_path, e);
+                std::process::exit(1);
+            })
+        }
+        true => print!("{}", assemble(|a| a.string(args.origin, instructions))),
     }
 }
 
-fn to_u8_slice(slice: &mut [u16]) -> &mut [u8] {
-    let byte_len = 2 * slice.len();
-    unsafe { std::slice::from_raw_parts_mut(slice.as_mut_ptr().cast::<u8>(), byte_len) }
+// errors and warnings go to stderr, errors stop here
+fn assemble<T, E: Display>(f: impl FnOnce(&mut Assembler) -> Result<T, E>) -> T {
+    let mut assembler = Assembler::new();
+    match f(&mut assembler) {
+        Ok(result) => {
+            for warning in assembler.diagnostics() {
+                eprintln!("{}", warning);
+            }
+            result
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parser(include_paths: &[String]) -> AsmParser {
+    let mut parser = AsmParser::new();
+    for path in include_paths {
+        parser.add_include_path(Path::new(path));
+    }
+    parser
 }
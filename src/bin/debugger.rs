@@ -0,0 +1,89 @@
+use clap::Parser;
+use computer_simulator::{
+    get_instructions, AsmParser, Assembler, Computer, CoreKind, Debugger, USER_CODE_START,
+};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(short = 'p', long = "program", required_unless_present = "source_file")]
+    program_name: Option<String>,
+
+    #[arg(short = 'f', long = "file", conflicts_with = "program_name")]
+    source_file: Option<String>,
+
+    // directories searched for .include files after the including file's own
+    #[arg(short = 'I', long = "include")]
+    include_paths: Vec<String>,
+
+    // gate or behavioral
+    #[arg(long, default_value = "gate")]
+    core: CoreKind,
+}
+
+fn main() {
+    let args: Args = Args::parse();
+
+    let instructions = match &args.source_file {
+        Some(path) => match parser(&args.include_paths).parse_file(Path::new(path)) {
+            Ok(instructions) => Some(instructions),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => get_instructions(args.program_name.as_ref().unwrap()),
+    };
+
+    let mut assembler = Assembler::new();
+    let bin = match assembler.process(USER_CODE_START, instructions) {
+        Ok(bin) => bin,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let size = bin.len() as u16;
+
+    let mut computer = Computer::new_headless(args.core);
+    if let Err(e) = computer.load_to_ram(USER_CODE_START, bin) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    computer.boot();
+
+    let mut debugger = Debugger::new(computer, USER_CODE_START, size, assembler.labels().clone());
+
+    let mut last = String::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        // an empty line repeats the last command
+        if !line.trim().is_empty() {
+            last = line;
+        }
+
+        match debugger.execute(&last) {
+            Some(output) => print!("{}", output),
+            None => break,
+        }
+    }
+}
+
+fn parser(include_paths: &[String]) -> AsmParser {
+    let mut parser = AsmParser::new();
+    for path in include_paths {
+        parser.add_include_path(Path::new(path));
+    }
+    parser
+}
@@ -0,0 +1,31 @@
+use clap::Parser;
+use computer_simulator::{parse_address, read_binary, Disassembler, USER_CODE_START};
+use std::path::Path;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(short = 'i', long = "input")]
+    input_file_path: String,
+
+    #[arg(long, default_value_t = USER_CODE_START, value_parser = parse_address)]
+    origin: u16,
+
+    #[arg(short = 'r', long, default_value_t = true, action = clap::ArgAction::Set)]
+    render: bool,
+}
+
+fn main() {
+    let args: Args = Args::parse();
+
+    let words = read_binary(Path::new(&args.input_file_path)).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args.input_file_path, e);
+        std::process::exit(1);
+    });
+
+    let mut disassembler = Disassembler::new();
+    match args.render {
+        true => print!("{}", disassembler.listing(args.origin, &words)),
+        false => print!("{}", disassembler.disassemble(args.origin, &words)),
+    }
+}
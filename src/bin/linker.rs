@@ -0,0 +1,50 @@
+use clap::Parser;
+use computer_simulator::{parse_address, write_binary, Linker, Object, USER_CODE_START};
+use std::{fs, path::Path};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    // object files written by the assembler with --object, the first one
+    // holds the entry point
+    #[arg(required = true)]
+    objects: Vec<String>,
+
+    #[arg(short = 'o', long = "output")]
+    output_file_path: String,
+
+    // where sections and symbols were placed
+    #[arg(short = 'm', long = "map")]
+    map_file_path: Option<String>,
+
+    #[arg(long, default_value_t = USER_CODE_START, value_parser = parse_address)]
+    origin: u16,
+}
+
+fn main() {
+    let args: Args = Args::parse();
+
+    let mut linker = Linker::new();
+    for path in args.objects.iter() {
+        match Object::load(Path::new(path)) {
+            Ok(object) => linker.add(path, object),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let image = match linker.link(args.origin) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    write_binary(Path::new(&args.output_file_path), &image.words).unwrap();
+    if let Some(path) = &args.map_file_path {
+        fs::write(Path::new(path), image.map()).unwrap();
+    }
+}
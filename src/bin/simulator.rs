@@ -1,8 +1,11 @@
 use clap::Parser;
 use computer_simulator::{
-    get_instructions, glfw_run, Assembler, Computer, Keyboard, PrintStateConfig, USER_CODE_START,
+    get_instructions, glfw_run, is_intel_hex, is_logisim_image, parse_address, parse_intel_hex,
+    parse_logisim_image, words_from_bytes, Assembler, Computer, CoreKind, HeadlessConfig, Keyboard,
+    Linker, Lockstep, Object, PrintStateConfig, RunLimit, SerialHost, Snapshot, StopReason,
+    WatchKind, USER_CODE_START,
 };
-use std::sync::Arc;
+use std::{fs, path::Path, sync::Arc};
 use tokio::{
     sync::{mpsc, Notify},
     time::{interval, Duration},
@@ -11,46 +14,100 @@ use tokio::{
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short = 'p', long = "program")]
-    program_name: String,
+    #[arg(short = 'p', long = "program", required_unless_present = "binary_file")]
+    program_name: Option<String>,
+
+    // a raw binary written by the assembler with -r false or the linker, an
+    // Intel HEX or Logisim image, or an object file written with --object
+    #[arg(short = 'b', long = "bin", conflicts_with = "program_name")]
+    binary_file: Option<String>,
+
+    // where the program is loaded and started, it has to be the origin the
+    // binary was assembled for
+    #[arg(long, default_value_t = USER_CODE_START, value_parser = parse_address)]
+    origin: u16,
 
     #[arg(short = 's', long, default_value_t = true)]
     print_state: bool,
 
     #[arg(long, default_value_t = 7)]
     print_state_every: u16,
+
+    // gate or behavioral
+    #[arg(long, default_value = "gate")]
+    core: CoreKind,
+
+    #[arg(long)]
+    headless: bool,
+
+    // run the gate-level CPU against the reference model instead
+    #[arg(long, conflicts_with = "headless")]
+    lockstep: bool,
+
+    #[arg(long, default_value_t = 100_000)]
+    cycles: u64,
+
+    #[arg(long, value_parser = parse_address)]
+    halt_at: Option<u16>,
+
+    // stop before the instruction at this address
+    #[arg(long = "break", value_parser = parse_address)]
+    breakpoints: Vec<u16>,
+
+    // stop after a store to this address
+    #[arg(long = "watch", value_parser = parse_address)]
+    watchpoints: Vec<u16>,
+
+    // continue from a snapshot instead of booting the program
+    #[arg(long, requires = "headless")]
+    restore: Option<String>,
+
+    // write a snapshot once the headless run stops
+    #[arg(long, requires = "headless")]
+    save: Option<String>,
+
+    // start:length, e.g. 0x0600:16
+    #[arg(long, value_parser = parse_dump)]
+    dump: Vec<(u16, u16)>,
+
+    // the serial console host side, stdio or the path of a Unix socket
+    #[arg(long)]
+    serial: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
     let args: Args = Args::parse();
 
+    if args.headless {
+        run_headless(args);
+        return;
+    }
+    if args.lockstep {
+        run_lockstep(args);
+        return;
+    }
+
     let (key_press_sender, key_press_receiver) = mpsc::channel(1);
     let (screen_sender, screen_receiver) = mpsc::channel(1);
     let quit = Arc::new(Notify::new());
-    let mut computer = Computer::new(screen_sender, quit.clone());
+    let mut computer = Computer::new_with_core(screen_sender, quit.clone(), args.core);
     let mut key_board = Keyboard::new(key_press_receiver, quit.clone());
 
     computer.connect_keyboard(&mut key_board);
-
-    let bin = Assembler::new()
-        .process(
-            USER_CODE_START,
-            get_instructions(&args.program_name.to_string()),
-        )
-        .unwrap();
+    add_stops(&mut computer, &args);
+    connect_serial(&mut computer, &args);
 
     // Load bin
-    computer.load_to_ram(0x0500, bin);
+    load_to_ram(&mut computer, &args);
 
     tokio::spawn(async move {
         key_board.run().await;
     });
 
     tokio::spawn(async move {
-        computer
+        let stop = computer
             .run(
-                computer.screen_control.clone(),
                 interval(Duration::from_nanos(1000)),
                 PrintStateConfig {
                     print_state: args.print_state,
@@ -58,8 +115,161 @@ async fn main() {
                 },
             )
             .await;
+        println!("Stopped on {}\n{}", stop, computer);
     });
 
     glfw_run(screen_receiver, key_press_sender, quit.clone());
-    //BUG: Can not run
+}
+
+fn run_headless(args: Args) {
+    let mut computer = Computer::new_headless(args.core);
+
+    load_to_ram(&mut computer, &args);
+    add_stops(&mut computer, &args);
+    connect_serial(&mut computer, &args);
+
+    if let Some(path) = &args.restore {
+        match Snapshot::load(Path::new(path)) {
+            Ok(snapshot) => computer.restore(&snapshot),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let report = computer.run_headless(HeadlessConfig {
+        limit: RunLimit::Cycles(args.cycles),
+        halt_at: args.halt_at,
+        memory_dump: args.dump,
+    });
+    println!("{}", report);
+
+    if let Some(path) = &args.save {
+        if let Err(e) = computer
+            .snapshot()
+            .and_then(|snapshot| snapshot.save(Path::new(path)))
+        {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    // a program that ran HALT exits with the low byte of R0
+    if let Some(StopReason::Halt(status)) = report.stop {
+        std::process::exit((status & 0xFF) as i32);
+    }
+}
+
+fn load_to_ram(computer: &mut Computer, args: &Args) {
+    if let Err(e) = computer.load_to_ram(args.origin, load_program(args)) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    computer.set_entry(args.origin);
+}
+
+// the binary file as it is, an object file linked on its own, or the
+// built-in program assembled at the origin
+fn load_program(args: &Args) -> Vec<u16> {
+    let program = match &args.binary_file {
+        Some(path) => load_binary(path, args.origin),
+        None => Assembler::new()
+            .process(
+                args.origin,
+                get_instructions(args.program_name.as_ref().unwrap()),
+            )
+            .map_err(|e| e.to_string()),
+    };
+    program.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// link errors already name the file
+fn load_binary(path: &str, origin: u16) -> Result<Vec<u16>, String> {
+    let bytes = fs::read(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+    if is_intel_hex(&bytes) || is_logisim_image(&bytes) {
+        let text = String::from_utf8_lossy(&bytes);
+        let image = match is_intel_hex(&bytes) {
+            true => parse_intel_hex(&text),
+            false => parse_logisim_image(&text),
+        };
+        let (start, words) = image.map_err(|e| format!("{}: {}", path, e))?;
+        return from_origin(start, words, origin).map_err(|e| format!("{}: {}", path, e));
+    }
+    if !Object::is_object(&bytes) {
+        return words_from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e));
+    }
+
+    let object = Object::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    let mut linker = Linker::new();
+    linker.add(path, object);
+    Ok(linker.link(origin).map_err(|e| e.to_string())?.words)
+}
+
+// images carry their own addresses, a Logisim image starts at 0, keep what is
+// at and after origin
+fn from_origin(start: u16, words: Vec<u16>, origin: u16) -> Result<Vec<u16>, String> {
+    if start > origin {
+        return Err(format!(
+            "image starts at 0x{:04X}, after the origin 0x{:04X}",
+            start, origin
+        ));
+    }
+
+    let skip = ((origin - start) as usize).min(words.len());
+    if words[..skip].iter().any(|w| *w != 0) {
+        return Err(format!("image has data below the origin 0x{:04X}", origin));
+    }
+    Ok(words[skip..].to_vec())
+}
+
+fn add_stops(computer: &mut Computer, args: &Args) {
+    for address in args.breakpoints.iter() {
+        computer.add_breakpoint(*address);
+    }
+    for address in args.watchpoints.iter() {
+        computer.add_watchpoint(*address, WatchKind::Write);
+    }
+}
+
+fn connect_serial(computer: &mut Computer, args: &Args) {
+    let host = match args.serial.as_deref() {
+        None => return,
+        Some("stdio") => SerialHost::stdio(),
+        #[cfg(not(unix))]
+        Some(path) => {
+            eprintln!("{}: Unix sockets need a Unix host", path);
+            std::process::exit(1);
+        }
+        #[cfg(unix)]
+        Some(path) => match SerialHost::unix_socket(Path::new(path)) {
+            Ok(host) => host,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+    };
+    computer.connect_serial(host);
+}
+
+fn run_lockstep(args: Args) {
+    let mut lockstep = Lockstep::new(args.origin, &load_program(&args));
+    match lockstep.run(args.cycles) {
+        Ok(()) => println!("{} cycles in lockstep", lockstep.cycles()),
+        Err(divergence) => {
+            eprintln!("{}", divergence);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_dump(s: &str) -> Result<(u16, u16), String> {
+    let (start, len) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected start:length, found '{}'", s))?;
+    Ok((parse_address(start)?, parse_address(len)?))
 }
@@ -13,6 +13,7 @@ mod gaters;
 mod gates;
 mod iobus;
 mod register;
+mod stackpointer;
 mod stepper;
 mod storage;
 
@@ -25,6 +26,7 @@ pub use gaters::{ANDer, NOTer, ORer, XORer};
 pub use gates::{ANDGate3, ANDGate4, ANDGate5, ANDGate8, ORGate3, ORGate4, ORGate5, ORGate6};
 pub use iobus::{IOBus, Mode};
 pub use register::Register;
+pub use stackpointer::StackPointer;
 pub use stepper::Stepper;
 pub use storage::{Bit, Word};
 
@@ -0,0 +1,178 @@
+use super::{
+    Adder, Bus, Component, Enableable, Enabler, Register, Settable, Updatable, Wire, BUS_WIDTH,
+};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+// StackPointer - a register that can put its value, its value + 1 or its
+// value - 1 on the bus, so a push or pop moves it without going through the ALU
+#[derive(Clone)]
+pub struct StackPointer {
+    pub register: Register,
+    pub increment: Wire,
+    pub decrement: Wire,
+    incrementer: Adder,
+    decrementer: Adder,
+    increment_enabler: Enabler,
+    decrement_enabler: Enabler,
+    bus: Arc<Mutex<Bus>>,
+}
+
+impl StackPointer {
+    pub fn new(bus: Arc<Mutex<Bus>>) -> Self {
+        let mut res = Self {
+            register: Register::new("SP", bus.clone(), bus.clone()),
+            increment: Wire::new("I".to_string(), false),
+            decrement: Wire::new("D".to_string(), false),
+            incrementer: Adder::new(),
+            decrementer: Adder::new(),
+            increment_enabler: Enabler::new(),
+            decrement_enabler: Enabler::new(),
+            bus,
+        };
+
+        // SP + 0 with the carry in set, and SP + 0xFFFF
+        for i in BUS_WIDTH..BUS_WIDTH * 2 {
+            res.decrementer.set_input_wire(i, true);
+        }
+
+        res
+    }
+
+    pub fn value(&self) -> u16 {
+        self.register.value()
+    }
+
+    fn drive(&self, enabler: &Enabler) {
+        let mut bus = self.bus.lock().unwrap();
+        for i in 0..BUS_WIDTH {
+            bus.set_input_wire(i, enabler.get_output_wire(i));
+        }
+    }
+}
+
+impl Display for StackPointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} I: {} D: {}",
+            self.register,
+            self.increment.get() as i32,
+            self.decrement.get() as i32
+        )
+    }
+}
+
+impl Enableable for StackPointer {
+    fn enable(&mut self) {
+        self.register.enable()
+    }
+
+    fn disable(&mut self) {
+        self.register.disable()
+    }
+}
+
+impl Settable for StackPointer {
+    fn set(&mut self) {
+        self.register.set()
+    }
+
+    fn unset(&mut self) {
+        self.register.unset()
+    }
+}
+
+impl Updatable for StackPointer {
+    fn update(&mut self) {
+        self.register.update();
+
+        for i in 0..BUS_WIDTH {
+            self.incrementer.set_input_wire(i, self.register.bit(i));
+            self.decrementer.set_input_wire(i, self.register.bit(i));
+        }
+        self.incrementer.update(true);
+        self.decrementer.update(false);
+
+        for i in 0..BUS_WIDTH {
+            self.increment_enabler
+                .set_input_wire(i, self.incrementer.get_output_wire(i));
+            self.decrement_enabler
+                .set_input_wire(i, self.decrementer.get_output_wire(i));
+        }
+        self.increment_enabler.update(self.increment.get());
+        self.decrement_enabler.update(self.decrement.get());
+
+        if self.increment.get() {
+            self.drive(&self.increment_enabler);
+        }
+        if self.decrement.get() {
+            self.drive(&self.decrement_enabler);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(sp: &mut StackPointer, bus: &Arc<Mutex<Bus>>, value: u16) {
+        bus.lock().unwrap().set_value(value);
+        sp.set();
+        sp.update();
+        sp.unset();
+        sp.update();
+    }
+
+    #[test]
+    fn test_stack_pointer_increment_decrement() {
+        let bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let mut sp = StackPointer::new(bus.clone());
+
+        for (value, incremented, decremented) in [
+            (0x0500, 0x0501, 0x04FF),
+            (0x0000, 0x0001, 0xFFFF),
+            (0xFFFF, 0x0000, 0xFFFE),
+        ] {
+            load(&mut sp, &bus, value);
+            assert_eq!(sp.value(), value);
+
+            bus.lock().unwrap().set_value(0);
+            sp.increment.update(true);
+            sp.update();
+            sp.increment.update(false);
+            assert_eq!(bus.lock().unwrap().get_value(), incremented);
+
+            bus.lock().unwrap().set_value(0);
+            sp.decrement.update(true);
+            sp.update();
+            sp.decrement.update(false);
+            assert_eq!(bus.lock().unwrap().get_value(), decremented);
+        }
+    }
+
+    #[test]
+    fn test_stack_pointer_set_from_own_output() {
+        let bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let mut sp = StackPointer::new(bus.clone());
+        load(&mut sp, &bus, 0x0500);
+
+        // enable and set happen on separate updates, like the CPU clock
+        sp.decrement.update(true);
+        sp.update();
+        sp.decrement.update(false);
+        sp.set();
+        sp.update();
+        sp.unset();
+        sp.update();
+        assert_eq!(sp.value(), 0x04FF);
+
+        bus.lock().unwrap().set_value(0);
+        sp.enable();
+        sp.update();
+        sp.disable();
+        assert_eq!(bus.lock().unwrap().get_value(), 0x04FF);
+    }
+}
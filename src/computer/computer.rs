@@ -1,67 +1,238 @@
 use super::{
-    components::{Bus, Settable, Updatable, BUS_WIDTH},
-    cpu::CPU,
-    io::{DisplayAdapter, Keyboard, KeyboardAdapter, ScreenControl},
+    components::{Bus, BUS_WIDTH},
+    cpu::{BehavioralCPU, Core, CoreKind, CpuState, CPU},
+    io::{
+        DisplayAdapter, InterruptController, Keyboard, KeyboardAdapter, ScreenControl, SerialHost,
+        Timer, Uart, TIMER_PERIOD,
+    },
     memory::Memory64K,
+    snapshot::{Snapshot, SnapshotError},
 };
-use std::sync::{Arc, Mutex};
+use crate::instructions::{HARDWARE_STACK_TOP, STACK_POINTER, STACK_TOP};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
 use tokio::{
     sync::{mpsc, Notify},
     time::Interval,
 };
 
 const CODE_REGION_START: u16 = 0x0500;
+// boot keeps the two words after it for the jump back to the start
+const CODE_REGION_END: u16 = 0xFEFD;
+
+#[derive(Debug, Error)]
+#[error("{len} words at 0x{origin:04X} do not fit the user code region 0x0500 - 0xFEFD")]
+pub struct LoadError {
+    pub origin: u16,
+    pub len: usize,
+}
 
 pub struct PrintStateConfig {
     pub print_state: bool,
     pub print_state_every: u16,
 }
 
+// a cycle is one full fetch-decode-execute, six steps of the stepper
+pub enum RunLimit {
+    Cycles(u64),
+    Steps(u64),
+}
+
+pub struct HeadlessConfig {
+    pub limit: RunLimit,
+    pub halt_at: Option<u16>,
+    pub memory_dump: Vec<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+// StopReason - why execution paused, breakpoints are checked at instruction
+// boundaries and watchpoints after the step that touched memory. Halt carries
+// the exit status, R0 when HALT ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    Read { address: u16, value: u16 },
+    Write { address: u16, old: u16, new: u16 },
+    Halt(u16),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Breakpoint(address) => write!(f, "breakpoint at {:>#06X}", address),
+            StopReason::Read { address, value } => {
+                write!(f, "read {:>#06X} from {:>#06X}", value, address)
+            }
+            StopReason::Write { address, old, new } => write!(
+                f,
+                "write {:>#06X} to {:>#06X}, was {:>#06X}",
+                new, address, old
+            ),
+            StopReason::Halt(status) => write!(f, "HALT, exit status {:>#06X}", status),
+        }
+    }
+}
+
+pub struct RunReport {
+    pub cycles: u64,
+    pub steps: u64,
+    pub halted: bool,
+    pub stop: Option<StopReason>,
+    pub state: CpuState,
+    pub memory: Vec<(u16, Vec<u16>)>,
+}
+
+impl Display for RunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Cycle count = {}, step count = {}, halted = {}",
+            self.cycles, self.steps, self.halted
+        )?;
+        if let Some(stop) = self.stop {
+            writeln!(f, "Stopped on {}", stop)?;
+        }
+        write!(f, "{}", self.state)?;
+
+        for (start, values) in self.memory.iter() {
+            for (i, chunk) in values.chunks(8).enumerate() {
+                let words: Vec<String> = chunk.iter().map(|v| format!("{:>#06X}", v)).collect();
+                write!(
+                    f,
+                    "\n{:>#06X}: {}",
+                    start.wrapping_add(i as u16 * 8),
+                    words.join(" ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct Computer {
-    main_bus: Arc<Mutex<Bus>>,
-    cpu: CPU,
-    memory: Arc<Mutex<Memory64K>>,
+    cpu: Box<dyn Core>,
+    core: CoreKind,
+    // stepper ticks since power on, six to an instruction
+    steps: u64,
+    // where boot points IAR, the start of the code region unless a program
+    // was loaded somewhere else
+    entry: u16,
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<(u16, WatchKind)>,
     display_adapter: Arc<Mutex<DisplayAdapter>>,
-    pub screen_control: ScreenControl,
-    keyboard_adapter: KeyboardAdapter,
-    screen_channel: mpsc::Sender<[[u8; 240]; 160]>,
-    quit: Arc<Notify>,
+    // None for a computer without a screen window
+    pub screen_control: Option<ScreenControl>,
+    keyboard_adapter: Arc<Mutex<KeyboardAdapter>>,
+    timer: Arc<Mutex<Timer>>,
+    serial: Arc<Mutex<Uart>>,
+    interrupt_controller: Arc<Mutex<InterruptController>>,
 }
 
 impl Computer {
     pub fn new(screen_channel: mpsc::Sender<[[u8; 240]; 160]>, quit: Arc<Notify>) -> Self {
-        let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
-        let memory = Arc::new(Mutex::new(Memory64K::new(main_bus.clone())));
-        let display_adapter = Arc::new(Mutex::new(DisplayAdapter::new()));
-        let mut res = Self {
-            main_bus: main_bus.clone(),
-            cpu: CPU::new(main_bus.clone(), memory.clone()),
-            memory: memory.clone(),
-            display_adapter: display_adapter.clone(),
-            screen_control: ScreenControl::new(
-                display_adapter.clone(),
-                screen_channel.clone(),
-                quit.clone(),
-            ),
-            keyboard_adapter: KeyboardAdapter::new(),
+        Self::new_with_core(screen_channel, quit, CoreKind::Gate)
+    }
+
+    pub fn new_with_core(
+        screen_channel: mpsc::Sender<[[u8; 240]; 160]>,
+        quit: Arc<Notify>,
+        core: CoreKind,
+    ) -> Self {
+        let mut res = Self::new_headless(core);
+        res.screen_control = Some(ScreenControl::new(
+            res.display_adapter.clone(),
             screen_channel,
             quit,
+        ));
+        res
+    }
+
+    // a computer without a screen window, for scripted and CI runs
+    pub fn new_headless(core: CoreKind) -> Self {
+        let display_adapter = Arc::new(Mutex::new(DisplayAdapter::new()));
+        let keyboard_adapter = Arc::new(Mutex::new(KeyboardAdapter::new()));
+        let timer = Arc::new(Mutex::new(Timer::new(TIMER_PERIOD)));
+        // the serial line stays in memory until connect_serial
+        let serial = Arc::new(Mutex::new(Uart::new(SerialHost::memory())));
+
+        // request lines IRQ_TIMER, IRQ_KEYBOARD and IRQ_SERIAL
+        let mut interrupt_controller = InterruptController::new();
+        interrupt_controller.add_source(timer.clone());
+        interrupt_controller.add_source(keyboard_adapter.clone());
+        interrupt_controller.add_source(serial.clone());
+
+        let mut res = Self {
+            cpu: Self::new_core(core),
+            core,
+            steps: 0,
+            entry: CODE_REGION_START,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            display_adapter,
+            screen_control: None,
+            keyboard_adapter,
+            timer,
+            serial,
+            interrupt_controller: Arc::new(Mutex::new(interrupt_controller)),
         };
-        res.cpu.connect_peripheral(res.display_adapter.clone());
+        res.connect_peripherals();
         res
     }
 
-    pub fn connect_keyboard(&mut self, keyboard: &mut Keyboard) {
-        keyboard.connect(self.keyboard_adapter.keyboard_in_bus.clone());
+    // connecting resets the peripherals, the display adapter gets a blank
+    // display RAM
+    fn connect_peripherals(&mut self) {
+        self.cpu.connect_peripheral(self.display_adapter.clone());
+        self.cpu.connect_peripheral(self.keyboard_adapter.clone());
+        self.cpu.connect_peripheral(self.timer.clone());
+        self.cpu.connect_peripheral(self.serial.clone());
+        self.cpu
+            .connect_interrupt_controller(self.interrupt_controller.clone());
     }
 
-    pub fn load_to_ram(&mut self, offset: u16, values: Vec<u16>) {
-        if offset < 0x0500 {
-            panic!("0x0000 - 0x04FF is a reserved memory area");
+    fn new_core(core: CoreKind) -> Box<dyn Core> {
+        match core {
+            CoreKind::Gate => {
+                let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+                let memory = Arc::new(Mutex::new(Memory64K::new(main_bus.clone())));
+                Box::new(CPU::new(main_bus, memory))
+            }
+            CoreKind::Behavioral => Box::new(BehavioralCPU::new()),
         }
-        if offset > 0xFEFF {
-            panic!("0xFEFF - 0xFFFF is a reserved memory area");
+    }
+
+    pub fn connect_keyboard(&mut self, keyboard: &mut Keyboard) {
+        keyboard.connect(
+            self.keyboard_adapter
+                .lock()
+                .unwrap()
+                .keyboard_in_bus
+                .clone(),
+        );
+    }
+
+    pub fn connect_serial(&mut self, host: SerialHost) {
+        self.serial.lock().unwrap().attach(host);
+    }
+
+    pub fn load_to_ram(&mut self, offset: u16, values: Vec<u16>) -> Result<(), LoadError> {
+        if offset < CODE_REGION_START
+            || offset as usize + values.len() > CODE_REGION_END as usize + 1
+        {
+            return Err(LoadError {
+                origin: offset,
+                len: values.len(),
+            });
         }
         println!(
             "Loading {} words to RAM at offset 0x{:X}",
@@ -70,50 +241,227 @@ impl Computer {
         );
 
         for i in 0..values.len() {
-            self.put_value_in_ram(offset + i as u16, values[i]);
+            self.cpu.write_memory(offset + i as u16, values[i]);
+        }
+        Ok(())
+    }
+
+    pub fn set_entry(&mut self, address: u16) {
+        self.entry = address;
+    }
+
+    pub fn read_memory(&self, address: u16) -> u16 {
+        self.cpu.read_memory(address)
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
+    // one tick of the stepper, Some once a breakpoint or watchpoint triggers
+    // or the CPU has halted, a halted CPU is not stepped any more
+    pub fn step(&mut self) -> Option<StopReason> {
+        if let Some(status) = self.exit_status() {
+            return Some(StopReason::Halt(status));
+        }
+
+        let before: Vec<u16> = self
+            .watchpoints
+            .iter()
+            .map(|(address, _)| self.cpu.read_memory(*address))
+            .collect();
+
+        self.cpu.step();
+        self.steps += 1;
+
+        // drained every step so the logs stay empty once watchpoints are gone
+        if let Some(stop) = self.check_watchpoints(&before) {
+            return Some(stop);
+        }
+
+        if let Some(status) = self.exit_status() {
+            return Some(StopReason::Halt(status));
+        }
+        let iar = self.cpu.state().iar;
+        match self.at_instruction_start() && self.breakpoints.contains(&iar) {
+            true => Some(StopReason::Breakpoint(iar)),
+            false => None,
+        }
+    }
+
+    // R0 once HALT has finished its cycle
+    pub fn exit_status(&self) -> Option<u16> {
+        if !self.at_instruction_start() {
+            return None;
+        }
+        let state = self.cpu.state();
+        match state.halted {
+            true => Some(state.registers[0]),
+            false => None,
+        }
+    }
+
+    fn check_watchpoints(&mut self, before: &[u16]) -> Option<StopReason> {
+        let reads = self.cpu.take_reads();
+        let writes = self.cpu.take_writes();
+
+        for (i, (address, kind)) in self.watchpoints.iter().enumerate() {
+            if *kind != WatchKind::Read {
+                if let Some((_, new)) = writes.iter().find(|(a, _)| a == address) {
+                    return Some(StopReason::Write {
+                        address: *address,
+                        old: before[i],
+                        new: *new,
+                    });
+                }
+            }
+            if *kind != WatchKind::Write {
+                if let Some((_, value)) = reads.iter().find(|(a, _)| a == address) {
+                    return Some(StopReason::Read {
+                        address: *address,
+                        value: *value,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // the machine state between two instructions
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError> {
+        if !self.at_instruction_start() {
+            return Err(SnapshotError::MidInstruction(self.steps % 6 + 1));
+        }
+
+        Ok(Snapshot {
+            steps: self.steps,
+            state: self.cpu.state(),
+            memory: (0..=0xFFFF).map(|a| self.cpu.read_memory(a)).collect(),
+            display: self.display_adapter.lock().unwrap().state(),
+            keyboard: self.keyboard_adapter.lock().unwrap().state(),
+            interrupts: self.interrupt_controller.lock().unwrap().state(),
+            timer: self.timer.lock().unwrap().state(),
+            serial: self.serial.lock().unwrap().state(),
+        })
+    }
+
+    // power on a new core and load the snapshot into it, breakpoints and
+    // watchpoints stay as they are
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu = Self::new_core(self.core);
+        self.connect_peripherals();
+
+        for (address, value) in snapshot.memory.iter().enumerate() {
+            if self.cpu.read_memory(address as u16) != *value {
+                self.cpu.write_memory(address as u16, *value);
+            }
+        }
+        self.cpu.set_state(&snapshot.state);
+        self.display_adapter
+            .lock()
+            .unwrap()
+            .set_state(&snapshot.display);
+        self.keyboard_adapter
+            .lock()
+            .unwrap()
+            .set_state(&snapshot.keyboard);
+        self.interrupt_controller
+            .lock()
+            .unwrap()
+            .set_state(&snapshot.interrupts);
+        self.timer.lock().unwrap().set_state(&snapshot.timer);
+        self.serial.lock().unwrap().set_state(&snapshot.serial);
+        self.steps = snapshot.steps;
+
+        if !self.watchpoints.is_empty() {
+            self.cpu.log_accesses();
         }
     }
 
-    fn put_value_in_ram(&mut self, address: u16, value: u16) {
-        self.memory.lock().unwrap().address_register.set();
-        self.main_bus.lock().unwrap().set_value(address);
-        self.memory.lock().unwrap().update();
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
 
-        self.memory.lock().unwrap().address_register.unset();
-        self.memory.lock().unwrap().update();
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| *b != address);
+        self.breakpoints.len() != len
+    }
 
-        self.main_bus.lock().unwrap().set_value(value);
-        self.memory.lock().unwrap().set();
-        self.memory.lock().unwrap().update();
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
 
-        self.memory.lock().unwrap().address_register.unset();
-        self.memory.lock().unwrap().update();
+    pub fn add_watchpoint(&mut self, address: u16, kind: WatchKind) {
+        if self.watchpoints.is_empty() {
+            self.cpu.log_accesses();
+        }
+        self.watchpoints.retain(|(a, _)| *a != address);
+        self.watchpoints.push((address, kind));
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|(a, _)| *a != address);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[(u16, WatchKind)] {
+        &self.watchpoints
+    }
+
+    // stepper ticks since power on
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn at_instruction_start(&self) -> bool {
+        self.steps.is_multiple_of(6)
+    }
+
+    pub fn set_iar(&mut self, address: u16) {
+        self.cpu.set_iar(address);
+    }
+
+    pub fn set_register(&mut self, register: usize, value: u16) {
+        self.cpu.set_register(register, value);
     }
 
     pub async fn run(
         &mut self,
-        mut screen_control: ScreenControl,
         mut tick_interval: Interval,
         print_state_config: PrintStateConfig,
-    ) {
+    ) -> StopReason {
         println!("Starting computer....");
-        self.put_value_in_ram(0xFEFE, 0x0040); //JMP back to code region start if IAR reaches the end
-        self.put_value_in_ram(0xFEFF, CODE_REGION_START);
-
-        // start at offet of user code
-        self.cpu.set_iar(CODE_REGION_START);
+        // a restored computer carries on from its snapshot
+        if self.steps == 0 {
+            self.boot();
+        }
 
-        {
+        if let Some(mut screen_control) = self.screen_control.clone() {
             tokio::spawn(async move {
                 screen_control.run().await;
             });
         }
 
+        self.resume(&mut tick_interval, &print_state_config).await
+    }
+
+    // keep running after run returned on a breakpoint or watchpoint
+    pub async fn resume(
+        &mut self,
+        tick_interval: &mut Interval,
+        print_state_config: &PrintStateConfig,
+    ) -> StopReason {
         let mut steps = 0;
         loop {
             tick_interval.tick().await;
 
-            self.cpu.step();
+            if let Some(stop) = self.step() {
+                return stop;
+            }
 
             if print_state_config.print_state {
                 if steps % print_state_config.print_state_every == 0 {
@@ -134,4 +482,784 @@ impl Computer {
             steps += 1;
         }
     }
+
+    pub fn run_headless(&mut self, config: HeadlessConfig) -> RunReport {
+        if self.steps == 0 {
+            self.boot();
+        }
+
+        let max_steps = match config.limit {
+            RunLimit::Cycles(cycles) => cycles * 6,
+            RunLimit::Steps(steps) => steps,
+        };
+
+        let mut steps = 0;
+        let mut halted = false;
+        let mut stop = None;
+        while steps < max_steps {
+            // a CPU that is already halted does not step
+            let before = self.steps;
+            stop = self.step();
+            steps += self.steps - before;
+            if stop.is_some() {
+                halted = matches!(stop, Some(StopReason::Halt(_)));
+                break;
+            }
+
+            if steps % 6 == 0 && Some(self.cpu.state().iar) == config.halt_at {
+                halted = true;
+                break;
+            }
+        }
+
+        RunReport {
+            cycles: steps / 6,
+            steps,
+            halted,
+            stop,
+            state: self.cpu.state(),
+            memory: config
+                .memory_dump
+                .iter()
+                .map(|(start, len)| {
+                    (
+                        *start,
+                        (0..*len)
+                            .map(|i| self.read_memory(start.wrapping_add(i)))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn boot(&mut self) {
+        self.cpu.write_memory(0xFEFE, 0x0040); //JMP back to code region start if IAR reaches the end
+        self.cpu.write_memory(0xFEFF, CODE_REGION_START);
+        self.cpu.write_memory(STACK_POINTER, STACK_TOP); // empty stack for PUSH and CALL
+        self.cpu.set_stack_pointer(HARDWARE_STACK_TOP); // and for HPUSH and HCALL
+
+        // start at offet of user code
+        self.cpu.set_iar(self.entry);
+    }
+}
+
+impl Display for Computer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsmParser, Assembler, USER_CODE_START};
+
+    fn get_computer(source: &str, core: CoreKind) -> Computer {
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let bin = Assembler::new()
+            .process(USER_CODE_START, Some(instructions))
+            .unwrap();
+
+        let mut computer = Computer::new_headless(core);
+        computer.load_to_ram(USER_CODE_START, bin).unwrap();
+        computer
+    }
+
+    #[test]
+    fn test_computer_run_headless() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            run_headless_program(core);
+        }
+    }
+
+    fn run_headless_program(core: CoreKind) {
+        let mut computer = get_computer(
+            "
+            DATA R0, 0x0005
+            DATA R1, 0x0003
+            CLF
+            ADD R0, R1
+            DATA R2, 0x0600
+            ST R2, R1
+        done:
+            JMP done
+        ",
+            core,
+        );
+        assert!(computer.screen_control.is_none());
+
+        let report = computer.run_headless(HeadlessConfig {
+            limit: RunLimit::Cycles(100),
+            halt_at: Some(0x0509),
+            memory_dump: vec![(0x0600, 2)],
+        });
+
+        assert!(report.halted);
+        assert_eq!(report.cycles, 6);
+        assert_eq!(report.steps, 36);
+        // registers power up with all bits set
+        assert_eq!(report.state.registers, [0x0005, 0x0008, 0x0600, 0xFFFF]);
+        assert_eq!(report.state.iar, 0x0509);
+        // memory powers up with all bits set too, so the untouched 0x0601
+        // holds 0xFFFF. peek used to report 0x0000 for cells that were never
+        // updated, while LD from the same cell returned 0xFFFF
+        assert_eq!(report.memory, vec![(0x0600, vec![0x0008, 0xFFFF])]);
+    }
+
+    #[test]
+    fn test_computer_load_to_ram_bounds() {
+        let mut computer = Computer::new_headless(CoreKind::Behavioral);
+
+        assert!(computer.load_to_ram(0x04FF, vec![0x0060]).is_err());
+        assert!(computer.load_to_ram(0xFEFE, vec![0x0060]).is_err());
+        // an image that runs past the region, or past 0xFFFF
+        assert!(computer.load_to_ram(0xFE00, vec![0; 0x100]).is_err());
+        assert!(computer.load_to_ram(0xF000, vec![0; 0x20000]).is_err());
+
+        computer.load_to_ram(0xFE00, vec![0x1234; 0xFE]).unwrap();
+        assert_eq!(computer.read_memory(0xFEFD), 0x1234);
+    }
+
+    #[test]
+    fn test_computer_entry() {
+        let instructions = AsmParser::new()
+            .parse(
+                "test.asm",
+                "
+            DATA R0, 0x0005
+        done:
+            JMP done
+        ",
+            )
+            .unwrap();
+        let bin = Assembler::new()
+            .process(0x1000, Some(instructions))
+            .unwrap();
+
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = Computer::new_headless(core);
+            computer.load_to_ram(0x1000, bin.clone()).unwrap();
+            computer.set_entry(0x1000);
+
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(10),
+                halt_at: Some(0x1002),
+                memory_dump: vec![],
+            });
+            assert!(report.halted);
+            assert_eq!(report.state.registers[0], 0x0005);
+        }
+    }
+
+    #[test]
+    fn test_computer_halt() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = get_computer(
+                "
+                DATA R0, 0x0102
+                HALT
+                DATA R0, 0x0003
+            ",
+                core,
+            );
+
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(100),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert!(report.halted);
+            assert_eq!(report.stop, Some(StopReason::Halt(0x0102)));
+            assert_eq!(report.cycles, 2);
+            assert!(report.state.halted);
+            assert_eq!(report.state.iar, 0x0503);
+
+            // it stays halted, through a snapshot as well
+            let snapshot = computer.snapshot().unwrap();
+            computer.restore(&snapshot);
+            assert_eq!(computer.step(), Some(StopReason::Halt(0x0102)));
+            assert_eq!(computer.steps(), 12);
+            assert_eq!(computer.cpu_state(), report.state);
+        }
+    }
+
+    #[test]
+    fn test_computer_timer() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = get_computer(
+                "
+                DATA R1, 0x0011
+                OUT Addr, R1
+                DATA R1, 0x0005
+                OUT Data, R1
+                DATA R1, 0x0012
+                OUT Addr, R1
+                DATA R1, 0x0001
+                OUT Data, R1
+                DATA R1, 0x0011
+                OUT Addr, R1
+                IN Data, R0
+                HALT
+            ",
+                core,
+            );
+
+            // three cycles start between loading the mode and reading the count
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(100),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert_eq!(report.stop, Some(StopReason::Halt(0x0002)));
+        }
+    }
+
+    #[test]
+    fn test_computer_serial_echo() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = get_computer(
+                "
+                DATA R1, 0x0014
+                OUT Addr, R1
+            wait:
+                IN Data, R0
+                DATA R2, 0x0001
+                AND R2, R0
+                JMPZ wait
+                DATA R1, 0x0013
+                OUT Addr, R1
+                IN Data, R0
+                OUT Data, R0
+                HALT
+            ",
+                core,
+            );
+            let host = SerialHost::memory();
+            computer.connect_serial(host.clone());
+
+            // nothing arrives for a while, the program polls the status
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(20),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert_eq!(report.stop, None);
+
+            host.send(b"A");
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(100),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert_eq!(report.stop, Some(StopReason::Halt(b'A' as u16)));
+            assert_eq!(host.take_sent(), b"A");
+        }
+    }
+
+    // saves what it changes on the hardware stack and counts the interrupts
+    // at 0x0600, as INTERRUPTS in cpu asks of a handler
+    const HANDLER: &str = "
+        handler:
+            HPUSH R0
+            HPUSH R1
+            HPUSH R2
+            HPUSH R3
+            DATA R1, 0x0010
+            OUT Addr, R1
+            IN Data, R0
+            DATA R1, 0x0600
+            LD R1, R2
+            INC R2
+            ST R1, R2
+            HPOP R3
+            HPOP R2
+            HPOP R1
+            HPOP R0
+            IRET
+        ";
+
+    #[test]
+    fn test_computer_interrupted_expansions() {
+        // the timer interrupts the loop all over the software stack and
+        // MUL and DIV expansions, every round still gets 42 / 5 = 8
+        let source = format!(
+            "
+            DATA R0, 0x0011
+            OUT Addr, R0
+            DATA R0, 0x002F
+            OUT Data, R0
+            DATA R0, 0x0012
+            OUT Addr, R0
+            DATA R0, 0x0003
+            OUT Data, R0
+            DATA R0, 0x0010
+            OUT Addr, R0
+            DATA R0, 0x0001
+            OUT Data, R0
+            DATA R0, handler
+            SETIV R0
+            DATA R0, 0x0600
+            XOR R1, R1
+            ST R0, R1
+            DATA R2, 0x0008
+            EI
+        loop:
+            PUSH R2
+            DATA R0, 0x0007
+            DATA R1, 0x0006
+            CALL product
+            DATA R0, 0x0005
+            DIV R0, R1
+            POP R2
+            CMPI R1, 0x0008
+            JMPE .next
+            HALT
+        .next:
+            DEC R2
+            CMPI R2, 0x0000
+            JMPE done
+            JMP loop
+        done:
+            DI
+            DATA R0, 0x0000
+            HALT
+
+        product:
+            PUSH R2
+            MUL R0, R1
+            POP R2
+            RET
+        {}",
+            HANDLER
+        );
+        let instructions = AsmParser::new().parse("test.asm", &source).unwrap();
+        let mut assembler = Assembler::new();
+        let listing = assembler
+            .listing(USER_CODE_START, Some(instructions))
+            .unwrap();
+        let handler = assembler.labels()["handler"];
+        let bin: Vec<u16> = listing
+            .lines
+            .iter()
+            .flat_map(|line| line.words.clone())
+            .collect();
+        let expansions: Vec<(&str, u16, u16)> = listing
+            .lines
+            .iter()
+            .filter(|line| line.words.len() > 2)
+            .map(|line| {
+                let mnemonic = line.source.split(' ').next().unwrap();
+                (
+                    mnemonic,
+                    line.address,
+                    line.address + line.words.len() as u16,
+                )
+            })
+            .collect();
+
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = Computer::new_headless(core);
+            computer.load_to_ram(USER_CODE_START, bin.clone()).unwrap();
+            computer.boot();
+
+            // where each interrupt came in, INT leaves it on top of the
+            // hardware stack
+            let mut interrupted = Vec::new();
+            let mut stop = None;
+            for _ in 0..200_000 {
+                stop = computer.step();
+                if stop.is_some() {
+                    break;
+                }
+                let state = computer.cpu_state();
+                if computer.at_instruction_start() && state.iar == handler {
+                    interrupted.push(computer.read_memory(state.sp));
+                }
+            }
+
+            assert_eq!(stop, Some(StopReason::Halt(0x0000)));
+            assert_eq!(computer.read_memory(STACK_POINTER), STACK_TOP);
+            assert_eq!(computer.read_memory(0x0600) as usize, interrupted.len());
+            for mnemonic in ["PUSH", "POP", "CALL", "RET", "MUL", "DIV"] {
+                assert!(
+                    interrupted.iter().any(|address| expansions
+                        .iter()
+                        .any(|(m, start, end)| *m == mnemonic && start < address && address < end)),
+                    "no interrupt inside {}",
+                    mnemonic
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_computer_keyboard_interrupt() {
+        // the program waits with interrupts on, the handler reads the key
+        let source = format!(
+            "
+            DATA R0, 0x0010
+            OUT Addr, R0
+            DATA R0, 0x0002
+            OUT Data, R0
+            DATA R0, handler
+            SETIV R0
+            DATA R0, 0x0600
+            XOR R1, R1
+            ST R0, R1
+            EI
+        wait:
+            DATA R0, 0x0600
+            LD R0, R1
+            CMPI R1, 0x0000
+            JMPE wait
+            DI
+            DATA R0, 0x0601
+            LD R0, R0
+            HALT
+        {}",
+            HANDLER.replace(
+                "DATA R1, 0x0600",
+                "DATA R1, 0x000F
+            OUT Addr, R1
+            IN Data, R2
+            DATA R1, 0x0601
+            ST R1, R2
+            DATA R1, 0x0600"
+            )
+        );
+
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = get_computer(&source, core);
+
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(200),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert_eq!(report.stop, None);
+            assert_eq!(computer.read_memory(0x0600), 0x0000);
+
+            computer
+                .keyboard_adapter
+                .lock()
+                .unwrap()
+                .keyboard_in_bus
+                .lock()
+                .unwrap()
+                .set_value(b'A' as u16);
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(200),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert_eq!(report.stop, Some(StopReason::Halt(b'A' as u16)));
+            // one key, one interrupt
+            assert_eq!(computer.read_memory(0x0600), 0x0001);
+            assert_eq!(computer.keyboard_adapter.lock().unwrap().state().pending, 0);
+        }
+    }
+
+    #[test]
+    fn test_computer_run_headless_limit() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            run_headless_limit(core);
+        }
+    }
+
+    fn run_headless_limit(core: CoreKind) {
+        let mut computer = get_computer(
+            "
+            DATA R0, 0x0005
+            DATA R1, 0x0003
+            CLF
+        ",
+            core,
+        );
+
+        let report = computer.run_headless(HeadlessConfig {
+            limit: RunLimit::Steps(15),
+            halt_at: None,
+            memory_dump: vec![],
+        });
+
+        assert!(!report.halted);
+        assert_eq!(report.cycles, 2);
+        assert_eq!(report.steps, 15);
+        assert_eq!(report.state.registers, [0x0005, 0x0003, 0xFFFF, 0xFFFF]);
+        assert_eq!(computer.read_memory(0xFEFE), 0x0040);
+        assert_eq!(computer.read_memory(0xFEFF), CODE_REGION_START);
+    }
+
+    #[test]
+    fn test_computer_stack() {
+        // recursion and nested calls, registers other than R3 survive
+        let source = "
+            DATA R0, 0x0004
+            DATA R1, 0x0000
+            CALL sum
+            PUSH R1
+            CALL outer
+            POP R2
+        done:
+            JMP done
+
+        ; R1 += R0 + (R0 - 1) + ... + 1
+        sum:
+            AND R0, R0
+            JMPZ .end
+            CLF
+            ADD R0, R1
+            PUSH R0
+            DATA R2, 0xFFFF
+            CLF
+            ADD R2, R0
+            CALL sum
+            POP R0
+        .end:
+            RET
+
+        outer:
+            CALL inner
+            RET
+        inner:
+            DATA R1, 0x0007
+            RET
+        ";
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let mut assembler = Assembler::new();
+        let bin = assembler
+            .process(USER_CODE_START, Some(instructions))
+            .unwrap();
+
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = Computer::new_headless(core);
+            computer.load_to_ram(USER_CODE_START, bin.clone()).unwrap();
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(2000),
+                halt_at: Some(assembler.labels()["done"]),
+                memory_dump: vec![],
+            });
+
+            assert!(report.halted);
+            assert_eq!(report.state.registers[..3], [0x0004, 0x0007, 0x000A]);
+            assert_eq!(computer.read_memory(STACK_POINTER), STACK_TOP);
+        }
+    }
+
+    #[test]
+    fn test_computer_arithmetic() {
+        // each program leaves its results in R0 - R3
+        let cases = [
+            (
+                "DATA R0, 0x0005
+                 DATA R1, 0x0003
+                 SUB R0, R1
+                 MOV R1, R2
+                 DATA R3, 0x0009
+                 SUB R0, R3",
+                [0x0005, 0xFFFE, 0xFFFE, 0x0004],
+            ),
+            (
+                "DATA R0, 0x0005
+                 NEG R0
+                 DATA R1, 0xFFFF
+                 INC R1
+                 DATA R2, 0x0000
+                 DEC R2
+                 DATA R3, 0x0001
+                 DEC R3",
+                [0xFFFB, 0x0000, 0xFFFF, 0x0000],
+            ),
+            (
+                "DATA R0, 0x0013
+                 DATA R1, 0x0021
+                 DATA R2, 0x1234
+                 MUL R0, R1",
+                [0x0013, 0x0273, 0x1234, 0x0273],
+            ),
+            (
+                "DATA R0, 0x03E8
+                 DATA R1, 0x0007
+                 DATA R2, 0x5555
+                 DIV R1, R0",
+                [0x008E, 0x0007, 0x5555, 0x0006],
+            ),
+            (
+                "DATA R1, 0x0000
+                 DATA R2, 0xFFFF
+                 DIV R1, R2",
+                [0xFFFF, 0x0000, 0xFFFF, 0xFFFF],
+            ),
+            (
+                "DATA R0, 0x0010
+                 DATA R1, 0x0000
+                 CMPI R0, 0x0010
+                 JMPE .equal
+                 JMP done
+             .equal:
+                 INC R1",
+                [0x0010, 0x0001, 0xFFFF, 0x0010],
+            ),
+        ];
+
+        for (program, registers) in cases {
+            let source = format!("{}\ndone: JMP done", program);
+            let instructions = AsmParser::new().parse("test.asm", &source).unwrap();
+            let mut assembler = Assembler::new();
+            let bin = assembler
+                .process(USER_CODE_START, Some(instructions))
+                .unwrap();
+
+            for core in [CoreKind::Gate, CoreKind::Behavioral] {
+                let mut computer = Computer::new_headless(core);
+                computer.load_to_ram(USER_CODE_START, bin.clone()).unwrap();
+                let report = computer.run_headless(HeadlessConfig {
+                    limit: RunLimit::Cycles(1000),
+                    halt_at: Some(assembler.labels()["done"]),
+                    memory_dump: vec![],
+                });
+
+                assert!(report.halted, "{}", program);
+                assert_eq!(report.state.registers, registers, "{}", program);
+            }
+        }
+    }
+
+    const STOP_PROGRAM: &str = "
+            DATA R0, 0x0600
+            DATA R1, 0x0007
+            ST R0, R1
+            LD R0, R2
+            DATA R3, 0x0001
+        done:
+            JMP done
+        ";
+
+    fn run_to_stop(computer: &mut Computer) -> Option<StopReason> {
+        (0..600).find_map(|_| computer.step())
+    }
+
+    #[test]
+    fn test_computer_breakpoints() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = get_computer(STOP_PROGRAM, core);
+            computer.add_breakpoint(0x0505);
+            computer.add_breakpoint(0x0508);
+            computer.add_breakpoint(0x0508);
+            assert_eq!(computer.breakpoints(), &[0x0505, 0x0508]);
+
+            let report = computer.run_headless(HeadlessConfig {
+                limit: RunLimit::Cycles(100),
+                halt_at: None,
+                memory_dump: vec![],
+            });
+            assert_eq!(report.stop, Some(StopReason::Breakpoint(0x0505)));
+            assert_eq!(report.steps, 18);
+            assert_eq!(report.state.iar, 0x0505);
+
+            // continuing steps off the breakpoint it stopped on
+            assert!(computer.remove_breakpoint(0x0508));
+            assert!(!computer.remove_breakpoint(0x0508));
+            assert_eq!(run_to_stop(&mut computer), None);
+            assert_eq!(computer.cpu_state().registers[3], 0x0001);
+        }
+    }
+
+    #[test]
+    fn test_computer_watchpoints() {
+        for core in [CoreKind::Gate, CoreKind::Behavioral] {
+            let mut computer = get_computer(STOP_PROGRAM, core);
+            computer.boot();
+            computer.add_watchpoint(0x0600, WatchKind::Write);
+            computer.add_watchpoint(0x0503, WatchKind::Read);
+
+            assert_eq!(
+                run_to_stop(&mut computer),
+                Some(StopReason::Read {
+                    address: 0x0503,
+                    value: 0x0007
+                })
+            );
+            assert_eq!(
+                run_to_stop(&mut computer),
+                Some(StopReason::Write {
+                    address: 0x0600,
+                    old: 0xFFFF,
+                    new: 0x0007
+                })
+            );
+            assert_eq!(computer.cpu_state().iar, 0x0505);
+
+            // a new kind replaces the old watchpoint on the same address
+            computer.add_watchpoint(0x0600, WatchKind::Access);
+            assert_eq!(
+                computer.watchpoints(),
+                &[(0x0503, WatchKind::Read), (0x0600, WatchKind::Access)]
+            );
+            assert_eq!(
+                run_to_stop(&mut computer),
+                Some(StopReason::Read {
+                    address: 0x0600,
+                    value: 0x0007
+                })
+            );
+            assert_eq!(computer.cpu_state().registers[2], 0x0007);
+
+            assert!(computer.remove_watchpoint(0x0600));
+            assert_eq!(run_to_stop(&mut computer), None);
+        }
+    }
+
+    #[test]
+    fn test_computer_snapshot() {
+        let source = "
+            DATA R0, 0x0007
+            OUT Addr, R0
+            DATA R1, 0x0010
+            DATA R2, 0x0001
+            DATA R3, 0x0600
+        loop:
+            OUT Data, R1
+            OUT Data, R2
+            CLF
+            SHL R2
+            ST R3, R2
+            ADD R2, R1
+            JMP loop
+        ";
+
+        for (from, to) in [
+            (CoreKind::Gate, CoreKind::Gate),
+            (CoreKind::Gate, CoreKind::Behavioral),
+            (CoreKind::Behavioral, CoreKind::Gate),
+        ] {
+            let mut original = get_computer(source, from);
+            original.boot();
+            for _ in 0..6 * 20 {
+                original.step();
+            }
+
+            let bytes = original.snapshot().unwrap().to_bytes();
+            let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+            assert_eq!(snapshot.steps, 120);
+            assert_ne!(snapshot.display.cells, vec![0xFFFF; 0x10000]);
+
+            let mut restored = Computer::new_headless(to);
+            restored.restore(&snapshot);
+            assert_eq!(restored.snapshot().unwrap(), snapshot);
+
+            for _ in 0..6 * 20 {
+                original.step();
+                restored.step();
+            }
+            assert_eq!(restored.snapshot().unwrap(), original.snapshot().unwrap());
+
+            original.step();
+            assert!(matches!(
+                original.snapshot(),
+                Err(SnapshotError::MidInstruction(2))
+            ));
+        }
+    }
 }
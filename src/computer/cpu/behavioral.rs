@@ -0,0 +1,572 @@
+use super::{Core, CpuState, Flags};
+use crate::computer::{
+    components::{Bus, Enableable, IOBus, Settable, BUS_WIDTH},
+    io::Peripheral,
+};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+const MEMORY_SIZE: usize = 0x10000;
+// what the gate fetch puts in IR when it takes an interrupt
+const INTERRUPT_INSTRUCTION: u16 = 0x0178;
+
+// BehavioralCPU - executes the same ISA as CPU one instruction at a time
+// instead of updating every gate. Registers, flags and memory match CPU at
+// every instruction boundary, including its power-on values and the flag
+// wires the ALU leaves untouched.
+#[derive(Clone)]
+pub struct BehavioralCPU {
+    registers: [u16; 4],
+    iar: u16,
+    ir: u16,
+    acc: u16,
+    tmp: u16,
+    sp: u16,
+    iv: u16,
+    flags: Flags,
+    saved_flags: Flags,
+    interrupts_enabled: bool,
+    halted: bool,
+
+    // position inside the six step fetch-decode-execute cycle
+    step: u8,
+    // the ALU keeps the carry of its last ADD, SHL or SHR on its output wire
+    alu_carry: bool,
+    memory: Vec<u16>,
+    // (address, value) of every store and load while logging is on
+    write_log: Option<Vec<(u16, u16)>>,
+    read_log: Option<Vec<(u16, u16)>>,
+
+    main_bus: Arc<Mutex<Bus>>,
+    io_bus: Arc<Mutex<IOBus>>,
+    peripherals: Vec<Arc<Mutex<dyn Peripheral>>>,
+    interrupt_controller: Option<Arc<Mutex<dyn Peripheral>>>,
+}
+
+impl Default for BehavioralCPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BehavioralCPU {
+    pub fn new() -> Self {
+        Self {
+            // like the gate latches, everything powers on with all bits set
+            registers: [0xFFFF; 4],
+            iar: 0xFFFF,
+            ir: 0xFFFF,
+            acc: 0xFFFF,
+            tmp: 0x0000,
+            sp: 0xFFFF,
+            iv: 0xFFFF,
+            flags: Flags::default(),
+            saved_flags: Flags::default(),
+            interrupts_enabled: false,
+            halted: false,
+            step: 0,
+            alu_carry: false,
+            memory: vec![0xFFFF; MEMORY_SIZE],
+            write_log: None,
+            read_log: None,
+            main_bus: Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+            io_bus: Arc::new(Mutex::new(IOBus::new())),
+            peripherals: Vec::new(),
+            interrupt_controller: None,
+        }
+    }
+
+    pub fn log_writes(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    fn load(&mut self, address: u16) -> u16 {
+        let value = self.memory[address as usize];
+        if let Some(log) = self.read_log.as_mut() {
+            log.push((address, value));
+        }
+        value
+    }
+
+    fn store(&mut self, address: u16, value: u16) {
+        self.memory[address as usize] = value;
+        if let Some(log) = self.write_log.as_mut() {
+            log.push((address, value));
+        }
+    }
+
+    fn execute(&mut self) {
+        for p in self.peripherals.iter() {
+            p.lock().unwrap().tick()
+        }
+        let request = match &self.interrupt_controller {
+            Some(controller) => controller.lock().unwrap().irq(),
+            None => false,
+        };
+
+        // fetch, an interrupt replaces the word at IAR with INT and leaves IAR
+        // pointing at the instruction it displaced
+        if self.interrupts_enabled && request {
+            self.ir = INTERRUPT_INSTRUCTION;
+            self.acc = self.add(self.iar, 1, false);
+        } else {
+            self.ir = self.load(self.iar);
+            self.acc = self.add(self.iar, 1, false);
+            self.iar = self.acc;
+        }
+
+        if self.ir & 0x0100 != 0 {
+            self.stack();
+            return;
+        }
+
+        let opcode = self.ir & 0x00FF;
+        let reg_a = ((opcode >> 2) & 0x3) as usize;
+        let reg_b = (opcode & 0x3) as usize;
+
+        match opcode {
+            // LD
+            0x00..=0x0F => self.registers[reg_b] = self.load(self.registers[reg_a]),
+            // ST
+            0x10..=0x1F => self.store(self.registers[reg_a], self.registers[reg_b]),
+            // DATA
+            0x20..=0x2F => {
+                self.registers[reg_b] = self.load(self.iar);
+                self.acc = self.add(self.iar, 1, false);
+                self.iar = self.acc;
+            }
+            // JR
+            0x30..=0x3F => self.iar = self.registers[reg_b],
+            // JMP
+            0x40..=0x4F => self.iar = self.load(self.iar),
+            // JMPF
+            0x50..=0x5F => {
+                let target = self.load(self.iar);
+                self.acc = self.add(self.iar, 1, false);
+                self.iar = self.acc;
+
+                let flags = self.flags;
+                if (opcode & 0x8 != 0 && flags.carry)
+                    || (opcode & 0x4 != 0 && flags.a_larger)
+                    || (opcode & 0x2 != 0 && flags.equal)
+                    || (opcode & 0x1 != 0 && flags.zero)
+                {
+                    self.iar = target;
+                }
+            }
+            // CLF, the ALU adds the bus one to an empty bus
+            0x60..=0x6F => {
+                self.add(0x0000, 0x0001, false);
+                self.flags = Flags {
+                    carry: self.alu_carry,
+                    a_larger: false,
+                    equal: false,
+                    zero: false,
+                };
+            }
+            // IN
+            0x70..=0x77 => self.registers[reg_b] = self.input(opcode & 0x4 != 0),
+            // OUT
+            0x78..=0x7F => self.output(opcode & 0x4 != 0, self.registers[reg_b]),
+            // ALU
+            _ => self.alu((opcode >> 4) & 0x7, reg_a, reg_b),
+        }
+    }
+
+    // the stack and interrupt instructions never reach the ALU, words with the
+    // ALU bit set as well do nothing apart from HALT
+    fn stack(&mut self) {
+        let opcode = self.ir & 0x00FF;
+        let reg_b = (opcode & 0x3) as usize;
+
+        match opcode {
+            // PUSH
+            0x00..=0x0F => {
+                self.sp = self.sp.wrapping_sub(1);
+                self.store(self.sp, self.registers[reg_b]);
+            }
+            // POP
+            0x10..=0x1F => {
+                self.registers[reg_b] = self.load(self.sp);
+                self.sp = self.sp.wrapping_add(1);
+            }
+            // CALL
+            0x20..=0x2F => {
+                self.sp = self.sp.wrapping_sub(1);
+                self.store(self.sp, self.iar);
+                self.iar = self.registers[reg_b];
+            }
+            // RET
+            0x30..=0x3F => {
+                self.iar = self.load(self.sp);
+                self.sp = self.sp.wrapping_add(1);
+            }
+            // SETSP
+            0x40..=0x4F => self.sp = self.registers[reg_b],
+            // GETSP
+            0x50..=0x5F => self.registers[reg_b] = self.sp,
+            // IRET
+            0x60..=0x63 => {
+                self.iar = self.load(self.sp);
+                self.flags = self.saved_flags;
+                self.interrupts_enabled = true;
+                self.sp = self.sp.wrapping_add(1);
+            }
+            // EI and DI
+            0x64..=0x6F => self.interrupts_enabled = opcode & 0x8 == 0,
+            // SETIV
+            0x70..=0x77 => self.iv = self.registers[reg_b],
+            // INT
+            0x78..=0x7F => {
+                self.sp = self.sp.wrapping_sub(1);
+                self.store(self.sp, self.iar);
+                self.saved_flags = self.flags;
+                self.interrupts_enabled = false;
+                self.iar = self.iv;
+            }
+            // HALT
+            0x80 => self.halted = true,
+            _ => {}
+        }
+    }
+
+    fn alu(&mut self, op: u16, reg_a: usize, reg_b: usize) {
+        let a = self.registers[reg_a];
+        let carry_in = self.flags.carry;
+
+        // TMP is set from register B, then the ALU idles on an ADD with an
+        // empty bus until the operation is decoded on the next step
+        self.tmp = self.registers[reg_b];
+        self.add(0x0000, self.tmp, false);
+        let b = self.tmp;
+
+        let result = match op {
+            0 => self.add(a, b, carry_in),
+            1 => {
+                self.alu_carry = a & 0x8000 != 0;
+                (a << 1) | carry_in as u16
+            }
+            2 => {
+                self.alu_carry = a & 0x0001 != 0;
+                (a >> 1) | ((carry_in as u16) << 15)
+            }
+            3 => !a,
+            4 => a & b,
+            5 => a | b,
+            6 => a ^ b,
+            _ => 0x0000,
+        };
+
+        self.acc = result;
+        self.flags = Flags {
+            carry: self.alu_carry,
+            a_larger: a > b,
+            equal: a == b,
+            // CMP drives every input of the zero detector high
+            zero: op != 7 && result == 0,
+        };
+
+        if op != 7 {
+            self.registers[reg_b] = result;
+        }
+    }
+
+    fn add(&mut self, a: u16, b: u16, carry_in: bool) -> u16 {
+        let (sum, carry1) = a.overflowing_add(b);
+        let (sum, carry2) = sum.overflowing_add(carry_in as u16);
+        self.alu_carry = carry1 || carry2;
+        sum
+    }
+
+    fn input(&mut self, address_mode: bool) -> u16 {
+        self.io_bus.lock().unwrap().update(false, address_mode);
+
+        self.io_bus.lock().unwrap().enable();
+        self.update_peripherals();
+        self.io_bus.lock().unwrap().disable();
+        self.update_peripherals();
+
+        let value = self.main_bus.lock().unwrap().get_value();
+        self.main_bus.lock().unwrap().set_value(0);
+        value
+    }
+
+    fn output(&mut self, address_mode: bool, value: u16) {
+        self.io_bus.lock().unwrap().update(true, address_mode);
+        self.main_bus.lock().unwrap().set_value(value);
+
+        self.io_bus.lock().unwrap().set();
+        self.update_peripherals();
+        self.io_bus.lock().unwrap().unset();
+        self.update_peripherals();
+
+        self.main_bus.lock().unwrap().set_value(0);
+        self.update_peripherals();
+    }
+
+    fn update_peripherals(&mut self) {
+        for p in self.peripherals.iter() {
+            p.lock().unwrap().update()
+        }
+    }
+}
+
+impl Core for BehavioralCPU {
+    fn step(&mut self) {
+        // the whole instruction runs on the first step of its cycle, a halted
+        // CPU starts no more cycles
+        if self.step == 0 && !self.halted {
+            self.execute();
+        }
+        self.step = (self.step + 1) % 6;
+    }
+
+    fn set_iar(&mut self, address: u16) {
+        self.iar = address;
+    }
+
+    fn set_stack_pointer(&mut self, value: u16) {
+        self.sp = value;
+    }
+
+    fn set_register(&mut self, register: usize, value: u16) {
+        self.registers[register] = value;
+    }
+
+    fn state(&self) -> CpuState {
+        CpuState {
+            registers: self.registers,
+            iar: self.iar,
+            ir: self.ir,
+            acc: self.acc,
+            tmp: self.tmp,
+            sp: self.sp,
+            iv: self.iv,
+            flags: self.flags,
+            saved_flags: self.saved_flags,
+            interrupts_enabled: self.interrupts_enabled,
+            halted: self.halted,
+        }
+    }
+
+    fn set_state(&mut self, state: &CpuState) {
+        self.registers = state.registers;
+        self.iar = state.iar;
+        self.ir = state.ir;
+        self.acc = state.acc;
+        self.tmp = state.tmp;
+        self.sp = state.sp;
+        self.iv = state.iv;
+        self.flags = state.flags;
+        self.saved_flags = state.saved_flags;
+        self.interrupts_enabled = state.interrupts_enabled;
+        self.halted = state.halted;
+        self.step = 0;
+    }
+
+    fn read_memory(&self, address: u16) -> u16 {
+        self.memory[address as usize]
+    }
+
+    fn write_memory(&mut self, address: u16, value: u16) {
+        self.memory[address as usize] = value;
+    }
+
+    fn log_accesses(&mut self) {
+        self.read_log = Some(Vec::new());
+        self.write_log = Some(Vec::new());
+    }
+
+    fn take_reads(&mut self) -> Vec<(u16, u16)> {
+        self.read_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn take_writes(&mut self) -> Vec<(u16, u16)> {
+        self.write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn connect_peripheral(&mut self, p: Arc<Mutex<dyn Peripheral>>) {
+        p.lock()
+            .unwrap()
+            .connect(self.io_bus.clone(), self.main_bus.clone());
+        self.peripherals.push(p);
+    }
+
+    fn connect_interrupt_controller(&mut self, p: Arc<Mutex<dyn Peripheral>>) {
+        self.connect_peripheral(p.clone());
+        self.interrupt_controller = Some(p);
+    }
+}
+
+impl Display for BehavioralCPU {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step: {}\n{}", self.step + 1, self.state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::{
+        cpu::CPU,
+        io::{InterruptController, Timer},
+        memory::Memory64K,
+    };
+
+    fn get_cpus(program: &[u16], start: u16) -> (CPU, BehavioralCPU) {
+        let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let memory = Arc::new(Mutex::new(Memory64K::new(main_bus.clone())));
+        let mut gate = CPU::new(main_bus, memory);
+        let mut behavioral = BehavioralCPU::new();
+
+        for (i, value) in program.iter().enumerate() {
+            gate.write_memory(start.wrapping_add(i as u16), *value);
+            behavioral.write_memory(start.wrapping_add(i as u16), *value);
+        }
+        gate.set_iar(start);
+        behavioral.set_iar(start);
+
+        (gate, behavioral)
+    }
+
+    fn run_lockstep(program: &[u16], start: u16, cycles: usize) {
+        let (mut gate, mut behavioral) = get_cpus(program, start);
+        compare_lockstep(&mut gate, &mut behavioral, cycles);
+    }
+
+    fn compare_lockstep(gate: &mut CPU, behavioral: &mut BehavioralCPU, cycles: usize) {
+        assert_eq!(gate.state(), behavioral.state());
+
+        for cycle in 0..cycles {
+            for _ in 0..6 {
+                gate.step();
+                behavioral.step();
+            }
+            assert_eq!(
+                gate.state(),
+                behavioral.state(),
+                "after cycle {}, IR {:#06X}",
+                cycle,
+                gate.state().ir
+            );
+        }
+
+        for address in 0..=0xFFFF {
+            assert_eq!(
+                gate.read_memory(address),
+                behavioral.read_memory(address),
+                "at {:#06X}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_behavioral_cpu_instructions() {
+        let mut program = vec![
+            0x0020, 0xFFFF, // DATA R0, 0xFFFF
+            0x0021, 0x0001, // DATA R1, 0x0001
+            0x0084, // ADD R1, R0 -> carry
+            0x0085, // ADD R1, R1 with carry in
+            0x0095, // SHL R1 with carry in
+            0x00A0, // SHR R0
+            0x00C1, // AND R0, R1
+            0x0060, // CLF
+            0x00F1, // CMP R0, R1
+            0x0052, 0x0510, // JMPE
+            0x00B5, // NOT R1
+            0x00D4, // OR R1, R0
+            0x00E5, // XOR R1, R1
+            0x0022, 0x0600, // DATA R2, 0x0600
+            0x0019, // ST R2, R1
+            0x0008, // LD R2, R0
+            0x0023, 0x0700, // DATA R3, 0x0700
+            0x0143, // SETSP R3
+            0x0101, // HPUSH R1
+            0x0152, // GETSP R2
+            0x0110, // HPOP R0
+            0x0181, // not an ADD, does nothing
+            0x0022, 0x0520, // DATA R2, 0x0520
+            0x0122, // HCALL R2
+            0x0040, 0x0523, // JMP
+            0x0153, // GETSP R3
+            0x0130, // HRET
+            0x0060, // CLF, skipped
+            0x0023, 0x0540, // DATA R3, 0x0540
+            0x0033, // JR R3
+        ];
+        program.resize(0x40, 0x0060); // CLF
+        program.push(0x0180); // HALT
+
+        run_lockstep(&program, 0x0500, 80);
+    }
+
+    // each core gets its own timer behind its own controller
+    fn connect_timer(core: &mut dyn Core, period: u16) {
+        let timer = Arc::new(Mutex::new(Timer::new(period)));
+        let mut controller = InterruptController::new();
+        controller.add_source(timer.clone());
+        let controller = Arc::new(Mutex::new(controller));
+
+        core.connect_peripheral(timer);
+        core.connect_peripheral(controller.clone());
+        core.connect_interrupt_controller(controller);
+    }
+
+    #[test]
+    fn test_behavioral_cpu_interrupts() {
+        let program = vec![
+            0x0020, 0x0520, // DATA R0, 0x0520
+            0x0170, // SETIV R0
+            0x0021, 0x0010, // DATA R1, 0x0010
+            0x007D, // OUT Addr, R1
+            0x0021, 0x0001, // DATA R1, 0x0001
+            0x0079, // OUT Data, R1 -> unmask the timer
+            0x0023, 0x0000, // DATA R3, 0x0000
+            0x0020, 0x0700, // DATA R0, 0x0700
+            0x0140, // SETSP R0
+            0x0164, // EI
+            0x0081, // ADD R0, R1
+            0x0040, 0x050F, // JMP
+        ];
+        let handler = [
+            0x0072, // IN Data, R2 -> acknowledge
+            0x0022, 0x0001, // DATA R2, 0x0001
+            0x008B, // ADD R2, R3
+            0x0160, // IRET
+        ];
+
+        let (mut gate, mut behavioral) = get_cpus(&program, 0x0500);
+        for (i, value) in handler.iter().enumerate() {
+            gate.write_memory(0x0520 + i as u16, *value);
+            behavioral.write_memory(0x0520 + i as u16, *value);
+        }
+        connect_timer(&mut gate, 7);
+        connect_timer(&mut behavioral, 7);
+
+        compare_lockstep(&mut gate, &mut behavioral, 80);
+        assert!(behavioral.state().registers[3] > 2);
+    }
+
+    #[test]
+    fn test_behavioral_cpu_random_words() {
+        for mut seed in [0x1234_5678_u32, 0x0BAD_F00D, 0xCAFE_BABE] {
+            let program: Vec<u16> = (0..512)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (seed >> 16) as u16
+                })
+                .collect();
+
+            run_lockstep(&program, 0x0000, 200);
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use super::{FlagState, InstructionDecoder3x8, ALU};
+use super::{Core, CpuState, FlagState, Flags, InstructionDecoder3x8, ALU};
 use crate::computer::{
     components::{
-        ANDGate3, Bit, Bus, BusOne, Component, Decoder2x4, Enableable, IOBus, ORGate3, ORGate4,
-        ORGate5, ORGate6, Register, Settable, Stepper, Updatable, BUS_WIDTH,
+        ANDGate3, ANDGate4, Bit, Bus, BusOne, Component, Decoder2x4, Enableable, Enabler, IOBus,
+        ORGate3, ORGate4, ORGate5, ORGate6, Register, Settable, StackPointer, Stepper, Updatable,
+        BUS_WIDTH,
     },
     gates::{Wire, AND, NOT, OR},
     io::Peripheral,
@@ -13,6 +14,10 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+// the word the fetch puts in IR instead of the one in memory when it takes an
+// interrupt
+const INTERRUPT_INSTRUCTION: u16 = 0x0178;
+
 #[derive(Clone)]
 pub struct CPU {
     gp_reg0: Register,
@@ -24,7 +29,11 @@ pub struct CPU {
     acc: Register,
     iar: Register, // Instruction address register
     ir: Register,  // Instruction register
+    sp: StackPointer,
+    iv: Register, // Interrupt vector
     pub flags: Register,
+    // FLAGS as they were when the interrupt was taken
+    iflags: Register,
 
     pub clock_state: bool,
     memory: Arc<Mutex<Memory64K>>,
@@ -58,6 +67,62 @@ pub struct CPU {
     pub ir_instruction_and_gate: ANDGate3,
     pub ir_instruction_not_gate: NOT,
 
+    // IR bit 0x0100 selects the stack instructions, the ALU and the legacy
+    // decoder only see words without it
+    pub ir_extended_not_gate: NOT,
+    pub ir_bit0_not_gate: NOT,
+    pub alu_instruction_and_gate: AND,
+    pub legacy_instruction_or_gate: OR,
+    pub stack_selector_gates: [ANDGate3; 8],
+    pub stack_step4_gates: [AND; 6],
+    pub stack_step5_gates: [AND; 4],
+    pub stack_step6_gates: [AND; 3],
+
+    // INTERRUPTS
+    // the controller's request is sampled at the start of every cycle and
+    // latched on step 1 if interrupts are enabled, the fetch then loads the
+    // INT word into IR and leaves IAR alone
+    pub interrupt_request: Wire,
+    pub interrupt_enable: Bit,
+    pub interrupt_request_and_gate: AND,
+    pub interrupt_latch: Bit,
+    pub interrupt_latch_and_gate: AND,
+    pub interrupt_latch_not_gate: NOT,
+    pub interrupt_fetch_and_gate: ANDGate3,
+    pub interrupt_instruction: Enabler,
+    pub fetch_ram_enable_and_gate: AND,
+    pub fetch_iar_set_and_gate: AND,
+    pub ir_bit5_not_gate: NOT,
+    pub interrupt_enable_select_or_gate: OR,
+    pub iret_selector_gate: ANDGate3,
+    pub interrupt_enable_selector_gate: AND,
+    pub setiv_selector_gate: AND,
+    pub int_selector_gate: AND,
+    // IRET, EI and DI, SETIV and INT
+    pub interrupt_step4_gates: [AND; 4],
+    // IRET and INT
+    pub interrupt_step5_gates: [AND; 2],
+    pub interrupt_step6_gates: [AND; 2],
+    pub interrupt_enable_set_or_gate: ORGate3,
+    pub interrupt_enable_set_and_gate: AND,
+    pub interrupt_enable_value_and_gate: AND,
+    pub interrupt_enable_value_or_gate: OR,
+    pub iv_enable_and_gate: AND,
+    pub iv_set_and_gate: AND,
+    pub iflags_set_and_gate: AND,
+
+    // HALT
+    // sets the halt bit on step 6, which holds the stepper clock low so the
+    // stepper never leaves step 6 again
+    pub halt_operand_or_gate: ORGate4,
+    pub halt_operand_not_gate: NOT,
+    pub halt_selector_gate: ANDGate4,
+    pub halt_step6_gate: AND,
+    pub halt_set_and_gate: AND,
+    pub halted: Bit,
+    pub halted_not_gate: NOT,
+    pub stepper_clock_and_gate: AND,
+
     pub io_bus_enable_gate: AND,
     pub register_a_enable_or_gate: ORGate3,
     pub register_b_enable_or_gate: ORGate4,
@@ -101,7 +166,28 @@ pub struct CPU {
 
     // FLAGS
     pub flags_set_and_gate: AND,
-    pub flags_set_or_gate: OR,
+    pub flags_set_or_gate: ORGate3,
+
+    // SP
+    pub sp_enable_or_gate: ORGate4,
+    pub sp_enable_and_gate: AND,
+    pub sp_increment_or_gate: ORGate3,
+    pub sp_increment_and_gate: AND,
+    pub sp_decrement_or_gate: ORGate3,
+    pub sp_decrement_and_gate: AND,
+    pub sp_set_or_gate: ORGate5,
+    pub sp_set_interrupt_or_gate: ORGate3,
+    pub sp_set_and_gate: AND,
+
+    // the stack and interrupt instructions join the legacy control lines here
+    pub mar_set_stack_or_gate: ORGate5,
+    pub mar_set_interrupt_or_gate: ORGate3,
+    pub iar_enable_stack_or_gate: ORGate3,
+    pub iar_set_stack_or_gate: ORGate5,
+    pub ram_enable_stack_or_gate: ORGate4,
+    pub ram_set_or_gate: ORGate4,
+    pub register_b_enable_stack_or_gate: ORGate5,
+    pub register_b_set_stack_or_gate: ORGate3,
 
     pub register_b_set: Wire,
 
@@ -114,6 +200,7 @@ pub struct CPU {
     pub carry_and_gate: AND,
 
     pub peripherals: Vec<Arc<Mutex<dyn Peripheral>>>,
+    pub interrupt_controller: Option<Arc<Mutex<dyn Peripheral>>>,
 }
 
 impl CPU {
@@ -147,6 +234,19 @@ impl CPU {
         CPU::update_on(&mut flags);
         CPU::update_set_status(&mut flags, false);
 
+        // IFLAGS
+        let mut iflags = Register::new("IFLAGS", flags_bus.clone(), alu_to_flags_bus.clone());
+        CPU::update_set_status(&mut iflags, true);
+        CPU::update_on(&mut iflags);
+        CPU::update_set_status(&mut iflags, false);
+
+        // INT
+        let mut interrupt_instruction = Enabler::new();
+        for i in 0..BUS_WIDTH {
+            interrupt_instruction
+                .set_input_wire(i, INTERRUPT_INSTRUCTION & (1 << (BUS_WIDTH - 1 - i)) != 0);
+        }
+
         // BUS one
         let busone_output = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
         let busone = BusOne::new(tmp_bus.clone(), busone_output.clone());
@@ -159,7 +259,7 @@ impl CPU {
             alu_to_flags_bus.clone(),
         );
 
-        Self {
+        let mut res = Self {
             gp_reg0: Register::new("R0", main_bus.clone(), main_bus.clone()),
             gp_reg1: Register::new("R1", main_bus.clone(), main_bus.clone()),
             gp_reg2: Register::new("R2", main_bus.clone(), main_bus.clone()),
@@ -168,7 +268,10 @@ impl CPU {
             acc,
             ir,
             iar: Register::new("IAR", main_bus.clone(), main_bus.clone()),
+            sp: StackPointer::new(main_bus.clone()),
+            iv: Register::new("IV", main_bus.clone(), main_bus.clone()),
             flags,
+            iflags,
             clock_state: false,
             memory,
             alu,
@@ -209,6 +312,76 @@ impl CPU {
             instruction_decoder_set2x4: Decoder2x4::new(),
             ir_instruction_and_gate: ANDGate3::new(),
             ir_instruction_not_gate: NOT::new(),
+            ir_extended_not_gate: NOT::new(),
+            ir_bit0_not_gate: NOT::new(),
+            alu_instruction_and_gate: AND::new(),
+            legacy_instruction_or_gate: OR::new(),
+            stack_selector_gates: (0..8)
+                .map(|_| ANDGate3::new())
+                .collect::<Vec<ANDGate3>>()
+                .try_into()
+                .unwrap(),
+            stack_step4_gates: (0..6)
+                .map(|_| AND::new())
+                .collect::<Vec<AND>>()
+                .try_into()
+                .unwrap(),
+            stack_step5_gates: (0..4)
+                .map(|_| AND::new())
+                .collect::<Vec<AND>>()
+                .try_into()
+                .unwrap(),
+            stack_step6_gates: (0..3)
+                .map(|_| AND::new())
+                .collect::<Vec<AND>>()
+                .try_into()
+                .unwrap(),
+            interrupt_request: Wire::new("Z".to_string(), false),
+            interrupt_enable: Bit::new(),
+            interrupt_request_and_gate: AND::new(),
+            interrupt_latch: Bit::new(),
+            interrupt_latch_and_gate: AND::new(),
+            interrupt_latch_not_gate: NOT::new(),
+            interrupt_fetch_and_gate: ANDGate3::new(),
+            interrupt_instruction,
+            fetch_ram_enable_and_gate: AND::new(),
+            fetch_iar_set_and_gate: AND::new(),
+            ir_bit5_not_gate: NOT::new(),
+            interrupt_enable_select_or_gate: OR::new(),
+            iret_selector_gate: ANDGate3::new(),
+            interrupt_enable_selector_gate: AND::new(),
+            setiv_selector_gate: AND::new(),
+            int_selector_gate: AND::new(),
+            interrupt_step4_gates: (0..4)
+                .map(|_| AND::new())
+                .collect::<Vec<AND>>()
+                .try_into()
+                .unwrap(),
+            interrupt_step5_gates: (0..2)
+                .map(|_| AND::new())
+                .collect::<Vec<AND>>()
+                .try_into()
+                .unwrap(),
+            interrupt_step6_gates: (0..2)
+                .map(|_| AND::new())
+                .collect::<Vec<AND>>()
+                .try_into()
+                .unwrap(),
+            interrupt_enable_set_or_gate: ORGate3::new(),
+            interrupt_enable_set_and_gate: AND::new(),
+            interrupt_enable_value_and_gate: AND::new(),
+            interrupt_enable_value_or_gate: OR::new(),
+            iv_enable_and_gate: AND::new(),
+            iv_set_and_gate: AND::new(),
+            iflags_set_and_gate: AND::new(),
+            halt_operand_or_gate: ORGate4::new(),
+            halt_operand_not_gate: NOT::new(),
+            halt_selector_gate: ANDGate4::new(),
+            halt_step6_gate: AND::new(),
+            halt_set_and_gate: AND::new(),
+            halted: Bit::new(),
+            halted_not_gate: NOT::new(),
+            stepper_clock_and_gate: AND::new(),
             io_bus_enable_gate: AND::new(),
             register_a_enable_or_gate: ORGate3::new(),
             register_b_enable_or_gate: ORGate4::new(),
@@ -248,8 +421,25 @@ impl CPU {
             acc_set_and_gate: AND::new(),
             ram_set_and_gate: AND::new(),
             tmp_set_and_gate: AND::new(),
-            flags_set_or_gate: OR::new(),
+            flags_set_or_gate: ORGate3::new(),
             flags_set_and_gate: AND::new(),
+            sp_enable_or_gate: ORGate4::new(),
+            sp_enable_and_gate: AND::new(),
+            sp_increment_or_gate: ORGate3::new(),
+            sp_increment_and_gate: AND::new(),
+            sp_decrement_or_gate: ORGate3::new(),
+            sp_decrement_and_gate: AND::new(),
+            sp_set_or_gate: ORGate5::new(),
+            sp_set_interrupt_or_gate: ORGate3::new(),
+            sp_set_and_gate: AND::new(),
+            mar_set_stack_or_gate: ORGate5::new(),
+            mar_set_interrupt_or_gate: ORGate3::new(),
+            iar_enable_stack_or_gate: ORGate3::new(),
+            iar_set_stack_or_gate: ORGate5::new(),
+            ram_enable_stack_or_gate: ORGate4::new(),
+            ram_set_or_gate: ORGate4::new(),
+            register_b_enable_stack_or_gate: ORGate5::new(),
+            register_b_set_stack_or_gate: ORGate3::new(),
             register_b_set: Wire::new("Z".to_string(), false),
             flag_state_gates: (0..4)
                 .map(|_| AND::new())
@@ -265,7 +455,31 @@ impl CPU {
             carry_temp: Bit::new(),
             carry_and_gate: AND::new(),
             peripherals: Vec::new(),
-        }
+            interrupt_controller: None,
+        };
+
+        // registers latch high on their first update, settle them before the
+        // first clock so state() reports what the bus would see
+        CPU::update_on(&mut res.gp_reg0);
+        CPU::update_on(&mut res.gp_reg1);
+        CPU::update_on(&mut res.gp_reg2);
+        CPU::update_on(&mut res.gp_reg3);
+        CPU::update_on(&mut res.acc);
+        CPU::update_on(&mut res.iar);
+        CPU::update_on(&mut res.ir);
+        CPU::update_on(&mut res.sp);
+        CPU::update_on(&mut res.iv);
+
+        // interrupts stay off until EI
+        res.interrupt_enable.update(false, true);
+        res.interrupt_enable.update(false, false);
+        res.interrupt_latch.update(false, true);
+        res.interrupt_latch.update(false, false);
+        res.interrupt_latch_not_gate.update(false);
+        res.halted.update(false, true);
+        res.halted.update(false, false);
+        res.halted_not_gate.update(false);
+        res
     }
 
     fn update_enable_status<T>(enableable: &mut T, state: bool)
@@ -322,39 +536,25 @@ impl CPU {
         u.lock().unwrap().update()
     }
 
-    pub fn connect_peripheral<T>(&mut self, p: Arc<Mutex<T>>)
-    where
-        T: Peripheral + 'static,
-    {
-        p.lock()
-            .unwrap()
-            .connect(self.io_bus.clone(), self.main_bus.clone());
-        self.peripherals.push(p);
-    }
-
-    pub fn set_iar(&mut self, address: u16) {
-        self.main_bus.lock().unwrap().set_value(address);
-
-        Self::update_set_status(&mut self.iar, true);
-        Self::update_on(&mut self.iar);
-        Self::update_set_status(&mut self.iar, false);
-        Self::update_on(&mut self.iar);
-
-        self.clear_main_bus()
-    }
-
-    pub fn step(&mut self) {
-        for _ in 0..2 {
-            self.clock_state = match self.clock_state {
-                true => false,
-                false => true,
-            };
-            self.to_step(self.clock_state);
+    // FLAGS only loads from the ALU side
+    fn load_flags(&mut self, flags: &Flags) {
+        {
+            let mut flags_in = self.alu_to_flags_bus.lock().unwrap();
+            flags_in.set_input_wire(FlagState::Carry as i32, flags.carry);
+            flags_in.set_input_wire(FlagState::ALarger as i32, flags.a_larger);
+            flags_in.set_input_wire(FlagState::Equal as i32, flags.equal);
+            flags_in.set_input_wire(FlagState::Zero as i32, flags.zero);
         }
+        Self::update_set_status(&mut self.flags, true);
+        Self::update_on(&mut self.flags);
+        Self::update_set_status(&mut self.flags, false);
+        Self::update_on(&mut self.flags);
     }
 
     fn to_step(&mut self, clock_state: bool) {
-        self.stepper.update(clock_state);
+        self.stepper_clock_and_gate
+            .update(clock_state, self.halted_not_gate.get());
+        self.stepper.update(self.stepper_clock_and_gate.get());
         self.run_step_4_gates();
         self.run_step_5_gates();
         self.run_step_6_gates();
@@ -378,7 +578,10 @@ impl CPU {
     }
 
     fn run_step_4_gates(&mut self) {
-        self.step4_gates[0].update(self.stepper.get_output_wire(3), self.ir.bit(8));
+        self.step4_gates[0].update(
+            self.stepper.get_output_wire(3),
+            self.alu_instruction_and_gate.get(),
+        );
 
         let mut gate = 1;
         for selector in 0..7 {
@@ -396,10 +599,30 @@ impl CPU {
         );
 
         self.ir_bit4_not_gate.update(self.ir.bit(12));
+
+        for i in 0..6 {
+            self.stack_step4_gates[i].update(
+                self.stepper.get_output_wire(3),
+                self.stack_selector_gates[i].get(),
+            );
+        }
+
+        let selectors = [
+            self.iret_selector_gate.get(),
+            self.interrupt_enable_selector_gate.get(),
+            self.setiv_selector_gate.get(),
+            self.int_selector_gate.get(),
+        ];
+        for (gate, selector) in self.interrupt_step4_gates.iter_mut().zip(selectors) {
+            gate.update(self.stepper.get_output_wire(3), selector);
+        }
     }
 
     fn run_step_5_gates(&mut self) {
-        self.step5_gates[0].update(self.stepper.get_output_wire(4), self.ir.bit(8));
+        self.step5_gates[0].update(
+            self.stepper.get_output_wire(4),
+            self.alu_instruction_and_gate.get(),
+        );
 
         self.step5_gates[1].update(
             self.stepper.get_output_wire(4),
@@ -428,12 +651,29 @@ impl CPU {
             self.instr_decoder3x8.selector_gates[7].get(),
             self.ir_bit4_not_gate.get(),
         );
+
+        for i in 0..4 {
+            self.stack_step5_gates[i].update(
+                self.stepper.get_output_wire(4),
+                self.stack_selector_gates[i].get(),
+            );
+        }
+
+        // IRET and INT
+        self.interrupt_step5_gates[0].update(
+            self.stepper.get_output_wire(4),
+            self.iret_selector_gate.get(),
+        );
+        self.interrupt_step5_gates[1].update(
+            self.stepper.get_output_wire(4),
+            self.int_selector_gate.get(),
+        );
     }
 
     fn run_step_6_gates(&mut self) {
         self.step6_gates[0].update(
             self.stepper.get_output_wire(5),
-            self.ir.bit(8),
+            self.alu_instruction_and_gate.get(),
             self.ir_instruction_not_gate.get(),
         );
 
@@ -447,6 +687,29 @@ impl CPU {
             self.instr_decoder3x8.selector_gates[5].get(),
             self.flag_state_or_gate.get(),
         );
+
+        // POP, CALL and RET
+        for i in 0..3 {
+            self.stack_step6_gates[i].update(
+                self.stepper.get_output_wire(5),
+                self.stack_selector_gates[i + 1].get(),
+            );
+        }
+
+        // IRET and INT
+        self.interrupt_step6_gates[0].update(
+            self.stepper.get_output_wire(5),
+            self.iret_selector_gate.get(),
+        );
+        self.interrupt_step6_gates[1].update(
+            self.stepper.get_output_wire(5),
+            self.int_selector_gate.get(),
+        );
+
+        self.halt_step6_gate.update(
+            self.stepper.get_output_wire(5),
+            self.halt_selector_gate.get(),
+        );
     }
 
     fn update_states(&mut self) {
@@ -459,12 +722,21 @@ impl CPU {
         // IR
         Self::update_on(&mut self.ir);
 
+        // SP
+        Self::update_on(&mut self.sp);
+
+        // IV
+        Self::update_on(&mut self.iv);
+
         // RAM
         Self::update_on_arc_mutex(self.memory.clone());
 
         // TMP
         Self::update_on(&mut self.tmp);
 
+        // IFLAGS, ahead of FLAGS so IRET can restore them over the ALU output
+        Self::update_on(&mut self.iflags);
+
         // FLAGS
         Self::update_on(&mut self.flags);
 
@@ -499,7 +771,16 @@ impl CPU {
     }
 
     fn update_instruction_decoder3x8(&mut self) {
-        self.instr_decoder3x8.bit0_not_gate.update(self.ir.bit(8));
+        self.ir_extended_not_gate.update(self.ir.bit(7));
+        self.ir_bit0_not_gate.update(self.ir.bit(8));
+        self.alu_instruction_and_gate
+            .update(self.ir.bit(8), self.ir_extended_not_gate.get());
+        self.legacy_instruction_or_gate
+            .update(self.ir.bit(8), self.ir.bit(7));
+
+        self.instr_decoder3x8
+            .bit0_not_gate
+            .update(self.legacy_instruction_or_gate.get());
 
         self.instr_decoder3x8
             .decoder
@@ -510,7 +791,52 @@ impl CPU {
                 self.instr_decoder3x8.decoder.get_output_wire(i as i32),
                 self.instr_decoder3x8.bit0_not_gate.get(),
             );
+            self.stack_selector_gates[i].update(
+                self.instr_decoder3x8.decoder.get_output_wire(i as i32),
+                self.ir_bit0_not_gate.get(),
+                self.ir.bit(7),
+            );
         }
+
+        // the last two stack classes hold the interrupt instructions, IR bits
+        // 0x0008 and 0x0004 tell them apart
+        self.ir_bit4_not_gate.update(self.ir.bit(12));
+        self.ir_bit5_not_gate.update(self.ir.bit(13));
+        self.interrupt_enable_select_or_gate
+            .update(self.ir.bit(12), self.ir.bit(13));
+
+        self.iret_selector_gate.update(
+            self.stack_selector_gates[6].get(),
+            self.ir_bit4_not_gate.get(),
+            self.ir_bit5_not_gate.get(),
+        );
+        self.interrupt_enable_selector_gate.update(
+            self.stack_selector_gates[6].get(),
+            self.interrupt_enable_select_or_gate.get(),
+        );
+        self.setiv_selector_gate.update(
+            self.stack_selector_gates[7].get(),
+            self.ir_bit4_not_gate.get(),
+        );
+        self.int_selector_gate
+            .update(self.stack_selector_gates[7].get(), self.ir.bit(12));
+
+        // HALT is the first class with both 0x0080 and 0x0100 set, and only
+        // 0x0180 itself, the rest of the class does nothing
+        self.halt_operand_or_gate.update(
+            self.ir.bit(12),
+            self.ir.bit(13),
+            self.ir.bit(14),
+            self.ir.bit(15),
+        );
+        self.halt_operand_not_gate
+            .update(self.halt_operand_or_gate.get());
+        self.halt_selector_gate.update(
+            self.instr_decoder3x8.decoder.get_output_wire(0),
+            self.ir.bit(8),
+            self.ir.bit(7),
+            self.halt_operand_not_gate.get(),
+        );
     }
 
     fn update_io_bus(&mut self) {
@@ -526,22 +852,39 @@ impl CPU {
         }
     }
 
+    // the stepper is about to start step 1
+    fn at_cycle_start(&self) -> bool {
+        !(0..5).any(|i| self.stepper.get_output_wire(i))
+    }
+
+    fn start_cycle(&mut self) {
+        for p in self.peripherals.iter() {
+            p.lock().unwrap().tick()
+        }
+
+        let request = match &self.interrupt_controller {
+            Some(controller) => controller.lock().unwrap().irq(),
+            None => false,
+        };
+        self.interrupt_request.update(request);
+    }
+
     fn update_alu(&mut self) {
         // update ALU operation based on instruction register
         self.alu_op_and_gates[2].update(
             self.ir.bit(9),
-            self.ir.bit(8),
+            self.alu_instruction_and_gate.get(),
             self.stepper.get_output_wire(4),
         );
 
         self.alu_op_and_gates[1].update(
             self.ir.bit(10),
-            self.ir.bit(8),
+            self.alu_instruction_and_gate.get(),
             self.stepper.get_output_wire(4),
         );
         self.alu_op_and_gates[0].update(
             self.ir.bit(11),
-            self.ir.bit(8),
+            self.alu_instruction_and_gate.get(),
             self.stepper.get_output_wire(4),
         );
 
@@ -555,6 +898,186 @@ impl CPU {
     }
 }
 
+impl Core for CPU {
+    fn step(&mut self) {
+        // a halted CPU starts no more cycles
+        if self.at_cycle_start() && !self.halted.get() {
+            self.start_cycle();
+        }
+
+        for _ in 0..2 {
+            self.clock_state = match self.clock_state {
+                true => false,
+                false => true,
+            };
+            self.to_step(self.clock_state);
+        }
+    }
+
+    fn set_iar(&mut self, address: u16) {
+        self.main_bus.lock().unwrap().set_value(address);
+
+        Self::update_set_status(&mut self.iar, true);
+        Self::update_on(&mut self.iar);
+        Self::update_set_status(&mut self.iar, false);
+        Self::update_on(&mut self.iar);
+
+        self.clear_main_bus()
+    }
+
+    fn set_register(&mut self, register: usize, value: u16) {
+        self.main_bus.lock().unwrap().set_value(value);
+
+        let reg = match register {
+            0 => &mut self.gp_reg0,
+            1 => &mut self.gp_reg1,
+            2 => &mut self.gp_reg2,
+            _ => &mut self.gp_reg3,
+        };
+        Self::update_set_status(reg, true);
+        Self::update_on(reg);
+        Self::update_set_status(reg, false);
+        Self::update_on(reg);
+
+        self.clear_main_bus()
+    }
+
+    fn set_stack_pointer(&mut self, value: u16) {
+        self.main_bus.lock().unwrap().set_value(value);
+
+        Self::update_set_status(&mut self.sp, true);
+        Self::update_on(&mut self.sp);
+        Self::update_set_status(&mut self.sp, false);
+        Self::update_on(&mut self.sp);
+
+        self.clear_main_bus()
+    }
+
+    fn state(&self) -> CpuState {
+        let flags_bus = self.flags_bus.lock().unwrap();
+        CpuState {
+            registers: [
+                self.gp_reg0.value(),
+                self.gp_reg1.value(),
+                self.gp_reg2.value(),
+                self.gp_reg3.value(),
+            ],
+            iar: self.iar.value(),
+            ir: self.ir.value(),
+            acc: self.acc.value(),
+            tmp: self.tmp.value(),
+            sp: self.sp.value(),
+            iv: self.iv.value(),
+            flags: Flags {
+                carry: flags_bus.get_output_wire(FlagState::Carry as i32),
+                a_larger: flags_bus.get_output_wire(FlagState::ALarger as i32),
+                equal: flags_bus.get_output_wire(FlagState::Equal as i32),
+                zero: flags_bus.get_output_wire(FlagState::Zero as i32),
+            },
+            saved_flags: Flags {
+                carry: self.iflags.bit(FlagState::Carry as i32),
+                a_larger: self.iflags.bit(FlagState::ALarger as i32),
+                equal: self.iflags.bit(FlagState::Equal as i32),
+                zero: self.iflags.bit(FlagState::Zero as i32),
+            },
+            interrupts_enabled: self.interrupt_enable.get(),
+            halted: self.halted.get(),
+        }
+    }
+
+    fn set_state(&mut self, state: &CpuState) {
+        for (i, value) in state.registers.iter().enumerate() {
+            self.set_register(i, *value);
+        }
+        self.set_iar(state.iar);
+        self.set_stack_pointer(state.sp);
+
+        // IR, TMP and IV load from the main bus, ACC from the ALU output
+        for (reg, value) in [
+            (&mut self.ir, state.ir),
+            (&mut self.tmp, state.tmp),
+            (&mut self.iv, state.iv),
+        ] {
+            self.main_bus.lock().unwrap().set_value(value);
+            Self::update_set_status(reg, true);
+            Self::update_on(reg);
+            Self::update_set_status(reg, false);
+            Self::update_on(reg);
+        }
+        self.clear_main_bus();
+
+        self.acc_bus.lock().unwrap().set_value(state.acc);
+        Self::update_set_status(&mut self.acc, true);
+        Self::update_on(&mut self.acc);
+        Self::update_set_status(&mut self.acc, false);
+        Self::update_on(&mut self.acc);
+
+        // IFLAGS copies FLAGS, so the saved flags go through FLAGS first
+        self.load_flags(&state.saved_flags);
+        Self::update_set_status(&mut self.iflags, true);
+        Self::update_on(&mut self.iflags);
+        Self::update_set_status(&mut self.iflags, false);
+        Self::update_on(&mut self.iflags);
+        self.load_flags(&state.flags);
+
+        self.interrupt_enable.update(state.interrupts_enabled, true);
+        self.interrupt_enable
+            .update(state.interrupts_enabled, false);
+
+        self.halted.update(state.halted, true);
+        self.halted.update(state.halted, false);
+        self.halted_not_gate.update(self.halted.get());
+    }
+
+    fn read_memory(&self, address: u16) -> u16 {
+        self.memory.lock().unwrap().peek(address)
+    }
+
+    fn write_memory(&mut self, address: u16, value: u16) {
+        let mut memory = self.memory.lock().unwrap();
+        memory.address_register.set();
+        self.main_bus.lock().unwrap().set_value(address);
+        memory.update();
+
+        memory.address_register.unset();
+        memory.update();
+
+        self.main_bus.lock().unwrap().set_value(value);
+        memory.set();
+        memory.update();
+
+        // leaving RAM set would store the next bus value over this cell
+        memory.unset();
+        memory.update();
+    }
+
+    fn log_accesses(&mut self) {
+        let mut memory = self.memory.lock().unwrap();
+        memory.log_reads();
+        memory.log_writes();
+    }
+
+    fn take_reads(&mut self) -> Vec<(u16, u16)> {
+        self.memory.lock().unwrap().take_reads()
+    }
+
+    fn take_writes(&mut self) -> Vec<(u16, u16)> {
+        self.memory.lock().unwrap().take_writes()
+    }
+
+    fn connect_peripheral(&mut self, p: Arc<Mutex<dyn Peripheral>>) {
+        p.lock()
+            .unwrap()
+            .connect(self.io_bus.clone(), self.main_bus.clone());
+        self.peripherals.push(p);
+    }
+
+    fn connect_interrupt_controller(&mut self, p: Arc<Mutex<dyn Peripheral>>) {
+        self.connect_peripheral(p.clone());
+        self.interrupt_controller = Some(p);
+    }
+}
+
 // Run enable
 impl CPU {
     fn run_enable(&mut self, state: bool) {
@@ -563,6 +1086,8 @@ impl CPU {
         self.run_enable_on_bus_one(state);
         self.run_enable_on_acc(state);
         self.run_enable_on_ram(state);
+        self.run_enable_on_sp(state);
+        self.run_enable_on_interrupt(state);
         self.run_enable_on_register_b();
         self.run_enable_on_register_a();
         self.run_enable_general_purpose_registers(state);
@@ -583,8 +1108,15 @@ impl CPU {
             self.step4_gates[6].get(),
         );
 
+        // CALL and INT push the return address
+        self.iar_enable_stack_or_gate.update(
+            self.iar_enable_or_gate.get(),
+            self.stack_step5_gates[2].get(),
+            self.interrupt_step5_gates[1].get(),
+        );
+
         self.iar_enable_and_gate
-            .update(state, self.iar_enable_or_gate.get());
+            .update(state, self.iar_enable_stack_or_gate.get());
 
         Self::update_enable_status(&mut self.iar, self.iar_enable_and_gate.get());
     }
@@ -613,18 +1145,89 @@ impl CPU {
     }
 
     fn run_enable_on_ram(&mut self, state: bool) {
-        self.ram_enable_or_gate.update(
+        // the fetch leaves RAM alone when it takes an interrupt
+        self.fetch_ram_enable_and_gate.update(
             self.stepper.get_output_wire(1),
+            self.interrupt_latch_not_gate.get(),
+        );
+        self.ram_enable_or_gate.update(
+            self.fetch_ram_enable_and_gate.get(),
             self.step6_gates[1].get(),
             self.step5_gates[4].get(),
             self.step5_gates[3].get(),
             self.step5_gates[1].get(),
         );
+        // POP, RET and IRET read the top of the stack
+        self.ram_enable_stack_or_gate.update(
+            self.ram_enable_or_gate.get(),
+            self.stack_step5_gates[1].get(),
+            self.stack_step5_gates[3].get(),
+            self.interrupt_step5_gates[0].get(),
+        );
         self.ram_enable_and_gate
-            .update(state, self.ram_enable_or_gate.get());
+            .update(state, self.ram_enable_stack_or_gate.get());
         Self::update_enable_status_arc_mutex(self.memory.clone(), self.ram_enable_and_gate.get());
     }
 
+    fn run_enable_on_sp(&mut self, state: bool) {
+        // POP, RET, GETSP and IRET
+        self.sp_enable_or_gate.update(
+            self.stack_step4_gates[1].get(),
+            self.stack_step4_gates[3].get(),
+            self.stack_step4_gates[5].get(),
+            self.interrupt_step4_gates[0].get(),
+        );
+        self.sp_enable_and_gate
+            .update(state, self.sp_enable_or_gate.get());
+        Self::update_enable_status(&mut self.sp, self.sp_enable_and_gate.get());
+
+        // PUSH, CALL and INT
+        self.sp_decrement_or_gate.update(
+            self.stack_step4_gates[0].get(),
+            self.stack_step4_gates[2].get(),
+            self.interrupt_step4_gates[3].get(),
+        );
+        self.sp_decrement_and_gate
+            .update(state, self.sp_decrement_or_gate.get());
+        self.sp.decrement.update(self.sp_decrement_and_gate.get());
+
+        // POP, RET and IRET
+        self.sp_increment_or_gate.update(
+            self.stack_step6_gates[0].get(),
+            self.stack_step6_gates[2].get(),
+            self.interrupt_step6_gates[0].get(),
+        );
+        self.sp_increment_and_gate
+            .update(state, self.sp_increment_or_gate.get());
+        self.sp.increment.update(self.sp_increment_and_gate.get());
+    }
+
+    fn run_enable_on_interrupt(&mut self, state: bool) {
+        // step 2 of a fetch that takes an interrupt
+        self.interrupt_fetch_and_gate.update(
+            state,
+            self.stepper.get_output_wire(1),
+            self.interrupt_latch.get(),
+        );
+        self.interrupt_instruction
+            .update(self.interrupt_fetch_and_gate.get());
+        if self.interrupt_fetch_and_gate.get() {
+            let mut main_bus = self.main_bus.lock().unwrap();
+            for i in 0..BUS_WIDTH {
+                main_bus.set_input_wire(i, self.interrupt_instruction.get_output_wire(i));
+            }
+        }
+
+        // INT jumps to the vector
+        self.iv_enable_and_gate
+            .update(state, self.interrupt_step6_gates[1].get());
+        Self::update_enable_status(&mut self.iv, self.iv_enable_and_gate.get());
+
+        // IRET puts the saved flags in front of FLAGS for the whole step, the
+        // ALU output they replace is only latched on the set half
+        Self::update_enable_status(&mut self.iflags, self.interrupt_step5_gates[0].get());
+    }
+
     fn run_enable_on_register_b(&mut self) {
         self.register_b_enable_or_gate.update(
             self.step4_gates[0].get(),
@@ -632,8 +1235,16 @@ impl CPU {
             self.step4_gates[4].get(),
             self.step4_gate3_and.get(),
         );
+        // PUSH, the CALL target, SETSP and SETIV
+        self.register_b_enable_stack_or_gate.update(
+            self.register_b_enable_or_gate.get(),
+            self.stack_step5_gates[0].get(),
+            self.stack_step6_gates[1].get(),
+            self.stack_step4_gates[4].get(),
+            self.interrupt_step4_gates[2].get(),
+        );
         self.register_b_enable
-            .update(self.register_b_enable_or_gate.get());
+            .update(self.register_b_enable_stack_or_gate.get());
     }
 
     fn run_enable_on_register_a(&mut self) {
@@ -729,6 +1340,7 @@ impl CPU {
             .update(self.ir_instruction_and_gate.get());
 
         self.refresh_flag_state_gates();
+        self.run_set_on_interrupt_latch(state);
 
         self.run_set_on_io(state);
         self.run_set_on_mar(state);
@@ -738,6 +1350,9 @@ impl CPU {
         self.run_set_on_ram(state);
         self.run_set_on_tmp(state);
         self.run_set_on_flags(state);
+        self.run_set_on_sp(state);
+        self.run_set_on_interrupt(state);
+        self.run_set_on_halt(state);
         self.run_set_on_register_b();
         self.run_set_general_purpose_registers(state);
     }
@@ -791,8 +1406,22 @@ impl CPU {
             self.step4_gates[2].get(),
             self.step4_gates[5].get(),
         );
+        // PUSH, POP, CALL and RET address the top of the stack
+        self.mar_set_stack_or_gate.update(
+            self.mar_set_or_gate.get(),
+            self.stack_step4_gates[0].get(),
+            self.stack_step4_gates[1].get(),
+            self.stack_step4_gates[2].get(),
+            self.stack_step4_gates[3].get(),
+        );
+        // IRET and INT
+        self.mar_set_interrupt_or_gate.update(
+            self.mar_set_stack_or_gate.get(),
+            self.interrupt_step4_gates[0].get(),
+            self.interrupt_step4_gates[3].get(),
+        );
         self.mar_set_and_gate
-            .update(state, self.mar_set_or_gate.get());
+            .update(state, self.mar_set_interrupt_or_gate.get());
 
         match self.mar_set_and_gate.get() {
             true => self.memory.lock().unwrap().address_register.set(),
@@ -801,16 +1430,29 @@ impl CPU {
     }
 
     fn run_set_on_iar(&mut self, state: bool) {
-        self.iar_set_or_gate.update(
+        // an interrupt keeps the address of the instruction it displaced
+        self.fetch_iar_set_and_gate.update(
             self.stepper.get_output_wire(2),
+            self.interrupt_latch_not_gate.get(),
+        );
+        self.iar_set_or_gate.update(
+            self.fetch_iar_set_and_gate.get(),
             self.step4_gates[4].get(),
             self.step5_gates[4].get(),
             self.step5_gates[5].get(),
             self.step6_gates2_and.get(),
             self.step6_gates[1].get(),
         );
+        // RET and IRET pop the return address, CALL and INT jump
+        self.iar_set_stack_or_gate.update(
+            self.iar_set_or_gate.get(),
+            self.stack_step5_gates[3].get(),
+            self.stack_step6_gates[1].get(),
+            self.interrupt_step5_gates[0].get(),
+            self.interrupt_step6_gates[1].get(),
+        );
         self.iar_set_and_gate
-            .update(state, self.iar_set_or_gate.get());
+            .update(state, self.iar_set_stack_or_gate.get());
         Self::update_set_status(&mut self.iar, self.iar_set_and_gate.get());
     }
 
@@ -833,8 +1475,15 @@ impl CPU {
     }
 
     fn run_set_on_ram(&mut self, state: bool) {
+        // PUSH, CALL and INT
+        self.ram_set_or_gate.update(
+            self.step5_gates[2].get(),
+            self.stack_step5_gates[0].get(),
+            self.stack_step5_gates[2].get(),
+            self.interrupt_step5_gates[1].get(),
+        );
         self.ram_set_and_gate
-            .update(state, self.step5_gates[2].get());
+            .update(state, self.ram_set_or_gate.get());
         Self::update_set_status_arc_mutex(self.memory.clone(), self.ram_set_and_gate.get());
     }
 
@@ -856,14 +1505,92 @@ impl CPU {
     }
 
     fn run_set_on_flags(&mut self, state: bool) {
-        self.flags_set_or_gate
-            .update(self.step5_gates[0].get(), self.step4_gates[7].get());
+        // the ALU, CLF and IRET
+        self.flags_set_or_gate.update(
+            self.step5_gates[0].get(),
+            self.step4_gates[7].get(),
+            self.interrupt_step5_gates[0].get(),
+        );
 
         self.flags_set_and_gate
             .update(state, self.flags_set_or_gate.get());
         Self::update_set_status(&mut self.flags, self.flags_set_and_gate.get());
     }
 
+    fn run_set_on_sp(&mut self, state: bool) {
+        // PUSH, CALL and SETSP on step 4, POP and RET on step 6
+        self.sp_set_or_gate.update(
+            self.stack_step4_gates[0].get(),
+            self.stack_step4_gates[2].get(),
+            self.stack_step4_gates[4].get(),
+            self.stack_step6_gates[0].get(),
+            self.stack_step6_gates[2].get(),
+        );
+        // INT on step 4, IRET on step 6
+        self.sp_set_interrupt_or_gate.update(
+            self.sp_set_or_gate.get(),
+            self.interrupt_step4_gates[3].get(),
+            self.interrupt_step6_gates[0].get(),
+        );
+        self.sp_set_and_gate
+            .update(state, self.sp_set_interrupt_or_gate.get());
+        Self::update_set_status(&mut self.sp, self.sp_set_and_gate.get());
+    }
+
+    fn run_set_on_halt(&mut self, state: bool) {
+        self.halt_set_and_gate
+            .update(state, self.halt_step6_gate.get());
+        self.halted.update(true, self.halt_set_and_gate.get());
+        self.halted_not_gate.update(self.halted.get());
+    }
+
+    fn run_set_on_interrupt_latch(&mut self, state: bool) {
+        self.interrupt_request_and_gate
+            .update(self.interrupt_request.get(), self.interrupt_enable.get());
+        self.interrupt_latch_and_gate
+            .update(state, self.stepper.get_output_wire(0));
+        self.interrupt_latch.update(
+            self.interrupt_request_and_gate.get(),
+            self.interrupt_latch_and_gate.get(),
+        );
+        self.interrupt_latch_not_gate
+            .update(self.interrupt_latch.get());
+    }
+
+    fn run_set_on_interrupt(&mut self, state: bool) {
+        // SETIV
+        self.iv_set_and_gate
+            .update(state, self.interrupt_step4_gates[2].get());
+        Self::update_set_status(&mut self.iv, self.iv_set_and_gate.get());
+
+        // INT saves the flags
+        self.iflags_set_and_gate
+            .update(state, self.interrupt_step5_gates[1].get());
+        Self::update_set_status(&mut self.iflags, self.iflags_set_and_gate.get());
+
+        // EI and DI write the inverse of IR bit 0x0008, IRET turns interrupts
+        // back on and INT turns them off
+        self.interrupt_enable_value_and_gate.update(
+            self.interrupt_step4_gates[1].get(),
+            self.ir_bit4_not_gate.get(),
+        );
+        self.interrupt_enable_value_or_gate.update(
+            self.interrupt_enable_value_and_gate.get(),
+            self.interrupt_step5_gates[0].get(),
+        );
+        self.interrupt_enable_set_or_gate.update(
+            self.interrupt_step4_gates[1].get(),
+            self.interrupt_step5_gates[0].get(),
+            self.interrupt_step5_gates[1].get(),
+        );
+        self.interrupt_enable_set_and_gate
+            .update(state, self.interrupt_enable_set_or_gate.get());
+        self.interrupt_enable.update(
+            self.interrupt_enable_value_or_gate.get(),
+            self.interrupt_enable_set_and_gate.get(),
+        );
+    }
+
     fn run_set_on_register_b(&mut self) {
         self.register_b_set_or_gate.update(
             self.step5_gates[1].get(),
@@ -871,8 +1598,14 @@ impl CPU {
             self.step5_gates[3].get(),
             self.step5_gate3_and.get(),
         );
+        // POP and GETSP
+        self.register_b_set_stack_or_gate.update(
+            self.register_b_set_or_gate.get(),
+            self.stack_step5_gates[1].get(),
+            self.stack_step4_gates[5].get(),
+        );
         self.register_b_set
-            .update(self.register_b_set_or_gate.get());
+            .update(self.register_b_set_stack_or_gate.get());
     }
 
     fn run_set_general_purpose_registers(&mut self, state: bool) {
@@ -919,13 +1652,15 @@ impl Display for CPU {
         let acc_bus_value = self.acc_bus.lock().unwrap().get_value();
         write!(
             f,
-            "step: {}\n{} {} {} {} {}\n{} {} {} {}\n<main_bus>: {:>#06X} <acc_bus>: {:>#06X}\n{}",
+            "step: {}\n{} {} {} {} {} {} {}\n{} {} {} {}\n<main_bus>: {:>#06X} <acc_bus>: {:>#06X}\n{}",
             self.stepper,
             self.iar,
             self.memory.lock().unwrap().address_register,
             self.ir,
             self.acc,
             self.tmp,
+            self.sp,
+            self.iv,
             self.gp_reg0,
             self.gp_reg1,
             self.gp_reg2,
@@ -940,6 +1675,7 @@ impl Display for CPU {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::computer::io::Timer;
 
     fn get_cpu() -> CPU {
         let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
@@ -958,6 +1694,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cpu_write_memory_unsets_ram() {
+        let mut cpu = get_cpu();
+        cpu.write_memory(0x0600, 0x1234);
+
+        // the next value on the bus must not reach the cell just written
+        cpu.main_bus.lock().unwrap().set_value(0xBEEF);
+        cpu.memory.lock().unwrap().update();
+        assert_eq!(cpu.read_memory(0x0600), 0x1234);
+    }
+
     #[test]
     fn test_cpu_instruction_received_from_memory() {
         let mut cpu = get_cpu();
@@ -1895,6 +2642,394 @@ mod tests {
         test_clf(0x0081, vec![0x0001, 0x0002, 0x0000, 0x0000]);
     }
 
+    #[test]
+    fn test_cpu_push() {
+        let test_push = |instruction: u16, register: usize| {
+            let mut cpu = get_cpu();
+            let input_registers = vec![0x0011, 0x0022, 0x0033, 0x0044];
+            set_memory_location(cpu.memory.clone(), 0x0000, instruction);
+            cpu.set_iar(0x0000);
+            cpu.set_stack_pointer(0x0500);
+            cpu.set_registers(input_registers.clone());
+
+            cpu.do_fetch_decode_execute();
+
+            cpu.check_registers(
+                instruction,
+                input_registers[0],
+                input_registers[1],
+                input_registers[2],
+                input_registers[3],
+            );
+            cpu.check_sp(0x04FF);
+            assert_eq!(cpu.read_memory(0x04FF), input_registers[register]);
+            cpu.check_iar(0x0001);
+        };
+
+        test_push(0x0100, 0); // HPUSH R0
+        test_push(0x0101, 1); // HPUSH R1
+        test_push(0x0102, 2); // HPUSH R2
+        test_push(0x0103, 3); // HPUSH R3
+    }
+
+    #[test]
+    fn test_cpu_pop() {
+        let test_pop = |instruction: u16, expected_output_registers: Vec<u16>| {
+            let mut cpu = get_cpu();
+            set_memory_location(cpu.memory.clone(), 0x0000, instruction);
+            set_memory_location(cpu.memory.clone(), 0x04FF, 0xBEEF);
+            cpu.set_iar(0x0000);
+            cpu.set_stack_pointer(0x04FF);
+            cpu.set_registers(vec![0x0011, 0x0022, 0x0033, 0x0044]);
+
+            cpu.do_fetch_decode_execute();
+
+            cpu.check_registers(
+                instruction,
+                expected_output_registers[0],
+                expected_output_registers[1],
+                expected_output_registers[2],
+                expected_output_registers[3],
+            );
+            cpu.check_sp(0x0500);
+            cpu.check_iar(0x0001);
+        };
+
+        test_pop(0x0110, vec![0xBEEF, 0x0022, 0x0033, 0x0044]); // HPOP R0
+        test_pop(0x0111, vec![0x0011, 0xBEEF, 0x0033, 0x0044]); // HPOP R1
+        test_pop(0x0112, vec![0x0011, 0x0022, 0xBEEF, 0x0044]); // HPOP R2
+        test_pop(0x0113, vec![0x0011, 0x0022, 0x0033, 0xBEEF]); // HPOP R3
+    }
+
+    #[test]
+    fn test_cpu_call() {
+        let test_call = |instruction: u16, expected_iar: u16| {
+            let mut cpu = get_cpu();
+            let input_registers = vec![0x0200, 0x0300, 0x0400, 0xF000];
+            set_memory_location(cpu.memory.clone(), 0x0010, instruction);
+            cpu.set_iar(0x0010);
+            cpu.set_stack_pointer(0x0500);
+            cpu.set_registers(input_registers.clone());
+
+            cpu.do_fetch_decode_execute();
+
+            cpu.check_registers(
+                instruction,
+                input_registers[0],
+                input_registers[1],
+                input_registers[2],
+                input_registers[3],
+            );
+            // the return address is the word after the CALL
+            cpu.check_sp(0x04FF);
+            assert_eq!(cpu.read_memory(0x04FF), 0x0011);
+            cpu.check_iar(expected_iar);
+        };
+
+        test_call(0x0120, 0x0200); // HCALL R0
+        test_call(0x0121, 0x0300); // HCALL R1
+        test_call(0x0122, 0x0400); // HCALL R2
+        test_call(0x0123, 0xF000); // HCALL R3
+    }
+
+    #[test]
+    fn test_cpu_ret() {
+        let mut cpu = get_cpu();
+        let input_registers = vec![0x0001, 0x0002, 0x0003, 0x0004];
+        set_memory_location(cpu.memory.clone(), 0x0000, 0x0130);
+        set_memory_location(cpu.memory.clone(), 0x04FF, 0x0123);
+        cpu.set_iar(0x0000);
+        cpu.set_stack_pointer(0x04FF);
+        cpu.set_registers(input_registers.clone());
+
+        cpu.do_fetch_decode_execute();
+
+        cpu.check_registers(
+            0x0130,
+            input_registers[0],
+            input_registers[1],
+            input_registers[2],
+            input_registers[3],
+        );
+        cpu.check_sp(0x0500);
+        cpu.check_iar(0x0123);
+    }
+
+    #[test]
+    fn test_cpu_setsp() {
+        let test_setsp = |instruction: u16, expected_sp: u16| {
+            let mut cpu = get_cpu();
+            cpu.set_stack_pointer(0x0500);
+            cpu.test_instruction(
+                instruction,
+                vec![0x1000, 0x2000, 0x3000, 0x4000],
+                vec![0x1000, 0x2000, 0x3000, 0x4000],
+            );
+            cpu.check_sp(expected_sp);
+        };
+
+        test_setsp(0x0140, 0x1000); // SETSP R0
+        test_setsp(0x0141, 0x2000); // SETSP R1
+        test_setsp(0x0142, 0x3000); // SETSP R2
+        test_setsp(0x0143, 0x4000); // SETSP R3
+    }
+
+    #[test]
+    fn test_cpu_getsp() {
+        let test_getsp = |instruction: u16, expected_output_registers: Vec<u16>| {
+            let mut cpu = get_cpu();
+            cpu.set_stack_pointer(0xABCD);
+            cpu.test_instruction(
+                instruction,
+                vec![0x1000, 0x2000, 0x3000, 0x4000],
+                expected_output_registers,
+            );
+            cpu.check_sp(0xABCD);
+        };
+
+        test_getsp(0x0150, vec![0xABCD, 0x2000, 0x3000, 0x4000]); // GETSP R0
+        test_getsp(0x0151, vec![0x1000, 0xABCD, 0x3000, 0x4000]); // GETSP R1
+        test_getsp(0x0152, vec![0x1000, 0x2000, 0xABCD, 0x4000]); // GETSP R2
+        test_getsp(0x0153, vec![0x1000, 0x2000, 0x3000, 0xABCD]); // GETSP R3
+    }
+
+    #[test]
+    fn test_cpu_stack_unused_opcodes() {
+        // words with the ALU bit set as well do nothing apart from HALT, 0x0181
+        // is not an ADD and 0x0191 not a SHL, neither halts
+        for instruction in [0x0181, 0x018F, 0x0191, 0x01E0, 0x01F5, 0x01F8] {
+            let mut cpu = get_cpu();
+            cpu.set_stack_pointer(0x0500);
+            cpu.test_instruction(
+                instruction,
+                vec![0x0001, 0x0002, 0x0003, 0x0004],
+                vec![0x0001, 0x0002, 0x0003, 0x0004],
+            );
+            cpu.check_sp(0x0500);
+            cpu.check_iar(0x0001);
+            cpu.check_flags_register(false, false, false, false);
+        }
+    }
+
+    #[test]
+    fn test_cpu_stack_nested_calls() {
+        let mut cpu = get_cpu();
+        let program = [
+            0x0020, 0x0010, // DATA R0, 0x0010
+            0x0120, // HCALL R0
+            0x0040, 0x0003, // JMP 0x0003
+        ];
+        let outer = [
+            0x0101, // HPUSH R1
+            0x0021, 0x0020, // DATA R1, 0x0020
+            0x0121, // HCALL R1
+            0x0111, // HPOP R1
+            0x0130, // HRET
+        ];
+        let inner = [
+            0x0152, // GETSP R2
+            0x0130, // HRET
+        ];
+        for (start, words) in [(0x0000, &program[..]), (0x0010, &outer), (0x0020, &inner)] {
+            for (i, word) in words.iter().enumerate() {
+                set_memory_location(cpu.memory.clone(), start + i as u16, *word);
+            }
+        }
+        cpu.set_iar(0x0000);
+        cpu.set_stack_pointer(0x0500);
+        cpu.set_registers(vec![0x0000, 0x7777, 0x0000, 0x0000]);
+
+        for _ in 0..10 {
+            cpu.do_fetch_decode_execute();
+        }
+
+        // inside the inner routine the outer return address, R1 and the
+        // inner return address were on the stack
+        cpu.check_registers(0x0130, 0x0010, 0x7777, 0x04FD, 0x0000);
+        cpu.check_sp(0x0500);
+        cpu.check_iar(0x0003);
+        assert_eq!(cpu.read_memory(0x04FF), 0x0003);
+        assert_eq!(cpu.read_memory(0x04FE), 0x7777);
+        assert_eq!(cpu.read_memory(0x04FD), 0x0014);
+    }
+
+    #[test]
+    fn test_cpu_ei_di() {
+        let mut cpu = get_cpu();
+        assert!(!cpu.state().interrupts_enabled);
+
+        for (instruction, enabled) in [
+            (0x0164, true),  // EI
+            (0x0168, false), // DI
+            (0x0167, true),  // EI, the register bits are ignored
+            (0x016C, false), // DI
+        ] {
+            cpu.test_instruction(
+                instruction,
+                vec![0x0001, 0x0002, 0x0003, 0x0004],
+                vec![0x0001, 0x0002, 0x0003, 0x0004],
+            );
+            assert_eq!(
+                cpu.state().interrupts_enabled,
+                enabled,
+                "{:#06X}",
+                instruction
+            );
+            cpu.check_iar(0x0001);
+        }
+    }
+
+    #[test]
+    fn test_cpu_setiv() {
+        let test_setiv = |instruction: u16, expected_iv: u16| {
+            let mut cpu = get_cpu();
+            cpu.test_instruction(
+                instruction,
+                vec![0x1000, 0x2000, 0x3000, 0x4000],
+                vec![0x1000, 0x2000, 0x3000, 0x4000],
+            );
+            assert_eq!(cpu.state().iv, expected_iv);
+        };
+
+        test_setiv(0x0170, 0x1000); // SETIV R0
+        test_setiv(0x0171, 0x2000); // SETIV R1
+        test_setiv(0x0172, 0x3000); // SETIV R2
+        test_setiv(0x0173, 0x4000); // SETIV R3
+    }
+
+    #[test]
+    fn test_cpu_int_iret() {
+        let mut cpu = get_cpu();
+        let program = [
+            0x0080, // ADD R0, R0 -> carry and equal
+            0x0178, // INT
+            0x00F1, // CMP R0, R1
+        ];
+        let handler = [
+            0x00F4, // CMP R1, R0 -> a larger
+            0x0160, // IRET
+        ];
+        for (start, words) in [(0x0000, &program[..]), (0x0040, &handler)] {
+            for (i, word) in words.iter().enumerate() {
+                set_memory_location(cpu.memory.clone(), start + i as u16, *word);
+            }
+        }
+        cpu.set_iar(0x0000);
+        cpu.set_stack_pointer(0x0500);
+        cpu.set_registers(vec![0x8000, 0x0001, 0x0040, 0x0000]);
+
+        cpu.do_fetch_decode_execute();
+        cpu.test_instruction_at(0x0172, 0x0100); // SETIV R2
+        cpu.set_iar(0x0001);
+        cpu.do_fetch_decode_execute();
+
+        // INT pushed the address after it and saved the ADD flags
+        let state = cpu.state();
+        cpu.check_iar(0x0040);
+        cpu.check_sp(0x04FF);
+        assert_eq!(cpu.read_memory(0x04FF), 0x0002);
+        assert!(!state.interrupts_enabled);
+        assert_eq!(state.saved_flags, state.flags);
+        assert!(state.flags.carry && state.flags.equal);
+
+        cpu.do_fetch_decode_execute();
+        cpu.check_flags_register(false, true, false, false);
+
+        // IRET brings them back and turns interrupts on
+        cpu.do_fetch_decode_execute();
+        cpu.check_iar(0x0002);
+        cpu.check_sp(0x0500);
+        cpu.check_flags_register(true, false, true, true);
+        assert!(cpu.state().interrupts_enabled);
+    }
+
+    // an interrupt request the test raises and lowers by hand
+    struct TestLine {
+        request: bool,
+    }
+
+    impl Peripheral for TestLine {
+        fn connect(&mut self, _: Arc<Mutex<IOBus>>, _: Arc<Mutex<Bus>>) {}
+
+        fn update(&mut self) {}
+
+        fn irq(&self) -> bool {
+            self.request
+        }
+    }
+
+    #[test]
+    fn test_cpu_hardware_interrupt() {
+        let mut cpu = get_cpu();
+        let line = Arc::new(Mutex::new(TestLine { request: true }));
+        cpu.connect_interrupt_controller(line.clone());
+
+        let program = [
+            0x0170, // SETIV R0
+            0x0081, // ADD R0, R1
+            0x0164, // EI
+            0x0081, // ADD R0, R1, displaced by the interrupt
+            0x0081, // ADD R0, R1
+        ];
+        for (i, word) in program.iter().enumerate() {
+            set_memory_location(cpu.memory.clone(), i as u16, *word);
+        }
+        set_memory_location(cpu.memory.clone(), 0x0040, 0x0160); // IRET
+        cpu.set_iar(0x0000);
+        cpu.set_stack_pointer(0x0500);
+        cpu.set_registers(vec![0x0040, 0x0000, 0x0000, 0x0000]);
+
+        // the line is up, but interrupts are off until EI
+        for _ in 0..3 {
+            cpu.do_fetch_decode_execute();
+        }
+        cpu.check_registers(0x0164, 0x0040, 0x0040, 0x0000, 0x0000);
+        cpu.check_iar(0x0003);
+
+        // the fetch takes the interrupt instead of the ADD at 0x0003
+        cpu.do_fetch_decode_execute();
+        cpu.check_ir(0x0178);
+        cpu.check_iar(0x0040);
+        cpu.check_registers(0x0178, 0x0040, 0x0040, 0x0000, 0x0000);
+        assert_eq!(cpu.read_memory(0x04FF), 0x0003);
+        assert!(!cpu.state().interrupts_enabled);
+
+        line.lock().unwrap().request = false;
+        cpu.do_fetch_decode_execute();
+        cpu.check_iar(0x0003);
+
+        for _ in 0..2 {
+            cpu.do_fetch_decode_execute();
+        }
+        cpu.check_registers(0x0081, 0x0040, 0x00C0, 0x0000, 0x0000);
+        cpu.check_iar(0x0005);
+        cpu.check_sp(0x0500);
+    }
+
+    #[test]
+    fn test_cpu_halt() {
+        let mut cpu = get_cpu();
+        let timer = Arc::new(Mutex::new(Timer::new(1)));
+        cpu.connect_interrupt_controller(timer.clone());
+        cpu.test_instruction(
+            0x0180,
+            vec![0x0001, 0x0002, 0x0003, 0x0004],
+            vec![0x0001, 0x0002, 0x0003, 0x0004],
+        );
+        cpu.check_iar(0x0001);
+        assert!(cpu.state().halted);
+        let state = cpu.state();
+
+        // the stepper stays on step 6, no cycle starts and the timer stops
+        timer.lock().unwrap().acknowledge();
+        for _ in 0..12 {
+            cpu.step();
+        }
+        assert!(cpu.stepper.get_output_wire(5));
+        assert!(!timer.lock().unwrap().irq());
+        assert_eq!(cpu.state(), state);
+    }
+
     #[test]
     fn test_cpu_io_input_instruction() {}
 
@@ -2424,6 +3559,13 @@ mod tests {
             )
         }
 
+        // run one instruction from address without touching the registers
+        fn test_instruction_at(&mut self, instruction: u16, address: u16) {
+            self.set_cpu_memory_location(address, instruction);
+            self.set_iar(address);
+            self.do_fetch_decode_execute();
+        }
+
         fn test_shift(
             &mut self,
             instruction: u16,
@@ -2570,6 +3712,16 @@ mod tests {
             )
         }
 
+        fn check_sp(&self, exp_value: u16) {
+            assert_eq!(
+                self.sp.value(),
+                exp_value,
+                "Expected SP to have value of: {:#X} but got {:#X}",
+                exp_value,
+                self.sp.value()
+            )
+        }
+
         fn check_ir(&self, exp_value: u16) {
             assert_eq!(
                 self.ir.value(),
@@ -1,13 +1,85 @@
 use crate::computer::{
     components::Decoder3x8,
     gates::{AND, NOT},
+    io::Peripheral,
+};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex},
 };
 
 mod alu;
+mod behavioral;
 mod cpu;
+mod state;
 
 use alu::ALU;
+pub use behavioral::BehavioralCPU;
 pub use cpu::CPU;
+pub use state::{CpuState, Flags};
+
+// Core - an execution engine for the ISA below, either the gate-level CPU
+// or the instruction-level BehavioralCPU
+pub trait Core: CoreClone + Display + Send {
+    // advance one step of the stepper, six steps make one instruction
+    fn step(&mut self);
+    fn set_iar(&mut self, address: u16);
+    fn set_stack_pointer(&mut self, value: u16);
+    // register is 0-3 for R0-R3
+    fn set_register(&mut self, register: usize, value: u16);
+    fn state(&self) -> CpuState;
+    // load every register and the flags, only meant for instruction boundaries
+    fn set_state(&mut self, state: &CpuState);
+    fn read_memory(&self, address: u16) -> u16;
+    fn write_memory(&mut self, address: u16, value: u16);
+    // (address, value) of the loads and stores since the last take, once
+    // log_accesses has been called
+    fn log_accesses(&mut self);
+    fn take_reads(&mut self) -> Vec<(u16, u16)>;
+    fn take_writes(&mut self) -> Vec<(u16, u16)>;
+    fn connect_peripheral(&mut self, p: Arc<Mutex<dyn Peripheral>>);
+    // connects p and drives the interrupt line from its irq
+    fn connect_interrupt_controller(&mut self, p: Arc<Mutex<dyn Peripheral>>);
+}
+
+pub trait CoreClone {
+    fn clone_box(&self) -> Box<dyn Core>;
+}
+
+impl<T> CoreClone for T
+where
+    T: 'static + Core + Clone,
+{
+    fn clone_box(&self) -> Box<dyn Core> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Core> {
+    fn clone(&self) -> Box<dyn Core> {
+        self.clone_box()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoreKind {
+    #[default]
+    Gate,
+    Behavioral,
+}
+
+impl FromStr for CoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gate" => Ok(CoreKind::Gate),
+            "behavioral" => Ok(CoreKind::Behavioral),
+            _ => Err(format!("expected gate or behavioral, found '{}'", s)),
+        }
+    }
+}
 
 pub enum FlagState {
     Carry = 0,
@@ -293,3 +365,73 @@ impl InstructionDecoder3x8 {
 // 0x00FD = CMP R3, R1
 // 0x00FE = CMP R3, R2
 // 0x00FF = CMP R3, R3
+
+// STACK
+// IR bit 0x0100 selects the stack instructions, they move the CPU's stack
+// pointer without going through the ALU and leave ACC and the flags alone.
+// The stack grows down and SP holds the address of the last word pushed.
+// Other 0x01xx words, including those with the ALU bit 0x0080 set, do nothing
+// ----------------------
+// 0x0100 = HPUSH R0
+// 0x0101 = HPUSH R1
+// 0x0102 = HPUSH R2
+// 0x0103 = HPUSH R3
+
+// 0x0110 = HPOP R0
+// 0x0111 = HPOP R1
+// 0x0112 = HPOP R2
+// 0x0113 = HPOP R3
+
+// push the address of the next instruction and jump to the register
+// 0x0120 = HCALL R0
+// 0x0121 = HCALL R1
+// 0x0122 = HCALL R2
+// 0x0123 = HCALL R3
+
+// pop the return address into IAR
+// 0x0130 = HRET
+
+// 0x0140 = SETSP R0
+// 0x0141 = SETSP R1
+// 0x0142 = SETSP R2
+// 0x0143 = SETSP R3
+
+// 0x0150 = GETSP R0
+// 0x0151 = GETSP R1
+// 0x0152 = GETSP R2
+// 0x0153 = GETSP R3
+
+// INTERRUPTS
+// At the start of every cycle, with interrupts enabled and the interrupt
+// controller requesting, the fetch loads 0x0178 into IR instead of the word
+// at IAR and leaves IAR pointing at it. INT pushes IAR, copies the flags to
+// the saved flags register, turns interrupts off and jumps to IV. Interrupts
+// are off at power on
+//
+// An interrupt can land partway through the expansion of PUSH, POP, CALL, RET,
+// MUL, DIV and the other pseudo-instructions, between a load of the software
+// stack pointer and the store of its new value, or with a register parked in
+// STACK_SCRATCH. A handler therefore saves every register it changes, R3
+// included, with HPUSH and HPOP, calls only with HCALL and HRET, and never
+// uses PUSH, POP, CALL, RET, MUL or DIV or touches STACK_POINTER and
+// STACK_SCRATCH. IRET restores the flags
+// ----------------------
+// pop IAR, restore the saved flags and turn interrupts back on
+// 0x0160 = IRET
+
+// 0x0164 = EI
+// 0x0168 = DI
+
+// set the interrupt vector
+// 0x0170 = SETIV R0
+// 0x0171 = SETIV R1
+// 0x0172 = SETIV R2
+// 0x0173 = SETIV R3
+
+// 0x0178 = INT
+
+// HALT
+// The stepper stops at the end of the instruction and the CPU takes no more
+// interrupts, R0 is the exit status
+// ----------------------
+// 0x0180 = HALT
@@ -0,0 +1,59 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    pub carry: bool,
+    pub a_larger: bool,
+    pub equal: bool,
+    pub zero: bool,
+}
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "C: {} A: {} E: {} Z: {}",
+            self.carry as i32, self.a_larger as i32, self.equal as i32, self.zero as i32
+        )
+    }
+}
+
+// CpuState - register values visible at an instruction boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuState {
+    pub registers: [u16; 4],
+    pub iar: u16,
+    pub ir: u16,
+    pub acc: u16,
+    pub tmp: u16,
+    pub sp: u16,
+    pub iv: u16,
+    pub flags: Flags,
+    pub saved_flags: Flags,
+    pub interrupts_enabled: bool,
+    // stopped by HALT
+    pub halted: bool,
+}
+
+impl Display for CpuState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[IAR]: {:>#06X} [IR]: {:>#06X} [ACC]: {:>#06X} [TMP]: {:>#06X} [SP]: {:>#06X} [IV]: {:>#06X}\n[R0]: {:>#06X} [R1]: {:>#06X} [R2]: {:>#06X} [R3]: {:>#06X}\n[FLAGS]: {} [IFLAGS]: {} IE: {} HALT: {}",
+            self.iar,
+            self.ir,
+            self.acc,
+            self.tmp,
+            self.sp,
+            self.iv,
+            self.registers[0],
+            self.registers[1],
+            self.registers[2],
+            self.registers[3],
+            self.flags,
+            self.saved_flags,
+            self.interrupts_enabled as i32,
+            self.halted as i32,
+        )
+    }
+}
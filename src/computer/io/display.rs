@@ -27,6 +27,15 @@ pub struct DisplayAdapter {
     display_ram_set_gate: ANDGate5,
 }
 
+// DisplayState - what the display adapter keeps between OUT instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayState {
+    pub input_address: u16,
+    pub write_to_ram: bool,
+    pub active: bool,
+    pub cells: Vec<u16>,
+}
+
 impl DisplayAdapter {
     pub fn new() -> Self {
         DisplayAdapter {
@@ -55,6 +64,38 @@ impl DisplayAdapter {
         }
     }
 
+    pub fn state(&self) -> DisplayState {
+        let display_ram = self.display_ram.as_ref();
+        DisplayState {
+            input_address: display_ram
+                .map(|ram| ram.input_address_register.value())
+                .unwrap_or_default(),
+            write_to_ram: self.write_to_ram.get(),
+            active: self.display_adapter_active_bit.get(),
+            cells: display_ram.map(|ram| ram.cells()).unwrap_or_default(),
+        }
+    }
+
+    // the adapter has to be connected, the display RAM only exists from then
+    pub fn set_state(&mut self, state: &DisplayState) {
+        let display_ram = self.display_ram.as_mut().unwrap();
+        display_ram.load_cells(&state.cells);
+
+        let bus = display_ram.input_bus.clone();
+        let saved = bus.lock().unwrap().get_value();
+        bus.lock().unwrap().set_value(state.input_address);
+        display_ram.input_address_register.set();
+        display_ram.input_address_register.update();
+        display_ram.input_address_register.unset();
+        display_ram.input_address_register.update();
+        bus.lock().unwrap().set_value(saved);
+
+        self.write_to_ram.update(state.write_to_ram, true);
+        self.write_to_ram.update(state.write_to_ram, false);
+        self.display_adapter_active_bit.update(state.active, true);
+        self.display_adapter_active_bit.update(state.active, false);
+    }
+
     fn toggle_write_to_ram(&mut self) {
         self.write_to_ram_toggle_gate
             .update(self.write_to_ram.get());
@@ -145,26 +186,30 @@ impl Peripheral for DisplayAdapter {
         self.address_select_not_gates[3].update(self.main_bus.lock().unwrap().get_output_wire(11));
         self.address_select_not_gates[4].update(self.main_bus.lock().unwrap().get_output_wire(12));
 
-        let main_bus = self.main_bus.clone();
-        let main_bus = main_bus.lock().unwrap();
-        self.address_select_and_gate.update(
-            self.address_select_not_gates[0].get(),
-            self.address_select_not_gates[1].get(),
-            self.address_select_not_gates[2].get(),
-            self.address_select_not_gates[3].get(),
-            self.address_select_not_gates[4].get(),
-            main_bus.get_output_wire(13),
-            main_bus.get_output_wire(14),
-            main_bus.get_output_wire(15),
-        );
+        // the bus locks must be released before writing, the display RAM
+        // reads from the main bus and the write checks the io bus again
+        {
+            let main_bus = self.main_bus.clone();
+            let main_bus = main_bus.lock().unwrap();
+            self.address_select_and_gate.update(
+                self.address_select_not_gates[0].get(),
+                self.address_select_not_gates[1].get(),
+                self.address_select_not_gates[2].get(),
+                self.address_select_not_gates[3].get(),
+                self.address_select_not_gates[4].get(),
+                main_bus.get_output_wire(13),
+                main_bus.get_output_wire(14),
+                main_bus.get_output_wire(15),
+            );
 
-        let io_bus = self.io_bus.clone();
-        let io_bus = io_bus.lock().unwrap();
-        self.is_address_output_mode_gate.update(
-            io_bus.is_set(),
-            io_bus.is_address_mode(),
-            io_bus.is_output_mode(),
-        );
+            let io_bus = self.io_bus.clone();
+            let io_bus = io_bus.lock().unwrap();
+            self.is_address_output_mode_gate.update(
+                io_bus.is_set(),
+                io_bus.is_address_mode(),
+                io_bus.is_output_mode(),
+            );
+        }
 
         self.display_adapter_active_bit.update(
             self.address_select_and_gate.get(),
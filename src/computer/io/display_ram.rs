@@ -7,14 +7,16 @@ use std::sync::{Arc, Mutex};
 
 // Display RAM is special as the writes (inputs) and reads (outputs) are two separate
 // units that operate independently.
+// the decoders are boxed, built inline they overflow the 2MB stack of a
+// spawned thread when the display adapter gets connected
 pub struct DisplayRAM {
     pub input_address_register: Register,
-    input_row_decoder: Decoder8x256,
-    input_col_decoder: Decoder8x256,
+    input_row_decoder: Box<Decoder8x256>,
+    input_col_decoder: Box<Decoder8x256>,
 
     pub output_address_register: Register,
-    pub output_row_decoder: Decoder8x256,
-    pub output_col_decoder: Decoder8x256,
+    pub output_row_decoder: Box<Decoder8x256>,
+    pub output_col_decoder: Box<Decoder8x256>,
 
     data: Vec<Vec<Cell>>,
     set: Wire,
@@ -25,14 +27,14 @@ pub struct DisplayRAM {
 
 impl DisplayRAM {
     pub fn new(input_bus: Arc<Mutex<Bus>>, output_bus: Arc<Mutex<Bus>>) -> Self {
-        Self {
+        let mut res = Self {
             input_address_register: Register::new("IMAR", input_bus.clone(), output_bus.clone()),
-            input_row_decoder: Decoder8x256::new(),
-            input_col_decoder: Decoder8x256::new(),
+            input_row_decoder: Box::new(Decoder8x256::new()),
+            input_col_decoder: Box::new(Decoder8x256::new()),
 
             output_address_register: Register::new("OMAR", input_bus.clone(), output_bus.clone()),
-            output_row_decoder: Decoder8x256::new(),
-            output_col_decoder: Decoder8x256::new(),
+            output_row_decoder: Box::new(Decoder8x256::new()),
+            output_col_decoder: Box::new(Decoder8x256::new()),
             // 0xF0 x 0xA0
             data: (0..256)
                 .map(|_| {
@@ -45,7 +47,38 @@ impl DisplayRAM {
             enable: Wire::new("E".to_string(), false),
             input_bus: input_bus.clone(),
             output_bus: output_bus.clone(),
+        };
+
+        // settled like Memory64K, a cell reads the same before and after the
+        // screen first enables it
+        for row in res.data.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.update(false, false);
+            }
+        }
+        res
+    }
+
+    // every cell, row by row
+    pub fn cells(&self) -> Vec<u16> {
+        self.data
+            .iter()
+            .flat_map(|row| row.iter().map(|cell| cell.value()))
+            .collect()
+    }
+
+    // store cells as returned by cells, the input bus keeps its value
+    pub fn load_cells(&mut self, values: &[u16]) {
+        let saved = self.input_bus.lock().unwrap().get_value();
+        for (i, value) in values.iter().enumerate() {
+            let cell = &mut self.data[i / 256][i % 256];
+            if cell.value() != *value {
+                self.input_bus.lock().unwrap().set_value(*value);
+                cell.update(true, false);
+                cell.update(false, false);
+            }
         }
+        self.input_bus.lock().unwrap().set_value(saved);
     }
 
     pub fn enable(&mut self) {
@@ -0,0 +1,268 @@
+use super::Peripheral;
+use crate::computer::{
+    components::{
+        ANDGate3, ANDGate4, ANDGate8, Bit, Bus, Component, IOBus, Register, Settable, Updatable,
+        BUS_WIDTH,
+    },
+    gates::NOT,
+};
+use std::sync::{Arc, Mutex};
+
+// the request line of each source is the bit with that number in the mask
+pub const IRQ_TIMER: u16 = 0;
+pub const IRQ_KEYBOARD: u16 = 1;
+pub const IRQ_SERIAL: u16 = 2;
+
+// [cpu] <-------------> interrupt controller <----------- sources
+//         read/write                           irq
+// OUT Addr 0x0010 selects the controller, OUT Data then loads the mask and
+// IN Data reads the masked requests, which acknowledges them
+pub struct InterruptController {
+    io_bus: Arc<Mutex<IOBus>>,
+    main_bus: Arc<Mutex<Bus>>,
+    sources: Vec<Arc<Mutex<dyn Peripheral>>>,
+
+    selected_bit: Bit,
+    mask_register: Register,
+
+    address_select_and_gate: ANDGate8,
+    address_select_not_gates: [NOT; 7],
+    is_address_output_mode_gate: ANDGate3,
+    mask_set_gate: ANDGate4,
+    read_gate: ANDGate4,
+}
+
+// InterruptState - the mask and whether the controller is selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InterruptState {
+    pub mask: u16,
+    pub selected: bool,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self {
+            io_bus: Arc::new(Mutex::new(IOBus::new())),
+            main_bus: Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+            sources: Vec::new(),
+            selected_bit: Bit::new(),
+            mask_register: Register::new(
+                "IMR",
+                Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+                Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+            ),
+            address_select_and_gate: ANDGate8::new(),
+            address_select_not_gates: (0..7)
+                .map(|_| NOT::new())
+                .collect::<Vec<NOT>>()
+                .try_into()
+                .unwrap(),
+            is_address_output_mode_gate: ANDGate3::new(),
+            mask_set_gate: ANDGate4::new(),
+            read_gate: ANDGate4::new(),
+        }
+    }
+
+    // sources are numbered in the order they are added
+    pub fn add_source(&mut self, source: Arc<Mutex<dyn Peripheral>>) -> u16 {
+        if self.sources.len() == BUS_WIDTH as usize {
+            panic!("the interrupt controller has {} request lines", BUS_WIDTH);
+        }
+        self.sources.push(source);
+        self.sources.len() as u16 - 1
+    }
+
+    pub fn state(&self) -> InterruptState {
+        InterruptState {
+            mask: self.mask_register.value(),
+            selected: self.selected_bit.get(),
+        }
+    }
+
+    pub fn set_state(&mut self, state: &InterruptState) {
+        let saved = self.main_bus.lock().unwrap().get_value();
+        self.main_bus.lock().unwrap().set_value(state.mask);
+        self.load_mask();
+        self.main_bus.lock().unwrap().set_value(saved);
+
+        self.selected_bit.update(state.selected, true);
+        self.selected_bit.update(state.selected, false);
+    }
+
+    // the requests the mask lets through, bit n for source n
+    pub fn pending(&self) -> u16 {
+        let mask = self.mask_register.value();
+        self.sources
+            .iter()
+            .enumerate()
+            .filter(|(i, source)| mask & (1 << i) != 0 && source.lock().unwrap().irq())
+            .fold(0, |pending, (i, _)| pending | (1 << i))
+    }
+
+    fn load_mask(&mut self) {
+        self.mask_register.set();
+        self.mask_register.update();
+        self.mask_register.unset();
+        self.mask_register.update();
+    }
+
+    fn read_pending(&mut self) {
+        let pending = self.pending();
+        self.main_bus.lock().unwrap().set_value(pending);
+
+        for (i, source) in self.sources.iter().enumerate() {
+            if pending & (1 << i) != 0 {
+                source.lock().unwrap().acknowledge();
+            }
+        }
+    }
+}
+
+impl Peripheral for InterruptController {
+    fn connect(&mut self, io_bus: Arc<Mutex<IOBus>>, main_bus: Arc<Mutex<Bus>>) {
+        self.io_bus = io_bus;
+        self.main_bus = main_bus;
+
+        self.selected_bit.update(false, true);
+        self.selected_bit.update(false, false);
+        self.mask_register = Register::new(
+            "IMR",
+            self.main_bus.clone(),
+            Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+        );
+
+        // every source starts masked
+        let saved = self.main_bus.lock().unwrap().get_value();
+        self.main_bus.lock().unwrap().set_value(0x0000);
+        self.load_mask();
+        self.main_bus.lock().unwrap().set_value(saved);
+    }
+
+    fn update(&mut self) {
+        // check if bus = 0x0010
+        {
+            let main_bus = self.main_bus.lock().unwrap();
+            for (gate, wire) in [8, 9, 10, 12, 13, 14, 15].into_iter().enumerate() {
+                self.address_select_not_gates[gate].update(main_bus.get_output_wire(wire));
+            }
+            self.address_select_and_gate.update(
+                self.address_select_not_gates[0].get(),
+                self.address_select_not_gates[1].get(),
+                self.address_select_not_gates[2].get(),
+                main_bus.get_output_wire(11),
+                self.address_select_not_gates[3].get(),
+                self.address_select_not_gates[4].get(),
+                self.address_select_not_gates[5].get(),
+                self.address_select_not_gates[6].get(),
+            );
+        }
+
+        let (mask_set, read) = {
+            let io_bus = self.io_bus.lock().unwrap();
+            self.is_address_output_mode_gate.update(
+                io_bus.is_set(),
+                io_bus.is_address_mode(),
+                io_bus.is_output_mode(),
+            );
+            self.selected_bit.update(
+                self.address_select_and_gate.get(),
+                self.is_address_output_mode_gate.get(),
+            );
+
+            self.mask_set_gate.update(
+                io_bus.is_set(),
+                io_bus.is_data_mode(),
+                io_bus.is_output_mode(),
+                self.selected_bit.get(),
+            );
+            self.read_gate.update(
+                io_bus.is_enable(),
+                io_bus.is_data_mode(),
+                io_bus.is_input_mode(),
+                self.selected_bit.get(),
+            );
+            (self.mask_set_gate.get(), self.read_gate.get())
+        };
+
+        if mask_set {
+            self.load_mask();
+        }
+        if read {
+            self.read_pending();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.pending() != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::{components::Enableable, io::Timer};
+
+    fn out(
+        controller: &mut InterruptController,
+        io_bus: &Arc<Mutex<IOBus>>,
+        bus: &Arc<Mutex<Bus>>,
+        address: bool,
+        value: u16,
+    ) {
+        io_bus.lock().unwrap().update(true, address);
+        bus.lock().unwrap().set_value(value);
+        io_bus.lock().unwrap().set();
+        controller.update();
+        io_bus.lock().unwrap().unset();
+        controller.update();
+        bus.lock().unwrap().set_value(0);
+        controller.update();
+    }
+
+    fn input(
+        controller: &mut InterruptController,
+        io_bus: &Arc<Mutex<IOBus>>,
+        bus: &Arc<Mutex<Bus>>,
+    ) -> u16 {
+        io_bus.lock().unwrap().update(false, false);
+        io_bus.lock().unwrap().enable();
+        controller.update();
+        io_bus.lock().unwrap().disable();
+        controller.update();
+        let value = bus.lock().unwrap().get_value();
+        bus.lock().unwrap().set_value(0);
+        value
+    }
+
+    #[test]
+    fn test_interrupt_controller() {
+        let io_bus = Arc::new(Mutex::new(IOBus::new()));
+        let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let timer = Arc::new(Mutex::new(Timer::new(1)));
+        let idle = Arc::new(Mutex::new(Timer::new(0)));
+
+        let mut controller = InterruptController::new();
+        assert_eq!(controller.add_source(timer.clone()), 0);
+        assert_eq!(controller.add_source(idle.clone()), 1);
+        controller.connect(io_bus.clone(), main_bus.clone());
+
+        // a request is held back until its mask bit is set
+        timer.lock().unwrap().tick();
+        assert!(!controller.irq());
+
+        out(&mut controller, &io_bus, &main_bus, true, 0x0010);
+        out(&mut controller, &io_bus, &main_bus, false, 0x0003);
+        assert_eq!(controller.state().mask, 0x0003);
+        assert!(controller.irq());
+
+        assert_eq!(input(&mut controller, &io_bus, &main_bus), 0x0001);
+        assert!(!timer.lock().unwrap().irq());
+        assert!(!controller.irq());
+
+        // selecting another device leaves the mask alone
+        out(&mut controller, &io_bus, &main_bus, true, 0x0007);
+        out(&mut controller, &io_bus, &main_bus, false, 0x0000);
+        assert!(!controller.state().selected);
+        assert_eq!(controller.state().mask, 0x0003);
+    }
+}
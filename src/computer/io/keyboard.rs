@@ -1,3 +1,4 @@
+use super::Peripheral;
 use crate::computer::{
     components::{
         ANDGate3, ANDGate8, Bit, Bus, Component, Enableable, IOBus, Mode, Register, Settable,
@@ -29,6 +30,14 @@ pub struct KeyboardAdapter {
     and_gate4: AND,
 }
 
+// KeyboardState - the key waiting on the keyboard bus and whether the adapter
+// is selected, the key code register is cleared as soon as it is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyboardState {
+    pub pending: u16,
+    pub selected: bool,
+}
+
 impl KeyboardAdapter {
     pub fn new() -> Self {
         Self {
@@ -58,6 +67,40 @@ impl KeyboardAdapter {
         }
     }
 
+    pub fn state(&self) -> KeyboardState {
+        KeyboardState {
+            pending: self.keyboard_in_bus.lock().unwrap().get_value(),
+            selected: self.memory_bit.get(),
+        }
+    }
+
+    pub fn set_state(&mut self, state: &KeyboardState) {
+        self.keyboard_in_bus
+            .lock()
+            .unwrap()
+            .set_value(state.pending);
+        self.memory_bit.update(state.selected, true);
+        self.memory_bit.update(state.selected, false);
+    }
+
+    fn update_key_code_reg(&mut self) {
+        if self.and_gate4.get() {
+            self.key_code_register.set();
+
+            self.key_code_register.enable();
+            self.key_code_register.update();
+            self.key_code_register.disable();
+
+            // clear the register once everything is out
+            self.keyboard_in_bus.lock().unwrap().set_value(0x00);
+            self.key_code_register.update();
+            self.key_code_register.unset();
+            self.key_code_register.update();
+        }
+    }
+}
+
+impl Peripheral for KeyboardAdapter {
     fn connect(&mut self, io_bus: Arc<Mutex<IOBus>>, main_bus: Arc<Mutex<Bus>>) {
         self.io_bus = io_bus;
         self.main_bus = main_bus;
@@ -110,20 +153,9 @@ impl KeyboardAdapter {
             .update(self.memory_bit.get(), self.and_gate3.get());
     }
 
-    fn update_key_code_reg(&mut self) {
-        if self.and_gate4.get() {
-            self.key_code_register.set();
-
-            self.key_code_register.enable();
-            self.key_code_register.update();
-            self.key_code_register.disable();
-
-            // clear the register once everything is out
-            self.keyboard_in_bus.lock().unwrap().set_value(0x00);
-            self.key_code_register.update();
-            self.key_code_register.unset();
-            self.key_code_register.update();
-        }
+    // a key waits on the keyboard bus until IN reads it
+    fn irq(&self) -> bool {
+        self.keyboard_in_bus.lock().unwrap().get_value() != 0
     }
 }
 
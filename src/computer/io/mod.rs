@@ -3,12 +3,27 @@ use std::sync::{Arc, Mutex};
 
 mod display;
 mod display_ram;
+mod interrupts;
 mod keyboard;
+mod timer;
+mod uart;
 
-pub use display::{DisplayAdapter, ScreenControl};
-pub use keyboard::{KeyPress, Keyboard, KeyboardAdapter};
+pub use display::{DisplayAdapter, DisplayState, ScreenControl};
+pub use interrupts::{InterruptController, InterruptState, IRQ_KEYBOARD, IRQ_SERIAL, IRQ_TIMER};
+pub use keyboard::{KeyPress, Keyboard, KeyboardAdapter, KeyboardState};
+pub use timer::{Timer, TimerState, TIMER_PERIOD, TIMER_REPEAT, TIMER_RUN};
+pub use uart::{SerialHost, Uart, UartState, UART_RX_READY, UART_TX_READY};
 
 pub trait Peripheral: Send {
     fn connect(&mut self, io_bus: Arc<Mutex<IOBus>>, bus: Arc<Mutex<Bus>>);
     fn update(&mut self);
+    // once at the start of every instruction cycle, before the CPU looks at
+    // its interrupt line
+    fn tick(&mut self) {}
+    // the interrupt request line, held until the cause is dealt with
+    fn irq(&self) -> bool {
+        false
+    }
+    // the interrupt controller reported the request to the CPU
+    fn acknowledge(&mut self) {}
 }
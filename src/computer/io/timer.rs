@@ -0,0 +1,335 @@
+use super::Peripheral;
+use crate::computer::{
+    components::{ANDGate3, ANDGate4, ANDGate8, Bit, Bus, Component, IOBus, BUS_WIDTH},
+    gates::NOT,
+};
+use std::sync::{Arc, Mutex};
+
+// mode bits, a timer that runs without repeating stops when it expires
+pub const TIMER_RUN: u16 = 0x0001;
+pub const TIMER_REPEAT: u16 = 0x0002;
+
+// instruction cycles between two requests of the timer the computer powers
+// on with
+pub const TIMER_PERIOD: u16 = 1024;
+
+// [cpu] <-------------> timer
+//         read/write
+// Timer - counts instruction cycles down from its reload value and raises its
+// interrupt request every time the count runs out.
+// OUT Addr 0x0011 selects the reload value, OUT Data then loads it and IN Data
+// reads the current count. OUT Addr 0x0012 selects the mode, OUT Data then
+// loads it and restarts the count from the reload value
+#[derive(Clone)]
+pub struct Timer {
+    io_bus: Arc<Mutex<IOBus>>,
+    main_bus: Arc<Mutex<Bus>>,
+    reload: u16,
+    count: u16,
+    mode: u16,
+    expired: bool,
+
+    reload_selected_bit: Bit,
+    mode_selected_bit: Bit,
+
+    address_select_not_gates: [NOT; 7],
+    reload_address_and_gate: ANDGate8,
+    mode_address_and_gate: ANDGate8,
+    is_address_output_mode_gate: ANDGate3,
+    reload_set_gate: ANDGate4,
+    mode_set_gate: ANDGate4,
+    read_gate: ANDGate4,
+}
+
+// TimerState - the programmed reload value and mode, cycles left until the
+// next request, whether one is waiting and which register is selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimerState {
+    pub reload: u16,
+    pub count: u16,
+    pub mode: u16,
+    pub expired: bool,
+    pub reload_selected: bool,
+    pub mode_selected: bool,
+}
+
+impl Timer {
+    // a running, repeating timer, a period of 0 leaves it stopped
+    pub fn new(period: u16) -> Self {
+        Self {
+            io_bus: Arc::new(Mutex::new(IOBus::new())),
+            main_bus: Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+            reload: period,
+            count: period,
+            mode: match period {
+                0 => 0,
+                _ => TIMER_RUN | TIMER_REPEAT,
+            },
+            expired: false,
+            reload_selected_bit: Bit::new(),
+            mode_selected_bit: Bit::new(),
+            address_select_not_gates: (0..7)
+                .map(|_| NOT::new())
+                .collect::<Vec<NOT>>()
+                .try_into()
+                .unwrap(),
+            reload_address_and_gate: ANDGate8::new(),
+            mode_address_and_gate: ANDGate8::new(),
+            is_address_output_mode_gate: ANDGate3::new(),
+            reload_set_gate: ANDGate4::new(),
+            mode_set_gate: ANDGate4::new(),
+            read_gate: ANDGate4::new(),
+        }
+    }
+
+    pub fn state(&self) -> TimerState {
+        TimerState {
+            reload: self.reload,
+            count: self.count,
+            mode: self.mode,
+            expired: self.expired,
+            reload_selected: self.reload_selected_bit.get(),
+            mode_selected: self.mode_selected_bit.get(),
+        }
+    }
+
+    pub fn set_state(&mut self, state: &TimerState) {
+        self.reload = state.reload;
+        self.count = state.count;
+        self.mode = state.mode;
+        self.expired = state.expired;
+
+        self.reload_selected_bit.update(state.reload_selected, true);
+        self.reload_selected_bit
+            .update(state.reload_selected, false);
+        self.mode_selected_bit.update(state.mode_selected, true);
+        self.mode_selected_bit.update(state.mode_selected, false);
+    }
+}
+
+impl Peripheral for Timer {
+    fn connect(&mut self, io_bus: Arc<Mutex<IOBus>>, main_bus: Arc<Mutex<Bus>>) {
+        self.io_bus = io_bus;
+        self.main_bus = main_bus;
+
+        self.reload_selected_bit.update(false, true);
+        self.reload_selected_bit.update(false, false);
+        self.mode_selected_bit.update(false, true);
+        self.mode_selected_bit.update(false, false);
+    }
+
+    fn update(&mut self) {
+        // check if bus = 0x0011 or 0x0012
+        let value = {
+            let main_bus = self.main_bus.lock().unwrap();
+            for (gate, wire) in [8, 9, 10, 12, 13, 14, 15].into_iter().enumerate() {
+                self.address_select_not_gates[gate].update(main_bus.get_output_wire(wire));
+            }
+            self.reload_address_and_gate.update(
+                self.address_select_not_gates[0].get(),
+                self.address_select_not_gates[1].get(),
+                self.address_select_not_gates[2].get(),
+                main_bus.get_output_wire(11),
+                self.address_select_not_gates[3].get(),
+                self.address_select_not_gates[4].get(),
+                self.address_select_not_gates[5].get(),
+                main_bus.get_output_wire(15),
+            );
+            self.mode_address_and_gate.update(
+                self.address_select_not_gates[0].get(),
+                self.address_select_not_gates[1].get(),
+                self.address_select_not_gates[2].get(),
+                main_bus.get_output_wire(11),
+                self.address_select_not_gates[3].get(),
+                self.address_select_not_gates[4].get(),
+                main_bus.get_output_wire(14),
+                self.address_select_not_gates[6].get(),
+            );
+            main_bus.get_value()
+        };
+
+        let (reload_set, mode_set, read) = {
+            let io_bus = self.io_bus.lock().unwrap();
+            self.is_address_output_mode_gate.update(
+                io_bus.is_set(),
+                io_bus.is_address_mode(),
+                io_bus.is_output_mode(),
+            );
+            self.reload_selected_bit.update(
+                self.reload_address_and_gate.get(),
+                self.is_address_output_mode_gate.get(),
+            );
+            self.mode_selected_bit.update(
+                self.mode_address_and_gate.get(),
+                self.is_address_output_mode_gate.get(),
+            );
+
+            self.reload_set_gate.update(
+                io_bus.is_set(),
+                io_bus.is_data_mode(),
+                io_bus.is_output_mode(),
+                self.reload_selected_bit.get(),
+            );
+            self.mode_set_gate.update(
+                io_bus.is_set(),
+                io_bus.is_data_mode(),
+                io_bus.is_output_mode(),
+                self.mode_selected_bit.get(),
+            );
+            self.read_gate.update(
+                io_bus.is_enable(),
+                io_bus.is_data_mode(),
+                io_bus.is_input_mode(),
+                self.reload_selected_bit.get(),
+            );
+            (
+                self.reload_set_gate.get(),
+                self.mode_set_gate.get(),
+                self.read_gate.get(),
+            )
+        };
+
+        if reload_set {
+            self.reload = value;
+        }
+        if mode_set {
+            self.mode = value;
+            self.count = self.reload;
+        }
+        if read {
+            self.main_bus.lock().unwrap().set_value(self.count);
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.mode & TIMER_RUN == 0 {
+            return;
+        }
+
+        // a count of 0 fires on the next tick
+        self.count = self.count.saturating_sub(1);
+        if self.count == 0 {
+            self.expired = true;
+            match self.mode & TIMER_REPEAT {
+                0 => self.mode &= !TIMER_RUN,
+                _ => self.count = self.reload,
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.expired
+    }
+
+    fn acknowledge(&mut self) {
+        self.expired = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::components::{Enableable, Settable};
+
+    #[test]
+    fn test_timer() {
+        let mut timer = Timer::new(3);
+        timer.tick();
+        timer.tick();
+        assert!(!timer.irq());
+        timer.tick();
+        assert!(timer.irq());
+
+        // the request stays up until the controller reports it, the count
+        // carries on in the meantime
+        timer.tick();
+        assert!(timer.irq());
+        timer.acknowledge();
+        assert!(!timer.irq());
+        timer.tick();
+        assert!(!timer.irq());
+        timer.tick();
+        assert!(timer.irq());
+
+        let mut stopped = Timer::new(0);
+        for _ in 0..10 {
+            stopped.tick();
+        }
+        assert!(!stopped.irq());
+    }
+
+    #[test]
+    fn test_timer_adapter() {
+        let io_bus = Arc::new(Mutex::new(IOBus::new()));
+        let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let mut timer = Timer::new(0);
+        timer.connect(io_bus.clone(), main_bus.clone());
+
+        // OUT Addr 0x0011, OUT Data 0x0002
+        main_bus.lock().unwrap().set_value(0x0011);
+        timer.update();
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, true);
+        timer.update();
+        io_bus.lock().unwrap().unset();
+        timer.update();
+
+        main_bus.lock().unwrap().set_value(0x0002);
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, false);
+        timer.update();
+        io_bus.lock().unwrap().unset();
+        timer.update();
+        assert_eq!(timer.state().reload, 0x0002);
+
+        // OUT Addr 0x0012, OUT Data TIMER_RUN
+        main_bus.lock().unwrap().set_value(0x0012);
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, true);
+        timer.update();
+        io_bus.lock().unwrap().unset();
+        timer.update();
+        assert!(timer.state().mode_selected);
+        assert!(!timer.state().reload_selected);
+
+        main_bus.lock().unwrap().set_value(TIMER_RUN);
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, false);
+        timer.update();
+        io_bus.lock().unwrap().unset();
+        timer.update();
+        assert_eq!(timer.state().mode, TIMER_RUN);
+        assert_eq!(timer.state().count, 0x0002);
+
+        // IN Data reads the count at 0x0011 only
+        io_bus.lock().unwrap().enable();
+        io_bus.lock().unwrap().update(false, false);
+        timer.update();
+        io_bus.lock().unwrap().disable();
+        assert_eq!(main_bus.lock().unwrap().get_value(), TIMER_RUN);
+
+        main_bus.lock().unwrap().set_value(0x0011);
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, true);
+        timer.update();
+        io_bus.lock().unwrap().unset();
+        timer.update();
+
+        timer.tick();
+        main_bus.lock().unwrap().set_value(0x0000);
+        io_bus.lock().unwrap().enable();
+        io_bus.lock().unwrap().update(false, false);
+        timer.update();
+        io_bus.lock().unwrap().disable();
+        assert_eq!(main_bus.lock().unwrap().get_value(), 0x0001);
+
+        // a timer that does not repeat stops once it runs out
+        assert!(!timer.irq());
+        timer.tick();
+        assert!(timer.irq());
+        assert_eq!(timer.state().mode, 0x0000);
+        timer.acknowledge();
+        timer.tick();
+        assert!(!timer.irq());
+    }
+}
@@ -0,0 +1,384 @@
+use super::Peripheral;
+use crate::computer::{
+    components::{ANDGate3, ANDGate4, ANDGate8, Bit, Bus, Component, IOBus, BUS_WIDTH},
+    gates::NOT,
+};
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+// status register bits
+pub const UART_RX_READY: u16 = 0x0001;
+pub const UART_TX_READY: u16 = 0x0002;
+
+enum SerialOutput {
+    Memory(Vec<u8>),
+    Writer(Box<dyn Write + Send>),
+}
+
+// SerialHost - the far end of the serial line, clones share the line
+#[derive(Clone)]
+pub struct SerialHost {
+    received: Arc<Mutex<VecDeque<u8>>>,
+    sent: Arc<Mutex<SerialOutput>>,
+}
+
+impl SerialHost {
+    // both directions stay in memory, send and take_sent play the host
+    pub fn memory() -> Self {
+        Self {
+            received: Arc::new(Mutex::new(VecDeque::new())),
+            sent: Arc::new(Mutex::new(SerialOutput::Memory(Vec::new()))),
+        }
+    }
+
+    // stdin feeds the line from a thread, sent bytes go to stdout
+    pub fn stdio() -> Self {
+        let host = Self {
+            received: Arc::new(Mutex::new(VecDeque::new())),
+            sent: Arc::new(Mutex::new(SerialOutput::Writer(
+                Box::new(std::io::stdout()),
+            ))),
+        };
+        host.spawn_reader(std::io::stdin());
+        host
+    }
+
+    // a client of the Unix socket listening at path
+    #[cfg(unix)]
+    pub fn unix_socket(path: &std::path::Path) -> std::io::Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        let host = Self {
+            received: Arc::new(Mutex::new(VecDeque::new())),
+            sent: Arc::new(Mutex::new(SerialOutput::Writer(Box::new(
+                stream.try_clone()?,
+            )))),
+        };
+        host.spawn_reader(stream);
+        Ok(host)
+    }
+
+    // queue bytes for the program to receive
+    pub fn send(&self, bytes: &[u8]) {
+        self.received.lock().unwrap().extend(bytes);
+    }
+
+    // what the program sent since the last take, always empty unless the
+    // line is in memory
+    pub fn take_sent(&self) -> Vec<u8> {
+        match &mut *self.sent.lock().unwrap() {
+            SerialOutput::Memory(bytes) => std::mem::take(bytes),
+            SerialOutput::Writer(_) => Vec::new(),
+        }
+    }
+
+    fn spawn_reader(&self, mut reader: impl Read + Send + 'static) {
+        let received = self.received.clone();
+        thread::spawn(move || {
+            let mut buffer = [0; 256];
+            while let Ok(n) = reader.read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                received.lock().unwrap().extend(&buffer[..n]);
+            }
+        });
+    }
+
+    fn has_received(&self) -> bool {
+        !self.received.lock().unwrap().is_empty()
+    }
+
+    fn receive(&self) -> Option<u8> {
+        self.received.lock().unwrap().pop_front()
+    }
+
+    fn transmit(&self, byte: u8) {
+        match &mut *self.sent.lock().unwrap() {
+            SerialOutput::Memory(bytes) => bytes.push(byte),
+            // a host that went away drops the byte
+            SerialOutput::Writer(writer) => {
+                let _ = writer.write_all(&[byte]).and_then(|_| writer.flush());
+            }
+        }
+    }
+}
+
+// [cpu] <-------------> uart <-------------> serial host
+//         read/write          bytes
+// OUT Addr 0x0013 selects the data register, OUT Data then sends the low
+// byte and IN Data reads the next received byte, 0 when nothing arrived.
+// OUT Addr 0x0014 selects the status register for IN Data
+#[derive(Clone)]
+pub struct Uart {
+    io_bus: Arc<Mutex<IOBus>>,
+    main_bus: Arc<Mutex<Bus>>,
+    host: SerialHost,
+
+    data_selected_bit: Bit,
+    status_selected_bit: Bit,
+    // the read and write gates one update ago, a byte only moves when they
+    // turn on
+    reading: bool,
+    writing: bool,
+    received_value: u16,
+
+    address_select_not_gates: [NOT; 7],
+    data_address_and_gate: ANDGate8,
+    status_address_and_gate: ANDGate8,
+    is_address_output_mode_gate: ANDGate3,
+    write_gate: ANDGate4,
+    read_data_gate: ANDGate4,
+    read_status_gate: ANDGate4,
+}
+
+// UartState - which register is selected, the bytes on the line belong to
+// the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UartState {
+    pub data_selected: bool,
+    pub status_selected: bool,
+}
+
+impl Uart {
+    pub fn new(host: SerialHost) -> Self {
+        Self {
+            io_bus: Arc::new(Mutex::new(IOBus::new())),
+            main_bus: Arc::new(Mutex::new(Bus::new(BUS_WIDTH))),
+            host,
+            data_selected_bit: Bit::new(),
+            status_selected_bit: Bit::new(),
+            reading: false,
+            writing: false,
+            received_value: 0,
+            address_select_not_gates: (0..7)
+                .map(|_| NOT::new())
+                .collect::<Vec<NOT>>()
+                .try_into()
+                .unwrap(),
+            data_address_and_gate: ANDGate8::new(),
+            status_address_and_gate: ANDGate8::new(),
+            is_address_output_mode_gate: ANDGate3::new(),
+            write_gate: ANDGate4::new(),
+            read_data_gate: ANDGate4::new(),
+            read_status_gate: ANDGate4::new(),
+        }
+    }
+
+    // move the line to another host, bytes not yet received stay behind
+    pub fn attach(&mut self, host: SerialHost) {
+        self.host = host;
+    }
+
+    pub fn state(&self) -> UartState {
+        UartState {
+            data_selected: self.data_selected_bit.get(),
+            status_selected: self.status_selected_bit.get(),
+        }
+    }
+
+    pub fn set_state(&mut self, state: &UartState) {
+        self.data_selected_bit.update(state.data_selected, true);
+        self.data_selected_bit.update(state.data_selected, false);
+        self.status_selected_bit.update(state.status_selected, true);
+        self.status_selected_bit
+            .update(state.status_selected, false);
+    }
+
+    fn status(&self) -> u16 {
+        match self.host.has_received() {
+            true => UART_RX_READY | UART_TX_READY,
+            false => UART_TX_READY,
+        }
+    }
+}
+
+impl Peripheral for Uart {
+    fn connect(&mut self, io_bus: Arc<Mutex<IOBus>>, main_bus: Arc<Mutex<Bus>>) {
+        self.io_bus = io_bus;
+        self.main_bus = main_bus;
+
+        self.data_selected_bit.update(false, true);
+        self.data_selected_bit.update(false, false);
+        self.status_selected_bit.update(false, true);
+        self.status_selected_bit.update(false, false);
+        self.reading = false;
+        self.writing = false;
+    }
+
+    fn update(&mut self) {
+        // check if bus = 0x0013 or 0x0014
+        let value = {
+            let main_bus = self.main_bus.lock().unwrap();
+            for (gate, wire) in [8, 9, 10, 12, 13, 14, 15].into_iter().enumerate() {
+                self.address_select_not_gates[gate].update(main_bus.get_output_wire(wire));
+            }
+            self.data_address_and_gate.update(
+                self.address_select_not_gates[0].get(),
+                self.address_select_not_gates[1].get(),
+                self.address_select_not_gates[2].get(),
+                main_bus.get_output_wire(11),
+                self.address_select_not_gates[3].get(),
+                self.address_select_not_gates[4].get(),
+                main_bus.get_output_wire(14),
+                main_bus.get_output_wire(15),
+            );
+            self.status_address_and_gate.update(
+                self.address_select_not_gates[0].get(),
+                self.address_select_not_gates[1].get(),
+                self.address_select_not_gates[2].get(),
+                main_bus.get_output_wire(11),
+                self.address_select_not_gates[3].get(),
+                main_bus.get_output_wire(13),
+                self.address_select_not_gates[5].get(),
+                self.address_select_not_gates[6].get(),
+            );
+            main_bus.get_value()
+        };
+
+        let (write, read_data, read_status) = {
+            let io_bus = self.io_bus.lock().unwrap();
+            self.is_address_output_mode_gate.update(
+                io_bus.is_set(),
+                io_bus.is_address_mode(),
+                io_bus.is_output_mode(),
+            );
+            self.data_selected_bit.update(
+                self.data_address_and_gate.get(),
+                self.is_address_output_mode_gate.get(),
+            );
+            self.status_selected_bit.update(
+                self.status_address_and_gate.get(),
+                self.is_address_output_mode_gate.get(),
+            );
+
+            self.write_gate.update(
+                io_bus.is_set(),
+                io_bus.is_data_mode(),
+                io_bus.is_output_mode(),
+                self.data_selected_bit.get(),
+            );
+            self.read_data_gate.update(
+                io_bus.is_enable(),
+                io_bus.is_data_mode(),
+                io_bus.is_input_mode(),
+                self.data_selected_bit.get(),
+            );
+            self.read_status_gate.update(
+                io_bus.is_enable(),
+                io_bus.is_data_mode(),
+                io_bus.is_input_mode(),
+                self.status_selected_bit.get(),
+            );
+            (
+                self.write_gate.get(),
+                self.read_data_gate.get(),
+                self.read_status_gate.get(),
+            )
+        };
+
+        if write && !self.writing {
+            self.host.transmit(value as u8);
+        }
+        if read_data && !self.reading {
+            self.received_value = self.host.receive().unwrap_or(0) as u16;
+        }
+        if read_data {
+            self.main_bus.lock().unwrap().set_value(self.received_value);
+        }
+        if read_status {
+            self.main_bus.lock().unwrap().set_value(self.status());
+        }
+        self.writing = write;
+        self.reading = read_data;
+    }
+
+    // a byte waits on the line until IN reads it
+    fn irq(&self) -> bool {
+        self.host.has_received()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::components::{Enableable, Settable};
+
+    #[test]
+    fn test_uart_adapter() {
+        let io_bus = Arc::new(Mutex::new(IOBus::new()));
+        let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let host = SerialHost::memory();
+        let mut uart = Uart::new(host.clone());
+        uart.connect(io_bus.clone(), main_bus.clone());
+
+        // OUT Addr 0x0014, IN Data
+        main_bus.lock().unwrap().set_value(0x0014);
+        uart.update();
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, true);
+        uart.update();
+        io_bus.lock().unwrap().unset();
+        uart.update();
+
+        main_bus.lock().unwrap().set_value(0x0000);
+        io_bus.lock().unwrap().enable();
+        io_bus.lock().unwrap().update(false, false);
+        uart.update();
+        io_bus.lock().unwrap().disable();
+        uart.update();
+        assert_eq!(main_bus.lock().unwrap().get_value(), UART_TX_READY);
+
+        host.send(b"hi");
+        assert!(uart.irq());
+        main_bus.lock().unwrap().set_value(0x0000);
+        io_bus.lock().unwrap().enable();
+        uart.update();
+        io_bus.lock().unwrap().disable();
+        uart.update();
+        assert_eq!(
+            main_bus.lock().unwrap().get_value(),
+            UART_RX_READY | UART_TX_READY
+        );
+
+        // OUT Addr 0x0013, the byte stays on the bus for as long as IN
+        // enables the adapter
+        main_bus.lock().unwrap().set_value(0x0013);
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, true);
+        uart.update();
+        io_bus.lock().unwrap().unset();
+        uart.update();
+        assert!(uart.state().data_selected);
+
+        main_bus.lock().unwrap().set_value(0x0000);
+        io_bus.lock().unwrap().enable();
+        io_bus.lock().unwrap().update(false, false);
+        uart.update();
+        uart.update();
+        io_bus.lock().unwrap().disable();
+        uart.update();
+        assert_eq!(main_bus.lock().unwrap().get_value(), b'h' as u16);
+
+        io_bus.lock().unwrap().enable();
+        uart.update();
+        io_bus.lock().unwrap().disable();
+        uart.update();
+        assert_eq!(main_bus.lock().unwrap().get_value(), b'i' as u16);
+        assert!(!uart.irq());
+
+        // OUT Data sends the low byte once
+        main_bus.lock().unwrap().set_value(0x0121);
+        io_bus.lock().unwrap().set();
+        io_bus.lock().unwrap().update(true, false);
+        uart.update();
+        uart.update();
+        io_bus.lock().unwrap().unset();
+        uart.update();
+        assert_eq!(host.take_sent(), b"!");
+        assert!(host.take_sent().is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use super::{
+    components::{Bus, BUS_WIDTH},
+    cpu::{BehavioralCPU, Core, CpuState, CPU},
+    memory::Memory64K,
+};
+use crate::assembler::Disassembler;
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+const CONTEXT_LINES: usize = 4;
+
+// Lockstep - runs the gate-level CPU next to the instruction-level reference
+// model and compares them after every instruction
+pub struct Lockstep {
+    origin: u16,
+    size: u16,
+    gate: CPU,
+    memory: Arc<Mutex<Memory64K>>,
+    reference: BehavioralCPU,
+    cycles: u64,
+}
+
+// Divergence - the first instruction after which the two models disagree
+#[derive(Debug)]
+pub struct Divergence {
+    pub cycle: u64,
+    pub address: u16,
+    pub gate: CpuState,
+    pub reference: CpuState,
+    pub gate_writes: Vec<(u16, u16)>,
+    pub reference_writes: Vec<(u16, u16)>,
+    pub context: String,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "diverged in cycle {} at 0x{:>04X}",
+            self.cycle, self.address
+        )?;
+
+        let (g, r) = (&self.gate, &self.reference);
+        let mut fields = vec![("IAR", g.iar, r.iar), ("IR", g.ir, r.ir)];
+        for i in 0..4 {
+            fields.push((["R0", "R1", "R2", "R3"][i], g.registers[i], r.registers[i]));
+        }
+        fields.push(("ACC", g.acc, r.acc));
+        fields.push(("SP", g.sp, r.sp));
+        fields.push(("IV", g.iv, r.iv));
+
+        for (name, gate, reference) in fields {
+            if gate != reference {
+                writeln!(
+                    f,
+                    "\t{}: gate {:>#06X} reference {:>#06X}",
+                    name, gate, reference
+                )?;
+            }
+        }
+        if g.flags != r.flags {
+            writeln!(f, "\tFLAGS: gate {} reference {}", g.flags, r.flags)?;
+        }
+        if g.saved_flags != r.saved_flags {
+            writeln!(
+                f,
+                "\tIFLAGS: gate {} reference {}",
+                g.saved_flags, r.saved_flags
+            )?;
+        }
+        if g.interrupts_enabled != r.interrupts_enabled {
+            writeln!(
+                f,
+                "\tIE: gate {} reference {}",
+                g.interrupts_enabled, r.interrupts_enabled
+            )?;
+        }
+        if g.halted != r.halted {
+            writeln!(f, "\tHALT: gate {} reference {}", g.halted, r.halted)?;
+        }
+        if self.gate_writes != self.reference_writes {
+            writeln!(
+                f,
+                "\twrites: gate {} reference {}",
+                format_writes(&self.gate_writes),
+                format_writes(&self.reference_writes)
+            )?;
+        }
+
+        write!(f, "{}", self.context)
+    }
+}
+
+fn format_writes(writes: &[(u16, u16)]) -> String {
+    let writes: Vec<String> = writes
+        .iter()
+        .map(|(address, value)| format!("[{:>#06X}] = {:>#06X}", address, value))
+        .collect();
+    format!("{{{}}}", writes.join(", "))
+}
+
+impl Lockstep {
+    pub fn new(origin: u16, program: &[u16]) -> Self {
+        let main_bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let memory = Arc::new(Mutex::new(Memory64K::new(main_bus.clone())));
+        let mut gate = CPU::new(main_bus, memory.clone());
+        let mut reference = BehavioralCPU::new();
+
+        for (i, value) in program.iter().enumerate() {
+            gate.write_memory(origin.wrapping_add(i as u16), *value);
+            reference.write_memory(origin.wrapping_add(i as u16), *value);
+        }
+        gate.set_iar(origin);
+        reference.set_iar(origin);
+
+        memory.lock().unwrap().log_writes();
+        reference.log_writes();
+
+        Self {
+            origin,
+            size: program.len() as u16,
+            gate,
+            memory,
+            reference,
+            cycles: 0,
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // run one instruction on both models
+    pub fn step(&mut self) -> Result<(), Box<Divergence>> {
+        let address = self.reference.state().iar;
+        for _ in 0..6 {
+            self.gate.step();
+            self.reference.step();
+        }
+        self.cycles += 1;
+
+        let gate = self.gate.state();
+        let reference = self.reference.state();
+        let gate_writes = self.memory.lock().unwrap().take_writes();
+        let reference_writes = self.reference.take_writes();
+
+        if same(&gate, &reference) && gate_writes == reference_writes {
+            return Ok(());
+        }
+
+        Err(Box::new(Divergence {
+            cycle: self.cycles,
+            address,
+            gate,
+            reference,
+            gate_writes,
+            reference_writes,
+            context: self.context(address),
+        }))
+    }
+
+    pub fn run(&mut self, cycles: u64) -> Result<(), Box<Divergence>> {
+        for _ in 0..cycles {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    fn context(&self, address: u16) -> String {
+        // prefer the program as loaded so instructions stay aligned, and fall
+        // back to the memory around the address once execution leaves it
+        let words: Vec<u16> = (0..self.size)
+            .map(|i| self.reference.read_memory(self.origin.wrapping_add(i)))
+            .collect();
+        let listing =
+            Disassembler::new().listing_around(self.origin, &words, address, CONTEXT_LINES);
+        if !listing.is_empty() {
+            return listing;
+        }
+
+        let start = address.saturating_sub(CONTEXT_LINES as u16);
+        let words: Vec<u16> = (0..CONTEXT_LINES as u16 * 3)
+            .map(|i| self.reference.read_memory(start.wrapping_add(i)))
+            .collect();
+        Disassembler::new().listing_around(start, &words, address, CONTEXT_LINES)
+    }
+}
+
+// TMP is internal to the ALU and not part of the comparison
+fn same(gate: &CpuState, reference: &CpuState) -> bool {
+    gate.iar == reference.iar
+        && gate.ir == reference.ir
+        && gate.registers == reference.registers
+        && gate.acc == reference.acc
+        && gate.sp == reference.sp
+        && gate.flags == reference.flags
+        && gate.iv == reference.iv
+        && gate.saved_flags == reference.saved_flags
+        && gate.interrupts_enabled == reference.interrupts_enabled
+        && gate.halted == reference.halted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generator::get_instructions, Assembler, USER_CODE_START};
+
+    #[test]
+    fn test_lockstep_bundled_programs() {
+        for program in ["ascii", "brush", "text_writer", "me"] {
+            let bin = Assembler::new()
+                .process(USER_CODE_START, get_instructions(program))
+                .unwrap();
+
+            let mut lockstep = Lockstep::new(USER_CODE_START, &bin);
+            if let Err(divergence) = lockstep.run(300) {
+                panic!("{}: {}", program, divergence);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lockstep_divergence() {
+        let bin = vec![
+            0x0020, 0x0600, // DATA R0, 0x0600
+            0x0021, 0x0005, // DATA R1, 0x0005
+            0x0011, // ST R0, R1
+            0x0085, // ADD R1, R1
+        ];
+
+        let mut lockstep = Lockstep::new(USER_CODE_START, &bin);
+        // only the reference sees the changed operand
+        lockstep.reference.write_memory(0x0503, 0x0007);
+
+        let divergence = lockstep.run(10).unwrap_err();
+        assert_eq!(divergence.cycle, 2);
+        assert_eq!(divergence.address, 0x0502);
+        assert_eq!(divergence.gate.registers[1], 0x0005);
+        assert_eq!(divergence.reference.registers[1], 0x0007);
+        assert_eq!(
+            divergence.to_string(),
+            "diverged in cycle 2 at 0x0502\n\tR1: gate 0x0005 reference 0x0007\n\t0x0500:\t{32 1536}\t\tDATA R0, 0x0600\n=>\t0x0502:\t{33 7}\t\tDATA R1, 0x0007\n\t0x0504:\t{17}\t\t\tST R0, R1\n\t0x0505:\t{133}\t\t\tADD R1, R1\n"
+        );
+
+        // a different store shows up in the write log
+        let mut lockstep = Lockstep::new(USER_CODE_START, &bin);
+        lockstep.reference.write_memory(0x0504, 0x0012);
+
+        let divergence = lockstep.run(10).unwrap_err();
+        assert_eq!(divergence.cycle, 3);
+        assert_eq!(divergence.gate_writes, vec![(0x0600, 0x0005)]);
+        assert_eq!(divergence.reference_writes, vec![(0x0600, 0xFFFF)]);
+    }
+}
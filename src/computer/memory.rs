@@ -53,11 +53,14 @@ pub struct Memory64K {
     set: Wire,
     enable: Wire,
     pub bus: Arc<Mutex<Bus>>,
+    // (address, value) of every store and load while logging is on
+    write_log: Option<Vec<(u16, u16)>>,
+    read_log: Option<Vec<(u16, u16)>>,
 }
 
 impl Memory64K {
     pub fn new(bus: Arc<Mutex<Bus>>) -> Self {
-        Self {
+        let mut res = Self {
             address_register: Register::new("MAR", bus.clone(), bus.clone()),
             row_decoder: Decoder8x256::new(),
             col_decoder: Decoder8x256::new(),
@@ -71,7 +74,55 @@ impl Memory64K {
             set: Wire::new("S".to_string(), false),
             enable: Wire::new("E".to_string(), false),
             bus,
+            write_log: None,
+            read_log: None,
+        };
+
+        // cells latch high on their first update, settle them all at power on
+        // so peek agrees with what a read returns
+        for row in res.data.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.update(false, false);
+            }
         }
+        res
+    }
+
+    // read a cell directly, without going through MAR and the bus
+    pub fn peek(&self, address: u16) -> u16 {
+        let (row, col) = Self::cell_index(address);
+        self.data[row][col].value()
+    }
+
+    pub fn log_writes(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    pub fn take_writes(&mut self) -> Vec<(u16, u16)> {
+        self.write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn log_reads(&mut self) {
+        self.read_log = Some(Vec::new());
+    }
+
+    // a read may be logged more than once while RAM stays enabled
+    pub fn take_reads(&mut self) -> Vec<(u16, u16)> {
+        self.read_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // the 8x256 decoders select one of 16 4x16 decoders with the low nibble
+    // of their input byte, so each byte of the address lands nibble swapped
+    fn cell_index(address: u16) -> (usize, usize) {
+        let row = ((address >> 8) as u8).rotate_left(4);
+        let col = (address as u8).rotate_left(4);
+        (row as usize, col as usize)
     }
 }
 
@@ -99,8 +150,16 @@ impl Updatable for Memory64K {
             self.address_register.bit(15),
         );
 
-        self.data[self.row_decoder.index() as usize][self.col_decoder.index() as usize]
-            .update(self.set.get(), self.enable.get());
+        let cell =
+            &mut self.data[self.row_decoder.index() as usize][self.col_decoder.index() as usize];
+        cell.update(self.set.get(), self.enable.get());
+
+        if let (true, Some(log)) = (self.set.get(), self.write_log.as_mut()) {
+            log.push((self.address_register.value(), cell.value()));
+        }
+        if let (true, Some(log)) = (self.enable.get(), self.read_log.as_mut()) {
+            log.push((self.address_register.value(), cell.value()));
+        }
     }
 }
 
@@ -233,4 +292,33 @@ mod tests {
             expected -= 1;
         }
     }
+
+    #[test]
+    fn test_memory_64k_peek() {
+        let bus = Arc::new(Mutex::new(Bus::new(BUS_WIDTH)));
+        let mut mem = Memory64K::new(bus.clone());
+
+        let addresses = [
+            0x0000, 0x0001, 0x0010, 0x0100, 0x1000, 0x1234, 0xFEFE, 0xFFFF,
+        ];
+        for address in addresses.iter() {
+            mem.address_register.set();
+            bus.lock().unwrap().set_value(*address);
+            mem.update();
+
+            mem.address_register.unset();
+            mem.update();
+
+            bus.lock().unwrap().set_value(!*address);
+            mem.set();
+            mem.update();
+
+            mem.unset();
+            mem.update();
+        }
+
+        for address in addresses.iter() {
+            assert_eq!(mem.peek(*address), !*address, "at {:#06X}", address);
+        }
+    }
 }
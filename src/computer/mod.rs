@@ -4,7 +4,15 @@ mod components;
 mod cpu;
 mod gates;
 mod io;
+mod lockstep;
 mod memory;
+mod snapshot;
 
-pub use computer::{Computer, PrintStateConfig};
-pub use io::{KeyPress, Keyboard};
+pub use computer::{
+    Computer, HeadlessConfig, LoadError, PrintStateConfig, RunLimit, RunReport, StopReason,
+    WatchKind,
+};
+pub use cpu::{CoreKind, CpuState, Flags};
+pub use io::{KeyPress, Keyboard, SerialHost};
+pub use lockstep::{Divergence, Lockstep};
+pub use snapshot::{Snapshot, SnapshotError};
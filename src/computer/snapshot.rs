@@ -0,0 +1,406 @@
+use super::{
+    cpu::{CpuState, Flags},
+    io::{
+        DisplayState, InterruptState, KeyboardState, TimerState, UartState, TIMER_PERIOD,
+        TIMER_REPEAT, TIMER_RUN,
+    },
+};
+use std::{fs, path::Path};
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"CSNP";
+const VERSION: u16 = 6;
+const WORDS: usize = 0x10000;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Not a snapshot file")]
+    BadMagic,
+
+    #[error("Unsupported snapshot version: {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("Snapshot ends early")]
+    Truncated,
+
+    #[error("Snapshots are taken between instructions, the stepper is at step {0} of 6")]
+    MidInstruction(u64),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+// Snapshot - the whole machine between two instructions. Version 6 layout,
+// all little endian:
+//   "CSNP", version u16, stepper ticks u64,
+//   R0-R3 IAR IR ACC TMP SP as u16, flags u8 (C 1, A 2, E 4, Z 8),
+//   IV u16, saved flags u8, interrupts enabled u8, halted u8,
+//   64K words of RAM,
+//   display input address u16, write to RAM u8, active u8, 64K display cells,
+//   pending key u16, keyboard selected u8,
+//   interrupt mask u16, interrupt controller selected u8,
+//   timer count u16, timer expired u8, timer reload u16, timer mode u16,
+//   timer reload selected u8, timer mode selected u8,
+//   serial data selected u8, serial status selected u8
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub steps: u64,
+    pub state: CpuState,
+    pub memory: Vec<u16>,
+    pub display: DisplayState,
+    pub keyboard: KeyboardState,
+    pub interrupts: InterruptState,
+    pub timer: TimerState,
+    pub serial: UartState,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(self.steps.to_le_bytes());
+
+        let state = &self.state;
+        for value in state
+            .registers
+            .iter()
+            .chain(&[state.iar, state.ir, state.acc, state.tmp, state.sp])
+        {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.push(flags_to_byte(&state.flags));
+        bytes.extend(state.iv.to_le_bytes());
+        bytes.push(flags_to_byte(&state.saved_flags));
+        bytes.push(state.interrupts_enabled as u8);
+        bytes.push(state.halted as u8);
+
+        write_words(&mut bytes, &self.memory);
+
+        bytes.extend(self.display.input_address.to_le_bytes());
+        bytes.push(self.display.write_to_ram as u8);
+        bytes.push(self.display.active as u8);
+        write_words(&mut bytes, &self.display.cells);
+
+        bytes.extend(self.keyboard.pending.to_le_bytes());
+        bytes.push(self.keyboard.selected as u8);
+
+        bytes.extend(self.interrupts.mask.to_le_bytes());
+        bytes.push(self.interrupts.selected as u8);
+        bytes.extend(self.timer.count.to_le_bytes());
+        bytes.push(self.timer.expired as u8);
+        bytes.extend(self.timer.reload.to_le_bytes());
+        bytes.extend(self.timer.mode.to_le_bytes());
+        bytes.push(self.timer.reload_selected as u8);
+        bytes.push(self.timer.mode_selected as u8);
+        bytes.push(self.serial.data_selected as u8);
+        bytes.push(self.serial.status_selected as u8);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.u16()?;
+        // version 1 predates the stack pointer, version 2 the interrupts,
+        // version 3 HALT, version 4 the programmable timer and version 5 the
+        // serial console
+        if !(1..=VERSION).contains(&version) {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let steps = reader.u64()?;
+        if steps % 6 != 0 {
+            return Err(SnapshotError::MidInstruction(steps % 6 + 1));
+        }
+
+        let mut registers = [0; 4];
+        for register in registers.iter_mut() {
+            *register = reader.u16()?;
+        }
+        let (iar, ir, acc, tmp) = (reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?);
+        let sp = match version {
+            1 => 0xFFFF,
+            _ => reader.u16()?,
+        };
+        let flags = flags_from_byte(reader.u8()?);
+        let (iv, saved_flags, interrupts_enabled) = match version {
+            1 | 2 => (0xFFFF, Flags::default(), false),
+            _ => (
+                reader.u16()?,
+                flags_from_byte(reader.u8()?),
+                reader.u8()? != 0,
+            ),
+        };
+        let halted = match version {
+            1..=3 => false,
+            _ => reader.u8()? != 0,
+        };
+        let state = CpuState {
+            registers,
+            iar,
+            ir,
+            acc,
+            tmp,
+            sp,
+            iv,
+            flags,
+            saved_flags,
+            interrupts_enabled,
+            halted,
+        };
+
+        let memory = reader.words(WORDS)?;
+        let display = DisplayState {
+            input_address: reader.u16()?,
+            write_to_ram: reader.u8()? != 0,
+            active: reader.u8()? != 0,
+            cells: reader.words(WORDS)?,
+        };
+        let keyboard = KeyboardState {
+            pending: reader.u16()?,
+            selected: reader.u8()? != 0,
+        };
+        // older timers ran at the power on period
+        let mut timer = TimerState {
+            reload: TIMER_PERIOD,
+            mode: TIMER_RUN | TIMER_REPEAT,
+            ..TimerState::default()
+        };
+        let interrupts = match version {
+            1 | 2 => InterruptState::default(),
+            _ => {
+                let interrupts = InterruptState {
+                    mask: reader.u16()?,
+                    selected: reader.u8()? != 0,
+                };
+                timer.count = reader.u16()?;
+                timer.expired = reader.u8()? != 0;
+                interrupts
+            }
+        };
+        if version >= 5 {
+            timer.reload = reader.u16()?;
+            timer.mode = reader.u16()?;
+            timer.reload_selected = reader.u8()? != 0;
+            timer.mode_selected = reader.u8()? != 0;
+        }
+        let serial = match version {
+            1..=5 => UartState::default(),
+            _ => UartState {
+                data_selected: reader.u8()? != 0,
+                status_selected: reader.u8()? != 0,
+            },
+        };
+
+        Ok(Self {
+            steps,
+            state,
+            memory,
+            display,
+            keyboard,
+            interrupts,
+            timer,
+            serial,
+        })
+    }
+}
+
+fn flags_to_byte(flags: &Flags) -> u8 {
+    flags.carry as u8
+        | (flags.a_larger as u8) << 1
+        | (flags.equal as u8) << 2
+        | (flags.zero as u8) << 3
+}
+
+fn flags_from_byte(byte: u8) -> Flags {
+    Flags {
+        carry: byte & 1 != 0,
+        a_larger: byte & 2 != 0,
+        equal: byte & 4 != 0,
+        zero: byte & 8 != 0,
+    }
+}
+
+fn write_words(bytes: &mut Vec<u8>, words: &[u16]) {
+    // a display that was never connected has no cells, store it blank
+    for i in 0..WORDS {
+        bytes.extend(words.get(i).copied().unwrap_or(0xFFFF).to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or(SnapshotError::Truncated)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn words(&mut self, count: usize) -> Result<Vec<u16>, SnapshotError> {
+        (0..count).map(|_| self.u16()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_bytes() {
+        let mut memory = vec![0xFFFF; WORDS];
+        memory[0x0500] = 0x0020;
+        let snapshot = Snapshot {
+            steps: 42,
+            state: CpuState {
+                registers: [1, 2, 3, 4],
+                iar: 0x0502,
+                ir: 0x0020,
+                acc: 0x0502,
+                tmp: 0,
+                sp: 0xFEFD,
+                iv: 0x0600,
+                flags: Flags {
+                    carry: true,
+                    a_larger: false,
+                    equal: true,
+                    zero: false,
+                },
+                saved_flags: Flags {
+                    carry: false,
+                    a_larger: true,
+                    equal: false,
+                    zero: true,
+                },
+                interrupts_enabled: true,
+                halted: true,
+            },
+            memory,
+            display: DisplayState {
+                input_address: 0x0010,
+                write_to_ram: true,
+                active: true,
+                cells: vec![0x00FF; WORDS],
+            },
+            keyboard: KeyboardState {
+                pending: 0x0041,
+                selected: false,
+            },
+            interrupts: InterruptState {
+                mask: 0x0003,
+                selected: true,
+            },
+            timer: TimerState {
+                reload: 0x0200,
+                count: 0x0123,
+                mode: TIMER_RUN,
+                expired: true,
+                reload_selected: false,
+                mode_selected: true,
+            },
+            serial: UartState {
+                data_selected: true,
+                status_selected: false,
+            },
+        };
+
+        let bytes = snapshot.to_bytes();
+        assert_eq!(&bytes[..6], b"CSNP\x06\x00");
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), snapshot);
+
+        // a version 5 file has no serial console
+        let mut old = bytes.clone();
+        old[4] = 5;
+        old.truncate(old.len() - 2);
+        let loaded = Snapshot::from_bytes(&old).unwrap();
+        assert_eq!(loaded.serial, UartState::default());
+        assert_eq!(loaded.timer, snapshot.timer);
+
+        // a version 4 timer always ran at the power on period
+        old[4] = 4;
+        old.truncate(old.len() - 6);
+        let loaded = Snapshot::from_bytes(&old).unwrap();
+        assert_eq!(loaded.timer.count, snapshot.timer.count);
+        assert_eq!(loaded.timer.reload, TIMER_PERIOD);
+        assert_eq!(loaded.timer.mode, TIMER_RUN | TIMER_REPEAT);
+
+        // a version 3 file was never halted
+        old[4] = 3;
+        old.remove(37);
+        let loaded = Snapshot::from_bytes(&old).unwrap();
+        assert!(!loaded.state.halted);
+        assert_eq!(loaded.timer.count, snapshot.timer.count);
+
+        // a version 2 file has no interrupt state, it loads as powered on
+        old[4] = 2;
+        old.truncate(old.len() - 6);
+        old.drain(33..37);
+        let loaded = Snapshot::from_bytes(&old).unwrap();
+        assert_eq!(loaded.state.iv, 0xFFFF);
+        assert!(!loaded.state.interrupts_enabled);
+        assert_eq!(loaded.interrupts, InterruptState::default());
+        assert_eq!(loaded.keyboard, snapshot.keyboard);
+
+        // and a version 1 file has no stack pointer either
+        old[4] = 1;
+        old.drain(30..32);
+        let loaded = Snapshot::from_bytes(&old).unwrap();
+        assert_eq!(loaded.state.sp, 0xFFFF);
+        assert_eq!(loaded.memory, snapshot.memory);
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            Snapshot::from_bytes(&bad),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        let mut bad = bytes.clone();
+        bad[4] = 7;
+        assert!(matches!(
+            Snapshot::from_bytes(&bad),
+            Err(SnapshotError::UnsupportedVersion(7))
+        ));
+
+        let mut bad = bytes.clone();
+        bad[6] = 43;
+        assert!(matches!(
+            Snapshot::from_bytes(&bad),
+            Err(SnapshotError::MidInstruction(2))
+        ));
+
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated)
+        ));
+    }
+}
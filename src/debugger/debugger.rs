@@ -0,0 +1,465 @@
+use crate::{
+    assembler::Disassembler,
+    computer::{Computer, Snapshot, StopReason, WatchKind},
+    parse_address,
+};
+use std::{collections::HashMap, path::Path};
+
+const HELP: &str = "step [n]            run n instructions (s)
+stepi [n]           run n stepper ticks (si)
+continue            run until a breakpoint, a watchpoint or 1000000 cycles (c)
+break <addr|label>  stop before the instruction at addr, no argument lists them (b)
+watch <addr|label>  stop after a store to addr, rwatch and awatch for loads or both
+regs                show the CPU registers
+x/<n> <addr|label>  examine n words of memory
+disas [addr|label]  disassemble around addr, IAR by default
+set <reg> <value>   set R0-R3 or IAR
+save <file>         write a snapshot of the machine
+restore <file>      continue from a snapshot
+quit                leave the debugger (q)";
+
+// continue gives control back after this many cycles, a program that never
+// reaches a breakpoint would otherwise hang the session
+const CONTINUE_CYCLES: u64 = 1_000_000;
+
+// Debugger - gdb style commands over a Computer
+pub struct Debugger {
+    computer: Computer,
+    origin: u16,
+    size: u16,
+    labels: HashMap<String, u16>,
+    continue_cycles: u64,
+}
+
+impl Debugger {
+    // origin and size describe the loaded program, labels come from the
+    // Assembler that built it
+    pub fn new(computer: Computer, origin: u16, size: u16, labels: HashMap<String, u16>) -> Self {
+        Self {
+            computer,
+            origin,
+            size,
+            labels,
+            continue_cycles: CONTINUE_CYCLES,
+        }
+    }
+
+    // run one command line, None once the session should end
+    pub fn execute(&mut self, line: &str) -> Option<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Some(String::new()),
+        };
+
+        let result = match command {
+            "step" | "s" => self.count(args).map(|n| self.run(Some(n), false)),
+            "stepi" | "si" => self.count(args).map(|n| self.run(Some(n), true)),
+            "continue" | "c" => Ok(self.run(None, false)),
+            "break" | "b" => self.add_breakpoint(args),
+            "watch" => self.add_watchpoint(args, WatchKind::Write),
+            "rwatch" => self.add_watchpoint(args, WatchKind::Read),
+            "awatch" => self.add_watchpoint(args, WatchKind::Access),
+            "regs" => Ok(format!("{}\n", self.computer)),
+            "disas" => self.disassemble(args),
+            "set" => self.set(args),
+            "save" => self.save(args),
+            "restore" => self.restore(args),
+            "help" | "h" => Ok(format!("{}\n", HELP)),
+            "quit" | "q" => return None,
+            _ if command.starts_with("x/") || command == "x" => self.examine(command, args),
+            _ => Err(format!("unknown command '{}', try help", command)),
+        };
+
+        Some(match result {
+            Ok(output) => output,
+            Err(e) => format!("{}\n", e),
+        })
+    }
+
+    // runs until `limit` instructions (or stepper ticks) have passed, or
+    // until a breakpoint or watchpoint stops it, no limit means at most
+    // continue_cycles
+    fn run(&mut self, limit: Option<u64>, ticks: bool) -> String {
+        if limit == Some(0) {
+            return self.report(None);
+        }
+
+        let mut count = 0;
+        loop {
+            if let Some(stop) = self.computer.step() {
+                return self.report(Some(stop));
+            }
+
+            if ticks || self.computer.at_instruction_start() {
+                count += 1;
+            }
+            if Some(count) == limit {
+                return self.report(None);
+            }
+            if limit.is_none() && count == self.continue_cycles {
+                return format!("Stopped after {} cycles\n", count) + self.report(None).as_str();
+            }
+        }
+    }
+
+    fn report(&self, stop: Option<StopReason>) -> String {
+        let mut result = match stop {
+            None => String::new(),
+            Some(StopReason::Breakpoint(address)) => self.breakpoint_line(address),
+            Some(StopReason::Read { address, value }) => format!(
+                "Watchpoint {}: [0x{:>04X}] read 0x{:>04X}\n",
+                self.watchpoint_number(address),
+                address,
+                value
+            ),
+            Some(StopReason::Write { address, old, new }) => format!(
+                "Watchpoint {}: [0x{:>04X}] 0x{:>04X} -> 0x{:>04X}\n",
+                self.watchpoint_number(address),
+                address,
+                old,
+                new
+            ),
+            Some(StopReason::Halt(status)) => format!("Halted, exit status 0x{:>04X}\n", status),
+        };
+
+        if !self.computer.at_instruction_start() {
+            result += format!("in step {} of 6\n", self.computer.steps() % 6 + 1).as_str();
+        }
+        let iar = self.computer.cpu_state().iar;
+        result + self.listing(iar, 0).as_str()
+    }
+
+    fn breakpoint_line(&self, address: u16) -> String {
+        let number = self
+            .computer
+            .breakpoints()
+            .iter()
+            .position(|b| *b == address)
+            .unwrap_or_default();
+        format!("Breakpoint {} at 0x{:>04X}\n", number + 1, address)
+    }
+
+    fn watchpoint_number(&self, address: u16) -> usize {
+        self.computer
+            .watchpoints()
+            .iter()
+            .position(|(a, _)| *a == address)
+            .unwrap_or_default()
+            + 1
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        if args.is_empty() {
+            return Ok(self
+                .computer
+                .breakpoints()
+                .iter()
+                .map(|address| self.breakpoint_line(*address))
+                .collect());
+        }
+
+        let address = self.address(args)?;
+        self.computer.add_breakpoint(address);
+        Ok(self.breakpoint_line(address))
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str], kind: WatchKind) -> Result<String, String> {
+        let address = self.address(args)?;
+        self.computer.add_watchpoint(address, kind);
+        Ok(format!(
+            "Watchpoint {}: [0x{:>04X}]\n",
+            self.watchpoint_number(address),
+            address
+        ))
+    }
+
+    fn examine(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+        let count = match command.strip_prefix("x/") {
+            Some(count) => parse_address(count)?,
+            None => 8,
+        };
+        let start = self.address(args)?;
+
+        let mut result = String::new();
+        for row in (0..count).step_by(8) {
+            let address = start.wrapping_add(row);
+            let words: Vec<String> = (row..count.min(row.saturating_add(8)))
+                .map(|i| {
+                    format!(
+                        "0x{:>04X}",
+                        self.computer.read_memory(start.wrapping_add(i))
+                    )
+                })
+                .collect();
+            result += format!("0x{:>04X}: {}\n", address, words.join(" ")).as_str();
+        }
+        Ok(result)
+    }
+
+    fn disassemble(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = match args.is_empty() {
+            true => self.computer.cpu_state().iar,
+            false => self.address(args)?,
+        };
+        Ok(self.listing(address, 5))
+    }
+
+    fn set(&mut self, args: &[&str]) -> Result<String, String> {
+        // accept "set R0 5", "set R0 = 5" and "set reg R0 5"
+        let args: Vec<&str> = args
+            .iter()
+            .copied()
+            .filter(|a| *a != "=" && !a.eq_ignore_ascii_case("reg"))
+            .collect();
+        if args.len() != 2 {
+            return Err("usage: set <R0-R3|IAR> <value>".to_string());
+        }
+
+        let value = self.value(args[1])?;
+        match args[0].to_uppercase().as_str() {
+            "R0" => self.computer.set_register(0, value),
+            "R1" => self.computer.set_register(1, value),
+            "R2" => self.computer.set_register(2, value),
+            "R3" => self.computer.set_register(3, value),
+            "IAR" => self.computer.set_iar(value),
+            _ => return Err(format!("unknown register '{}'", args[0])),
+        }
+        Ok(format!("{} = 0x{:>04X}\n", args[0].to_uppercase(), value))
+    }
+
+    fn save(&self, args: &[&str]) -> Result<String, String> {
+        let path = args.first().ok_or("usage: save <file>")?;
+        self.computer
+            .snapshot()
+            .and_then(|snapshot| snapshot.save(Path::new(path)))
+            .map_err(|e| e.to_string())?;
+        Ok(format!("Saved to {}\n", path))
+    }
+
+    fn restore(&mut self, args: &[&str]) -> Result<String, String> {
+        let path = args.first().ok_or("usage: restore <file>")?;
+        let snapshot = Snapshot::load(Path::new(path)).map_err(|e| e.to_string())?;
+        self.computer.restore(&snapshot);
+        Ok(self.report(None))
+    }
+
+    fn listing(&self, address: u16, context: usize) -> String {
+        let mut disassembler = Disassembler::new();
+        for (name, address) in self.labels.iter() {
+            disassembler.add_label(name, *address);
+        }
+
+        if address.wrapping_sub(self.origin) < self.size {
+            let words: Vec<u16> = (0..self.size)
+                .map(|i| self.computer.read_memory(self.origin.wrapping_add(i)))
+                .collect();
+            return disassembler.listing_around(self.origin, &words, address, context);
+        }
+
+        let words: Vec<u16> = (0..context as u16 * 2 + 2)
+            .map(|i| self.computer.read_memory(address.wrapping_add(i)))
+            .collect();
+        disassembler.listing_around(address, &words, address, context)
+    }
+
+    fn count(&self, args: &[&str]) -> Result<u64, String> {
+        match args.first() {
+            Some(count) => Ok(parse_address(count)? as u64),
+            None => Ok(1),
+        }
+    }
+
+    fn address(&self, args: &[&str]) -> Result<u16, String> {
+        match args.first() {
+            Some(arg) => self.value(arg),
+            None => Err("expected an address or label".to_string()),
+        }
+    }
+
+    fn value(&self, arg: &str) -> Result<u16, String> {
+        match self.labels.get(arg) {
+            Some(address) => Ok(*address),
+            None => parse_address(arg).map_err(|_| format!("unknown label or number '{}'", arg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsmParser, Assembler, CoreKind, USER_CODE_START};
+
+    fn get_debugger(source: &str) -> Debugger {
+        let instructions = AsmParser::new().parse("test.asm", source).unwrap();
+        let mut assembler = Assembler::new();
+        let bin = assembler
+            .process(USER_CODE_START, Some(instructions))
+            .unwrap();
+
+        let mut computer = Computer::new_headless(CoreKind::Behavioral);
+        let size = bin.len() as u16;
+        computer.load_to_ram(USER_CODE_START, bin).unwrap();
+        computer.boot();
+
+        Debugger::new(computer, USER_CODE_START, size, assembler.labels().clone())
+    }
+
+    const PROGRAM: &str = "
+            DATA R0, 0x0600
+            DATA R1, 0x0001
+            DATA R2, 0x0001
+        loop:
+            CLF
+            ADD R2, R1
+            ST R0, R1
+            JMP loop
+        ";
+
+    #[test]
+    fn test_debugger_step() {
+        let mut debugger = get_debugger(PROGRAM);
+
+        assert_eq!(
+            debugger.execute("step").unwrap(),
+            "=>\t0x0502:\t{33 1}\t\tDATA R1, 0x0001\n"
+        );
+        assert_eq!(
+            debugger.execute("stepi 2").unwrap(),
+            "in step 3 of 6\n=>\t0x0504:\t{34 1}\t\tDATA R2, 0x0001\n"
+        );
+        assert_eq!(
+            debugger.execute("s 2").unwrap(),
+            "\nloop:\n=>\t0x0506:\t{96}\t\t\tCLF\n"
+        );
+        assert_eq!(debugger.computer.cpu_state().registers[2], 0x0001);
+
+        // a count of 0 shows where the machine is without running it
+        let steps = debugger.computer.steps();
+        assert_eq!(
+            debugger.execute("step 0").unwrap(),
+            "\nloop:\n=>\t0x0506:\t{96}\t\t\tCLF\n"
+        );
+        assert_eq!(
+            debugger.execute("stepi 0").unwrap(),
+            "\nloop:\n=>\t0x0506:\t{96}\t\t\tCLF\n"
+        );
+        assert_eq!(debugger.computer.steps(), steps);
+    }
+
+    #[test]
+    fn test_debugger_breakpoints() {
+        let mut debugger = get_debugger(PROGRAM);
+
+        assert_eq!(
+            debugger.execute("break loop").unwrap(),
+            "Breakpoint 1 at 0x0506\n"
+        );
+        assert_eq!(
+            debugger.execute("b 0x0508").unwrap(),
+            "Breakpoint 2 at 0x0508\n"
+        );
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "Breakpoint 1 at 0x0506\n\nloop:\n=>\t0x0506:\t{96}\t\t\tCLF\n"
+        );
+        assert_eq!(
+            debugger.execute("continue").unwrap(),
+            "Breakpoint 2 at 0x0508\n=>\t0x0508:\t{17}\t\t\tST R0, R1\n"
+        );
+        assert_eq!(
+            debugger.execute("break").unwrap(),
+            "Breakpoint 1 at 0x0506\nBreakpoint 2 at 0x0508\n"
+        );
+        assert_eq!(
+            debugger.execute("break nowhere").unwrap(),
+            "unknown label or number 'nowhere'\n"
+        );
+
+        // without a breakpoint on the way continue still comes back
+        let mut looping = get_debugger(PROGRAM);
+        looping.continue_cycles = 1000;
+        assert_eq!(
+            looping.execute("c").unwrap(),
+            "Stopped after 1000 cycles\n=>\t0x0507:\t{137}\t\t\tADD R2, R1\n"
+        );
+    }
+
+    #[test]
+    fn test_debugger_watchpoints() {
+        let mut debugger = get_debugger(PROGRAM);
+
+        assert_eq!(
+            debugger.execute("watch 0x0600").unwrap(),
+            "Watchpoint 1: [0x0600]\n"
+        );
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "Watchpoint 1: [0x0600] 0xFFFF -> 0x0002\nin step 2 of 6\n=>\t0x0509:\t{64 1286}\t\tJMP loop\n"
+        );
+        assert_eq!(
+            debugger.execute("x/2 0x0600").unwrap(),
+            "0x0600: 0x0002 0xFFFF\n"
+        );
+
+        // the last row stops at the count, even one that ends near 0xFFFF
+        let rows = debugger.execute("x/65535 0x0600").unwrap();
+        assert_eq!(rows.lines().count(), 8192);
+        let last = rows.lines().last().unwrap();
+        assert!(last.starts_with("0x05F8: "));
+        assert_eq!(last.split_whitespace().count(), 8);
+    }
+
+    #[test]
+    fn test_debugger_set() {
+        let mut debugger = get_debugger(PROGRAM);
+
+        assert_eq!(
+            debugger.execute("set R3 = 0x1234").unwrap(),
+            "R3 = 0x1234\n"
+        );
+        assert_eq!(debugger.execute("set reg r1 7").unwrap(), "R1 = 0x0007\n");
+        assert_eq!(debugger.execute("set IAR loop").unwrap(), "IAR = 0x0506\n");
+        assert_eq!(
+            debugger.execute("set R4 1").unwrap(),
+            "unknown register 'R4'\n"
+        );
+
+        let state = debugger.computer.cpu_state();
+        assert_eq!(state.registers[1], 0x0007);
+        assert_eq!(state.registers[3], 0x1234);
+        assert_eq!(state.iar, 0x0506);
+
+        assert!(debugger.execute("regs").unwrap().contains("0x1234"));
+        assert!(debugger.execute("disas").unwrap().contains("=>\t0x0506:"));
+        assert!(debugger.execute("quit").is_none());
+    }
+
+    #[test]
+    fn test_debugger_snapshot() {
+        let mut debugger = get_debugger(PROGRAM);
+        let path = std::env::temp_dir().join("test_debugger_snapshot.snap");
+        let path = path.to_str().unwrap();
+
+        debugger.execute("step 3");
+        debugger.execute("si");
+        assert_eq!(
+            debugger.execute(&format!("save {}", path)).unwrap(),
+            "Snapshots are taken between instructions, the stepper is at step 2 of 6\n"
+        );
+        debugger.execute("si 5");
+        assert_eq!(
+            debugger.execute(&format!("save {}", path)).unwrap(),
+            format!("Saved to {}\n", path)
+        );
+
+        debugger.execute("step 4");
+        assert_eq!(debugger.computer.read_memory(0x0600), 0x0002);
+        assert_eq!(
+            debugger.execute(&format!("restore {}", path)).unwrap(),
+            "=>\t0x0507:\t{137}\t\t\tADD R2, R1\n"
+        );
+        assert_eq!(debugger.computer.read_memory(0x0600), 0xFFFF);
+        std::fs::remove_file(path).unwrap();
+    }
+}
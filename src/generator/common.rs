@@ -1,6 +1,7 @@
 use crate::instructions::{
-    IOMode, Instructions, Label, Number, Register, SafeInstruction, Symbol, ADD, AND, CALL, CLF,
-    CMP, DATA, DEFLABEL, DEFSYMBOL, IN, JMP, JMPF, JR, LOAD, NOT, OUT, SHL, STORE, XOR,
+    Expression, IOMode, Instructions, Label, Node, Number, Operator, Register, SafeInstruction,
+    Symbol, ADD, AND, CALL, CLF, CMP, DATA, DEFLABEL, DEFSYMBOL, FILL, IN, JMP, JMPF, LOAD, NOT,
+    OUT, RET, SHL, STORE, WORD, XOR,
 };
 use lazy_static::lazy_static;
 use std::{collections::HashMap, rc::Rc};
@@ -529,9 +530,9 @@ pub fn initialise_common_code() -> Vec<SafeInstruction> {
 
     instructions.add(vec![
         Rc::new(DEFSYMBOL::new("LINE-WIDTH", 0x001E)),
-        Rc::new(DEFSYMBOL::new("CALL-RETURN-ADDRESS", 0xFF33)),
+        Rc::new(DEFSYMBOL::new("TEMP-VARIABLES", 0xFF00)),
         Rc::new(DEFSYMBOL::new("ONE", 0x0001)),
-        Rc::new(DEFSYMBOL::new("LINEX", 0xFF01)),
+        Rc::new(DEFSYMBOL::from_expression("LINEX", temp_variable(0x01))),
         Rc::new(DEFSYMBOL::new("PEN-POSITION-ADDR", 0x0400)),
         Rc::new(DEFSYMBOL::new("KEYCODE-REGISTER", 0x0401)),
         Rc::new(DEFSYMBOL::new("DISPLAY-ADAPTER-ADDR", 0x0007)),
@@ -561,36 +562,73 @@ pub fn initialise_common_code() -> Vec<SafeInstruction> {
     instructions.get()
 }
 
+// the address of a variable in the temporary variables region at 0xFF00
+pub fn temp_variable(offset: u16) -> Expression {
+    Expression::new(Node::binary(
+        Operator::Add,
+        Node::Symbol("TEMP-VARIABLES".to_string()),
+        Node::Number(offset),
+    ))
+}
+
 pub fn call_routine(routine: &str) -> Vec<SafeInstruction> {
     vec![Rc::new(CALL::new(Label::new(routine)))]
 }
 
+// copies the font table into the ASCII region, every character has 8 lines
+// from c * 8
 fn routine_load_font_descriptions(lable: &str) -> Vec<SafeInstruction> {
+    let first = *CHARACTERS.keys().min().unwrap();
+    let last = *CHARACTERS.keys().max().unwrap();
     let mut instructions = Instructions::new();
 
-    instructions.add(vec![Rc::new(DEFLABEL::new(lable))]);
-
-    for i in CHARACTERS.keys() {
-        instructions.add_blocks(vec![load_font_character_into_font_region(i)]);
-    }
-
-    instructions.add(vec![Rc::new(JR::new(Register::REG3))]);
-    instructions.get()
-}
-
-fn load_font_character_into_font_region(c: &char) -> Vec<SafeInstruction> {
-    let font_description = CHARACTERS.get(c).unwrap();
-    let mut instructions = Instructions::new();
-
-    for i in 0..8 {
-        let line = font_description[i];
-        instructions.add(vec![Rc::new(DATA::new(
+    instructions.add(vec![
+        Rc::new(DEFLABEL::new(lable)),
+        Rc::new(DATA::new(
             Register::REG0,
-            Number::new((*c as u16) << 3 + i as u16),
-        ))]);
-        instructions.add(vec![Rc::new(DATA::new(Register::REG1, Number::new(line)))]);
-        instructions.add(vec![Rc::new(STORE::new(Register::REG0, Register::REG1))]);
+            Expression::new(Node::Label(".table".to_string())),
+        )),
+        Rc::new(DATA::new(
+            Register::REG1,
+            Expression::new(Node::binary(
+                Operator::Shl,
+                Node::Char(first),
+                Node::Number(3),
+            )),
+        )),
+        Rc::new(DEFLABEL::new(".loop")),
+        Rc::new(LOAD::new(Register::REG0, Register::REG2)),
+        Rc::new(STORE::new(Register::REG1, Register::REG2)),
+        Rc::new(DATA::new(Register::REG2, Symbol::new("ONE"))),
+        Rc::new(CLF::new()),
+        Rc::new(ADD::new(Register::REG2, Register::REG0)), // next table word
+        Rc::new(ADD::new(Register::REG2, Register::REG1)), // next font line
+        Rc::new(DATA::new(
+            Register::REG3,
+            Expression::new(Node::Label(".table-end".to_string())),
+        )),
+        Rc::new(CMP::new(Register::REG0, Register::REG3)),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".done"))),
+        Rc::new(JMP::new(Label::new(".loop"))),
+        Rc::new(DEFLABEL::new(".done")),
+        Rc::new(RET::new()),
+    ]);
+
+    // characters without a font stay blank
+    instructions.add(vec![Rc::new(DEFLABEL::new(".table"))]);
+    for c in first..=last {
+        let font_description: SafeInstruction = match CHARACTERS.get(&c) {
+            Some(lines) => Rc::new(WORD::new(
+                lines
+                    .iter()
+                    .map(|line| Expression::new(Node::Number(*line)))
+                    .collect(),
+            )),
+            None => Rc::new(FILL::new(8, Expression::new(Node::Number(0)))),
+        };
+        instructions.add(vec![font_description]);
     }
+    instructions.add(vec![Rc::new(DEFLABEL::new(".table-end"))]);
 
     instructions.get()
 }
@@ -602,11 +640,6 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
     instructions.add(vec![Rc::new(DEFLABEL::new(label_prefix))]);
 
     instructions.add(vec![
-        Rc::new(DATA::new(
-            Register::REG2,
-            Symbol::new("CALL-RETURN-ADDRESS"),
-        )),
-        Rc::new(STORE::new(Register::REG2, Register::REG3)),
         Rc::new(DATA::new(Register::REG2, Symbol::new("PEN-POSITION-ADDR"))),
         Rc::new(LOAD::new(Register::REG2, Register::REG2)),
     ]);
@@ -627,7 +660,7 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(CMP::new(Register::REG3, Register::REG1)),
         Rc::new(JMPF::new(
             vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-carriage-return")),
+            Label::new(".carriage-return"),
         )),
     ]);
     instructions.add_blocks(vec![select_display_adapter(Register::REG3)]);
@@ -635,7 +668,7 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
     // calculate memory position of font line
     // start of loop:
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-STARTLOOP"))),
+        Rc::new(DEFLABEL::new(".STARTLOOP")),
         Rc::new(DATA::new(Register::REG3, Symbol::new("KEYCODE-REGISTER"))), // load keycode
         Rc::new(LOAD::new(Register::REG3, Register::REG3)),
         Rc::new(SHL::new(Register::REG3)),
@@ -662,19 +695,14 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(DATA::new(Register::REG1, Number::new(0x0007))),
         Rc::new(CMP::new(Register::REG0, Register::REG1)), // if fontY == 0x0007 then we have rendered the last line
         // if all 8 lines rendered, jump out of loop, we're done
-        Rc::new(JMPF::new(
-            vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-ENDLOOP")),
-        )),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".ENDLOOP"))),
         // otherwise jump back to start of loop and render next line of font
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-STARTLOOP"),
-        ))),
+        Rc::new(JMP::new(Label::new(".STARTLOOP"))),
     ]);
 
     //update pen position we are moving to the next character
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-ENDLOOP"))),
+        Rc::new(DEFLABEL::new(".ENDLOOP")),
         // increment line x
         Rc::new(DATA::new(Register::REG1, Symbol::new("LINEX"))),
         Rc::new(LOAD::new(Register::REG1, Register::REG1)),
@@ -689,26 +717,18 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(CMP::new(Register::REG1, Register::REG3)),
         Rc::new(JMPF::new(
             vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-carriage-return")),
-        )),
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-increment-cursor"),
-        ))),
-        Rc::new(DEFLABEL::new(
-            &(label_prefix.to_owned() + "-increment-cursor"),
+            Label::new(".carriage-return"),
         )),
+        Rc::new(JMP::new(Label::new(".increment-cursor"))),
+        Rc::new(DEFLABEL::new(".increment-cursor")),
         Rc::new(DATA::new(Register::REG1, Symbol::new("ONE"))), // one
         Rc::new(ADD::new(Register::REG1, Register::REG0)),      // increment pen position by 1
         Rc::new(DATA::new(Register::REG1, Symbol::new("PEN-POSITION-ADDR"))),
         Rc::new(STORE::new(Register::REG1, Register::REG0)), // store new value of pen position in memory
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-deselectIO"),
-        ))),
+        Rc::new(JMP::new(Label::new(".deselectIO"))),
         // used for when the enter key is hit and you need to return
         // this is absolutely monstrous
-        Rc::new(DEFLABEL::new(
-            &(label_prefix.to_owned() + "-carriage-return"),
-        )),
+        Rc::new(DEFLABEL::new(".carriage-return")),
         Rc::new(DATA::new(Register::REG1, Symbol::new("LINEX"))),
         Rc::new(LOAD::new(Register::REG1, Register::REG1)), // retrieve linex
         // if linex == 0
@@ -717,7 +737,7 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(CMP::new(Register::REG1, Register::REG2)),
         Rc::new(JMPF::new(
             vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-reposition-pen")),
+            Label::new(".reposition-pen"),
         )),
         // if linex == 1...
         Rc::new(DATA::new(Register::REG2, Symbol::new("ONE"))),
@@ -725,7 +745,7 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(CMP::new(Register::REG1, Register::REG2)),
         Rc::new(JMPF::new(
             vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-reposition-pen")),
+            Label::new(".reposition-pen"),
         )),
         // if linex == end of line
         Rc::new(DATA::new(Register::REG2, Symbol::new("LINE-WIDTH"))),
@@ -733,12 +753,10 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(CMP::new(Register::REG1, Register::REG2)),
         Rc::new(JMPF::new(
             vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-reposition-pen")),
+            Label::new(".reposition-pen"),
         )),
         // otherwise calculate difference and move pen down
-        Rc::new(DEFLABEL::new(
-            &(label_prefix.to_owned() + "-reposition-pen-when-midline"),
-        )),
+        Rc::new(DEFLABEL::new(".reposition-pen-when-midline")),
         Rc::new(DATA::new(Register::REG2, Symbol::new("ONE"))),
         Rc::new(DATA::new(Register::REG0, Number::new(0x00EF))), // 239 pixels (next line)
         // subtract linex - 239
@@ -752,48 +770,30 @@ fn routine_draw_font_character(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(ADD::new(Register::REG1, Register::REG0)),
         Rc::new(DATA::new(Register::REG1, Symbol::new("PEN-POSITION-ADDR"))),
         Rc::new(STORE::new(Register::REG1, Register::REG0)), // store new value of pen position in memory
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-resetlinex"),
-        ))),
+        Rc::new(JMP::new(Label::new(".resetlinex"))),
         // needs value in reg3
-        Rc::new(DEFLABEL::new(
-            &(label_prefix.to_owned() + "-reposition-pen"),
-        )),
+        Rc::new(DEFLABEL::new(".reposition-pen")),
         // add subtraction to pen position
         Rc::new(DATA::new(Register::REG0, Symbol::new("PEN-POSITION-ADDR"))),
         Rc::new(LOAD::new(Register::REG0, Register::REG0)),
         Rc::new(ADD::new(Register::REG3, Register::REG0)),
         Rc::new(DATA::new(Register::REG1, Symbol::new("PEN-POSITION-ADDR"))),
         Rc::new(STORE::new(Register::REG1, Register::REG0)), // store new value of pen position in memory
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-resetlinex"),
-        ))),
+        Rc::new(JMP::new(Label::new(".resetlinex"))),
         // reset linex
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-resetlinex"))),
+        Rc::new(DEFLABEL::new(".resetlinex")),
         Rc::new(DATA::new(Register::REG2, Symbol::new("LINEX"))), //reset linex
         Rc::new(DATA::new(Register::REG3, Number::new(0x0000))),
         Rc::new(STORE::new(Register::REG2, Register::REG3)),
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-deselectIO"),
-        ))),
+        Rc::new(JMP::new(Label::new(".deselectIO"))),
     ]);
-    instructions.add(vec![Rc::new(DEFLABEL::new(
-        &(label_prefix.to_owned() + "-deselectIO"),
-    ))]);
+    instructions.add(vec![Rc::new(DEFLABEL::new(".deselectIO"))]);
 
     // deselect IO adapter
     instructions.add_blocks(vec![deselect_io(Register::REG3)]);
 
     // return to callee
-    instructions.add(vec![
-        Rc::new(CLF::new()),
-        Rc::new(DATA::new(
-            Register::REG3,
-            Symbol::new("CALL-RETURN-ADDRESS"),
-        )),
-        Rc::new(LOAD::new(Register::REG3, Register::REG3)),
-        Rc::new(JR::new(Register::REG3)),
-    ]);
+    instructions.add(vec![Rc::new(RET::new())]);
     instructions.get()
 }
 
@@ -801,29 +801,17 @@ fn routine_poll_keyboard(label_prefix: &str) -> Vec<SafeInstruction> {
     let mut instructions = Instructions::new();
     instructions.add(vec![Rc::new(DEFLABEL::new(label_prefix))]);
 
-    // push retun address
-    instructions.add(vec![
-        Rc::new(DATA::new(
-            Register::REG2,
-            Symbol::new("CALL-RETURN-ADDRESS"),
-        )),
-        Rc::new(STORE::new(Register::REG2, Register::REG3)),
-    ]);
-
     instructions.add(vec![
         Rc::new(DATA::new(Register::REG2, Number::new(0x000F))), //select keyboard keyboard
         Rc::new(OUT::new(IOMode::AddressMode, Register::REG2)),
     ]);
 
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-STARTLOOP"))),
+        Rc::new(DEFLABEL::new(".STARTLOOP")),
         Rc::new(IN::new(IOMode::DataMode, Register::REG3)), // request key from keyboard adapter
         Rc::new(AND::new(Register::REG3, Register::REG3)),  // check if value is zero
-        Rc::new(JMPF::new(
-            vec!["Z".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-STARTLOOP")),
-        )), // if it is - keep polling
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-ENDLOOP"))), //otherwise
+        Rc::new(JMPF::new(vec!["Z".to_string()], Label::new(".STARTLOOP"))), // if it is - keep polling
+        Rc::new(DEFLABEL::new(".ENDLOOP")),                                  //otherwise
         Rc::new(DATA::new(Register::REG0, Symbol::new("KEYCODE-REGISTER"))),
         Rc::new(STORE::new(Register::REG0, Register::REG3)), //store key in here
         // deselect keyboard
@@ -832,15 +820,7 @@ fn routine_poll_keyboard(label_prefix: &str) -> Vec<SafeInstruction> {
     ]);
 
     // return to callee
-    instructions.add(vec![
-        Rc::new(CLF::new()),
-        Rc::new(DATA::new(
-            Register::REG3,
-            Symbol::new("CALL-RETURN-ADDRESS"),
-        )),
-        Rc::new(LOAD::new(Register::REG3, Register::REG3)),
-        Rc::new(JR::new(Register::REG3)),
-    ]);
+    instructions.add(vec![Rc::new(RET::new())]);
 
     instructions.get()
 }
@@ -897,3 +877,41 @@ pub fn reset_linex() -> Vec<SafeInstruction> {
 
     instructions.get()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::computer::{Computer, CoreKind, HeadlessConfig, RunLimit};
+    use crate::USER_CODE_START;
+
+    #[test]
+    fn test_common_font_table() {
+        let mut instructions = Instructions::new();
+        instructions.add(initialise_common_code());
+        instructions.add(vec![
+            Rc::new(DEFLABEL::new("main")),
+            Rc::new(JMP::new(Label::new("main"))),
+        ]);
+        let mut assembler = Assembler::new();
+        let bin = assembler
+            .process(USER_CODE_START, Some(instructions))
+            .unwrap();
+
+        let mut computer = Computer::new_headless(CoreKind::Behavioral);
+        computer.load_to_ram(USER_CODE_START, bin).unwrap();
+        let report = computer.run_headless(HeadlessConfig {
+            limit: RunLimit::Cycles(20000),
+            halt_at: Some(assembler.labels()["main"]),
+            memory_dump: vec![],
+        });
+        assert!(report.halted);
+
+        for (c, lines) in CHARACTERS.iter() {
+            for (i, line) in lines.iter().enumerate() {
+                let address = ((*c as u16) << 3) + i as u16;
+                assert_eq!(computer.read_memory(address), *line, "'{}' line {}", c, i);
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod common;
 mod program;
 
+pub(crate) use common::initialise_common_code;
 pub use program::get_instructions;
@@ -1,11 +1,11 @@
 use super::common::{
     call_routine, deselect_io, initialise_common_code, render_string, reset_linex,
-    select_display_adapter, update_pen_position,
+    select_display_adapter, temp_variable, update_pen_position,
 };
 
 use crate::instructions::{
     IOMode, Instructions, Label, Number, Register, SafeInstruction, Symbol, ADD, CLF, CMP, DATA,
-    DEFLABEL, JMP, JMPF, JR, LOAD, NOT, OUT, SHL, STORE,
+    DEFLABEL, JMP, JMPF, LOAD, NOT, OUT, RET, SHL, STORE,
 };
 
 use std::rc::Rc;
@@ -28,7 +28,7 @@ fn ascii_table(instructions: &mut Instructions) -> Option<Instructions> {
     instructions.add(vec![
         Rc::new(DEFLABEL::new("main")),
         Rc::new(DATA::new(Register::REG0, Number::new(0x0020))),
-        Rc::new(DATA::new(Register::REG2, Number::new(0xFF23))),
+        Rc::new(DATA::new(Register::REG2, temp_variable(0x23))),
         Rc::new(STORE::new(Register::REG2, Register::REG0)),
         Rc::new(DATA::new(Register::REG2, Symbol::new("LINEX"))),
         Rc::new(DATA::new(Register::REG1, Number::new(0x0000))),
@@ -40,20 +40,20 @@ fn ascii_table(instructions: &mut Instructions) -> Option<Instructions> {
     instructions.add(vec![Rc::new(DEFLABEL::new("main-loop"))]);
 
     instructions.add(vec![
-        Rc::new(DATA::new(Register::REG2, Number::new(0xFF23))),
+        Rc::new(DATA::new(Register::REG2, temp_variable(0x23))),
         Rc::new(LOAD::new(Register::REG2, Register::REG0)),
         Rc::new(DATA::new(Register::REG1, Symbol::new("ONE"))),
         Rc::new(ADD::new(Register::REG1, Register::REG0)),
         Rc::new(DATA::new(Register::REG2, Symbol::new("KEYCODE-REGISTER"))),
         Rc::new(STORE::new(Register::REG2, Register::REG0)),
-        Rc::new(DATA::new(Register::REG2, Number::new(0xFF23))),
+        Rc::new(DATA::new(Register::REG2, temp_variable(0x23))),
         Rc::new(STORE::new(Register::REG2, Register::REG0)),
     ]);
 
     instructions.add_blocks(vec![call_routine("ROUTINE-io-drawFontCharacter")]);
 
     instructions.add(vec![
-        Rc::new(DATA::new(Register::REG2, Number::new(0xFF23))),
+        Rc::new(DATA::new(Register::REG2, temp_variable(0x23))),
         Rc::new(LOAD::new(Register::REG2, Register::REG0)),
         Rc::new(DATA::new(Register::REG2, Number::new(0x007E))),
         Rc::new(CMP::new(Register::REG0, Register::REG2)),
@@ -138,11 +138,6 @@ fn routine_draw_brush(label_prefix: &str) -> Vec<SafeInstruction> {
     instructions.add(vec![Rc::new(DEFLABEL::new(label_prefix))]);
 
     instructions.add(vec![
-        Rc::new(DATA::new(
-            Register::REG2,
-            Symbol::new("CALL-RETURN-ADDRESS"),
-        )),
-        Rc::new(STORE::new(Register::REG2, Register::REG3)),
         Rc::new(DATA::new(Register::REG2, Symbol::new("PEN-POSITION-ADDR"))),
         Rc::new(LOAD::new(Register::REG2, Register::REG2)),
     ]);
@@ -162,51 +157,39 @@ fn routine_draw_brush(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(LOAD::new(Register::REG3, Register::REG3)),
         Rc::new(DATA::new(Register::REG1, Number::new(0x0107))), // load keycode
         Rc::new(CMP::new(Register::REG3, Register::REG1)),       // load keycode
-        Rc::new(JMPF::new(
-            vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-left")),
-        )),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".left"))),
         Rc::new(DATA::new(Register::REG1, Number::new(0x0106))), // load keycode
         Rc::new(CMP::new(Register::REG3, Register::REG1)),       // load keycode
-        Rc::new(JMPF::new(
-            vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-right")),
-        )),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".right"))),
         Rc::new(DATA::new(Register::REG1, Number::new(0x0108))), // load keycode
         Rc::new(CMP::new(Register::REG3, Register::REG1)),       // load keycode
-        Rc::new(JMPF::new(
-            vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "down")),
-        )),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".down"))),
         Rc::new(DATA::new(Register::REG1, Number::new(0x0109))), // load keycode
         Rc::new(CMP::new(Register::REG3, Register::REG1)),       // load keycode
-        Rc::new(JMPF::new(
-            vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-up")),
-        )),
-        Rc::new(JMP::new(Label::new(&(label_prefix.to_owned() + "-start")))),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".up"))),
+        Rc::new(JMP::new(Label::new(".start"))),
     ]);
 
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-right"))),
+        Rc::new(DEFLABEL::new(".right")),
         Rc::new(DATA::new(Register::REG1, Symbol::new("ONE"))), // load keycode
         Rc::new(ADD::new(Register::REG1, Register::REG2)),      // load keycode
         Rc::new(DATA::new(Register::REG3, Symbol::new("PEN-POSITION-ADDR"))), // load keycode
         Rc::new(STORE::new(Register::REG3, Register::REG2)),    // load keycode
-        Rc::new(JMP::new(Label::new(&(label_prefix.to_owned() + "-start")))),
+        Rc::new(JMP::new(Label::new(".start"))),
     ]);
 
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-down"))),
+        Rc::new(DEFLABEL::new(".down")),
         Rc::new(DATA::new(Register::REG1, Number::new(0x00F0))), // load keycode
         Rc::new(ADD::new(Register::REG1, Register::REG2)),       // load keycode
         Rc::new(DATA::new(Register::REG3, Symbol::new("PEN-POSITION-ADDR"))), // load keycode
         Rc::new(STORE::new(Register::REG3, Register::REG2)),     // load keycode
-        Rc::new(JMP::new(Label::new(&(label_prefix.to_owned() + "-start")))),
+        Rc::new(JMP::new(Label::new(".start"))),
     ]);
 
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-up"))),
+        Rc::new(DEFLABEL::new(".up")),
         Rc::new(DATA::new(Register::REG1, Symbol::new("ONE"))), // load keycode
         Rc::new(DATA::new(Register::REG1, Number::new(0x00F0))), // load keycode
         Rc::new(NOT::new(Register::REG1)),
@@ -215,11 +198,11 @@ fn routine_draw_brush(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(ADD::new(Register::REG1, Register::REG2)),
         Rc::new(DATA::new(Register::REG3, Symbol::new("PEN-POSITION-ADDR"))), // load keycode
         Rc::new(STORE::new(Register::REG3, Register::REG2)),                  // load keycode
-        Rc::new(JMP::new(Label::new(&(label_prefix.to_owned() + "-start")))),
+        Rc::new(JMP::new(Label::new(".start"))),
     ]);
 
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-left"))),
+        Rc::new(DEFLABEL::new(".left")),
         Rc::new(DATA::new(Register::REG0, Symbol::new("ONE"))), // load keycode
         Rc::new(DATA::new(Register::REG1, Symbol::new("ONE"))), // load keycode
         Rc::new(NOT::new(Register::REG1)),
@@ -228,19 +211,17 @@ fn routine_draw_brush(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(ADD::new(Register::REG1, Register::REG2)),
         Rc::new(DATA::new(Register::REG3, Symbol::new("PEN-POSITION-ADDR"))), // load keycode
         Rc::new(STORE::new(Register::REG3, Register::REG2)),                  // load keycode
-        Rc::new(JMP::new(Label::new(&(label_prefix.to_owned() + "-start")))),
+        Rc::new(JMP::new(Label::new(".start"))),
     ]);
 
-    instructions.add(vec![Rc::new(DEFLABEL::new(
-        &(label_prefix.to_owned() + "-start"),
-    ))]);
+    instructions.add(vec![Rc::new(DEFLABEL::new(".start"))]);
 
     instructions.add_blocks(vec![select_display_adapter(Register::REG3)]);
 
     // calculate memory position of font line
     // start of loop:
     instructions.add(vec![
-        Rc::new(DEFLABEL::new(&(label_prefix.to_owned() + "-STARTLOOP"))),
+        Rc::new(DEFLABEL::new(".STARTLOOP")),
         Rc::new(DATA::new(Register::REG3, Number::new(0x0000))), // load keycode
         Rc::new(SHL::new(Register::REG3)),
         Rc::new(SHL::new(Register::REG3)),
@@ -266,38 +247,21 @@ fn routine_draw_brush(label_prefix: &str) -> Vec<SafeInstruction> {
         Rc::new(DATA::new(Register::REG1, Number::new(0x0008))),
         Rc::new(CMP::new(Register::REG0, Register::REG1)), // if fontY == 0x0007 then we have rendered the last line
         // if all 8 lines rendered, jump out of loop, we're done
-        Rc::new(JMPF::new(
-            vec!["E".to_string()],
-            Label::new(&(label_prefix.to_owned() + "-ENDLOOP")),
-        )),
+        Rc::new(JMPF::new(vec!["E".to_string()], Label::new(".ENDLOOP"))),
         // otherwise jump back to start of loop and render next line of font
-        Rc::new(JMP::new(Label::new(
-            &(label_prefix.to_owned() + "-STARTLOOP"),
-        ))),
+        Rc::new(JMP::new(Label::new(".STARTLOOP"))),
     ]);
 
     //update pen position we are moving to the next character
-    instructions.add(vec![Rc::new(DEFLABEL::new(
-        &(label_prefix.to_owned() + "-ENDLOOP"),
-    ))]);
+    instructions.add(vec![Rc::new(DEFLABEL::new(".ENDLOOP"))]);
 
-    instructions.add(vec![Rc::new(DEFLABEL::new(
-        &(label_prefix.to_owned() + "-deselectIO"),
-    ))]);
+    instructions.add(vec![Rc::new(DEFLABEL::new(".deselectIO"))]);
 
     // deselect IO adapter
     instructions.add_blocks(vec![deselect_io(Register::REG3)]);
 
     // return to callee
-    instructions.add(vec![
-        Rc::new(CLF::new()),
-        Rc::new(DATA::new(
-            Register::REG3,
-            Symbol::new("CALL-RETURN-ADDRESS"),
-        )),
-        Rc::new(LOAD::new(Register::REG3, Register::REG3)),
-        Rc::new(JR::new(Register::REG3)),
-    ]);
+    instructions.add(vec![Rc::new(RET::new())]);
 
     instructions.get()
 }
@@ -0,0 +1,101 @@
+use super::{Error, SourceLocation};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// Diagnostic - an error or warning from the assembler, with where it is in
+// the source when the instructions were parsed and what might fix it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Option<SourceLocation>,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            location: None,
+            message,
+            suggestion: None,
+        }
+    }
+
+    pub fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn suggest(self, suggestion: String) -> Self {
+        Self {
+            suggestion: Some(suggestion),
+            ..self
+        }
+    }
+
+    // errors that carry their own location keep it
+    pub fn at(self, location: Option<SourceLocation>) -> Self {
+        Self {
+            location: self.location.or(location),
+            ..self
+        }
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Self {
+        let suggestion = match &error {
+            Error::UnknownLabel(_) => Some("define the label or check its spelling"),
+            Error::UnknownSymbol(_) => Some("define the symbol with %NAME = value"),
+            Error::LabelExist(_) => Some("rename one of the labels, or use a .local label"),
+            Error::SymbolExist(_) => Some("rename one of the symbols"),
+            Error::SymbolReserved(_) => Some("pick a name the assembler does not use"),
+            Error::ScratchRegister(_) => Some("move the value into R0-R2 first"),
+            Error::SameRegister(_) => Some("copy the value into another register with MOV"),
+            Error::OrgBehind(..) => Some(".org can only move forward, raise its address"),
+            Error::NotRelocatable(_) => Some("assemble without --object to fix its address"),
+            _ => None,
+        };
+        let diagnostic = match error {
+            Error::Parse(location, message) => Self {
+                location: Some(location),
+                ..Self::error(message)
+            },
+            error => Self::error(error.to_string()),
+        };
+        match suggestion {
+            Some(suggestion) => diagnostic.suggest(suggestion.to_string()),
+            None => diagnostic,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n  help: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
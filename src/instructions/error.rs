@@ -1,3 +1,4 @@
+use super::{Diagnostic, SourceLocation};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -29,4 +30,32 @@ pub enum Error {
     // reserved
     #[error("symbol '{0}' is reserved for internal use, please use another symbol name")]
     SymbolReserved(String),
+
+    #[error("{0}")]
+    Expression(String),
+
+    #[error("'.org 0x{0:04X}' is behind the current address 0x{1:04X}")]
+    OrgBehind(u16, u16),
+
+    #[error("'{0}' needs an absolute address and cannot go in an object")]
+    NotRelocatable(String),
+
+    #[error("{0}: {1}")]
+    Parse(SourceLocation, String),
+
+    #[error("{0}: {1}")]
+    Io(String, std::io::Error),
+
+    #[error("'{0}' cannot use R3, the assembler needs it as scratch")]
+    ScratchRegister(String),
+
+    #[error("'{0}' needs two different registers")]
+    SameRegister(String),
+
+    #[error("no instructions to assemble")]
+    NoInstructions,
+
+    // everything found in one pass, warnings included
+    #[error("{}", .0.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))]
+    Assembly(Vec<Diagnostic>),
 }
@@ -0,0 +1,399 @@
+use super::{Error, Label, Marker, Reference, Resolver, SourceLocation, Symbol};
+use std::{any::Any, fmt::Display, rc::Rc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    And,
+    Or,
+}
+
+impl Operator {
+    // higher binds tighter, the same order as C
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operator::Or => 1,
+            Operator::And => 2,
+            Operator::Shl | Operator::Shr => 3,
+            Operator::Add | Operator::Sub => 4,
+            Operator::Mul | Operator::Div => 5,
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Sub => write!(f, "-"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Div => write!(f, "/"),
+            Operator::Shl => write!(f, "<<"),
+            Operator::Shr => write!(f, ">>"),
+            Operator::And => write!(f, "&"),
+            Operator::Or => write!(f, "|"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    // the high and low byte of a word
+    Hi,
+    Lo,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Function::Hi => write!(f, "hi"),
+            Function::Lo => write!(f, "lo"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Number(u16),
+    Char(char),
+    Label(String),
+    Symbol(String),
+    Negate(Box<Node>),
+    Not(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+const UNARY_PRECEDENCE: u8 = 6;
+
+impl Node {
+    pub fn binary(operator: Operator, left: Node, right: Node) -> Self {
+        Node::Binary(operator, Box::new(left), Box::new(right))
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Node::Binary(operator, _, _) => operator.precedence(),
+            Node::Negate(_) | Node::Not(_) => UNARY_PRECEDENCE,
+            _ => UNARY_PRECEDENCE + 1,
+        }
+    }
+
+    // whether the value is known without a resolver
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Node::Number(_) | Node::Char(_) => true,
+            Node::Label(_) | Node::Symbol(_) => false,
+            Node::Negate(node) | Node::Not(node) | Node::Call(_, node) => node.is_constant(),
+            Node::Binary(_, left, right) => left.is_constant() && right.is_constant(),
+        }
+    }
+
+    // the value before it is cut to 16 bits, wide enough that only
+    // unreasonable expressions overflow
+    fn evaluate(&self, resolver: &Option<Rc<dyn Resolver>>) -> Result<i64, String> {
+        let value = match self {
+            Node::Number(value) => *value as i64,
+            Node::Char(c) => *c as i64,
+            Node::Label(name) => match resolver {
+                Some(resolver) => resolver
+                    .label_resolver(&Label::new(name))
+                    .map_err(|e| e.to_string())? as i64,
+                None => return Err(Error::UnknownLabel(name.clone()).to_string()),
+            },
+            Node::Symbol(name) => match resolver {
+                Some(resolver) => resolver
+                    .symbol_resolver(&Symbol::new(name))
+                    .map_err(|e| e.to_string())? as i64,
+                None => return Err(Error::UnknownSymbol(name.clone()).to_string()),
+            },
+            Node::Negate(node) => -node.evaluate(resolver)?,
+            Node::Not(node) => !node.evaluate(resolver)?,
+            Node::Call(function, node) => {
+                let word = to_word(node.evaluate(resolver)?, node)?;
+                match function {
+                    Function::Hi => (word >> 8) as i64,
+                    Function::Lo => (word & 0x00FF) as i64,
+                }
+            }
+            Node::Binary(operator, left, right) => {
+                let (a, b) = (left.evaluate(resolver)?, right.evaluate(resolver)?);
+                let result = match operator {
+                    Operator::Add => a.checked_add(b),
+                    Operator::Sub => a.checked_sub(b),
+                    Operator::Mul => a.checked_mul(b),
+                    Operator::Div if b == 0 => {
+                        return Err(format!("division by zero in '{}'", self))
+                    }
+                    Operator::Div => a.checked_div(b),
+                    Operator::Shl | Operator::Shr if !(0..64).contains(&b) => {
+                        return Err(format!("cannot shift by {} in '{}'", b, self))
+                    }
+                    Operator::Shl => Some(a << b).filter(|v| v >> b == a),
+                    Operator::Shr => Some(a >> b),
+                    Operator::And => Some(a & b),
+                    Operator::Or => Some(a | b),
+                };
+                result.ok_or_else(|| format!("'{}' overflows", self))?
+            }
+        };
+        Ok(value)
+    }
+
+    // names this node is relocated by in an object file, only name, name + n,
+    // n + name and name - n can be, anything else that uses a name is Fixed
+    fn reference(&self) -> Option<Reference> {
+        match self {
+            Node::Label(name) => Some(Reference::Label(name.clone())),
+            Node::Symbol(name) => Some(Reference::Symbol(name.clone())),
+            Node::Binary(Operator::Add, left, right) if right.is_constant() => left.reference(),
+            Node::Binary(Operator::Add, left, right) if left.is_constant() => right.reference(),
+            Node::Binary(Operator::Sub, left, right) if right.is_constant() => left.reference(),
+            _ if self.is_constant() => None,
+            _ => Some(Reference::Fixed(self.names())),
+        }
+    }
+
+    fn names(&self) -> Vec<Reference> {
+        match self {
+            Node::Number(_) | Node::Char(_) => Vec::new(),
+            Node::Label(name) => vec![Reference::Label(name.clone())],
+            Node::Symbol(name) => vec![Reference::Symbol(name.clone())],
+            Node::Negate(node) | Node::Not(node) | Node::Call(_, node) => node.names(),
+            Node::Binary(_, left, right) => [left.names(), right.names()].concat(),
+        }
+    }
+}
+
+// negative values are taken as two's complement
+fn to_word(value: i64, node: &Node) -> Result<u16, String> {
+    match value {
+        -0x8000..=0xFFFF => Ok(value as u16),
+        _ => Err(format!("'{}' = {} does not fit in 16 bits", node, value)),
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Number(value) => write!(f, "0x{:>04X}", value),
+            Node::Char(c) => match c {
+                '\'' | '\\' => write!(f, "'\\{}'", c),
+                '\n' => write!(f, "'\\n'"),
+                '\t' => write!(f, "'\\t'"),
+                '\0' => write!(f, "'\\0'"),
+                c if c.is_control() => write!(f, "0x{:>04X}", *c as u32),
+                c => write!(f, "'{}'", c),
+            },
+            Node::Label(name) => write!(f, "{}", name),
+            Node::Symbol(name) => write!(f, "%{}", name),
+            Node::Negate(node) | Node::Not(node) => {
+                write!(
+                    f,
+                    "{}",
+                    if matches!(self, Node::Negate(_)) {
+                        "-"
+                    } else {
+                        "~"
+                    }
+                )?;
+                match node.precedence() < UNARY_PRECEDENCE {
+                    true => write!(f, "({})", node),
+                    false => write!(f, "{}", node),
+                }
+            }
+            Node::Call(function, node) => write!(f, "{}({})", function, node),
+            Node::Binary(operator, left, right) => {
+                // operators are left associative, so only the right side
+                // needs brackets at the same precedence
+                match left.precedence() < operator.precedence() {
+                    true => write!(f, "({})", left)?,
+                    false => write!(f, "{}", left)?,
+                }
+                write!(f, " {} ", operator)?;
+                match right.precedence() <= operator.precedence() {
+                    true => write!(f, "({})", right),
+                    false => write!(f, "{}", right),
+                }
+            }
+        }
+    }
+}
+
+// Expression - an operand worked out at assembly time from numbers,
+// characters, labels and symbols, errors point at the source it was
+// parsed from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression {
+    pub node: Node,
+    pub location: Option<SourceLocation>,
+}
+
+impl Expression {
+    pub fn new(node: Node) -> Self {
+        Self {
+            node,
+            location: None,
+        }
+    }
+
+    pub fn at(node: Node, location: SourceLocation) -> Self {
+        Self {
+            node,
+            location: Some(location),
+        }
+    }
+
+    pub fn evaluate(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<u16, Error> {
+        self.node
+            .evaluate(&resolver)
+            .and_then(|value| to_word(value, &self.node))
+            .map_err(|message| self.error(message))
+    }
+
+    // the value before it is cut to 16 bits, negative values wrap around
+    pub fn value(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<i64, Error> {
+        self.node
+            .evaluate(&resolver)
+            .map_err(|message| self.error(message))
+    }
+
+    fn error(&self, message: String) -> Error {
+        match &self.location {
+            Some(location) => Error::Parse(location.clone(), message),
+            None => Error::Expression(message),
+        }
+    }
+
+    pub fn reference(&self) -> Option<Reference> {
+        self.node.reference()
+    }
+}
+
+impl Marker for Expression {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestResolver;
+
+    impl Resolver for TestResolver {
+        fn label_resolver(&self, label: &Label) -> Result<u16, Error> {
+            match label.name.as_str() {
+                "start" => Ok(0x0500),
+                _ => Err(Error::UnknownLabel(label.name.clone())),
+            }
+        }
+
+        fn symbol_resolver(&self, symbol: &Symbol) -> Result<u16, Error> {
+            match symbol.name.as_str() {
+                "TEMP" => Ok(0xFF00),
+                _ => Err(Error::UnknownSymbol(symbol.name.clone())),
+            }
+        }
+    }
+
+    fn n(value: u16) -> Node {
+        Node::Number(value)
+    }
+
+    fn evaluate(node: Node) -> Result<u16, String> {
+        Expression::new(node)
+            .evaluate(Some(Rc::new(TestResolver)))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_expression_evaluate() {
+        let font = Node::binary(
+            Operator::Add,
+            Node::binary(Operator::Shl, Node::Char('A'), n(3)),
+            n(2),
+        );
+        assert_eq!(font.to_string(), "('A' << 0x0003) + 0x0002");
+        assert_eq!(evaluate(font), Ok(0x020A));
+
+        let cases = vec![
+            (
+                Node::binary(Operator::Add, Node::Symbol("TEMP".into()), n(0x23)),
+                0xFF23,
+            ),
+            (
+                Node::binary(Operator::Sub, Node::Label("start".into()), n(1)),
+                0x04FF,
+            ),
+            (Node::Call(Function::Hi, Box::new(n(0x1234))), 0x0012),
+            (Node::Call(Function::Lo, Box::new(n(0x1234))), 0x0034),
+            (Node::Not(Box::new(n(0x00FF))), 0xFF00),
+            (Node::Negate(Box::new(n(1))), 0xFFFF),
+            (
+                Node::binary(
+                    Operator::Or,
+                    Node::binary(Operator::And, n(0x0F0F), n(0x00FF)),
+                    Node::binary(
+                        Operator::Div,
+                        n(0x80),
+                        Node::binary(Operator::Shr, n(8), n(2)),
+                    ),
+                ),
+                0x004F,
+            ),
+        ];
+        for (node, value) in cases {
+            assert_eq!(evaluate(node.clone()), Ok(value), "{}", node);
+        }
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        let cases = vec![
+            (
+                Node::binary(Operator::Add, n(0xFFFF), n(1)),
+                "'0xFFFF + 0x0001' = 65536 does not fit in 16 bits",
+            ),
+            (
+                Node::binary(Operator::Div, n(1), Node::binary(Operator::Sub, n(1), n(1))),
+                "division by zero in '0x0001 / (0x0001 - 0x0001)'",
+            ),
+            (
+                Node::binary(Operator::Shl, n(1), n(64)),
+                "cannot shift by 64 in '0x0001 << 0x0040'",
+            ),
+            (
+                Node::binary(Operator::Shl, n(1), Node::binary(Operator::Mul, n(8), n(7))),
+                "'0x0001 << 0x0008 * 0x0007' = 72057594037927936 does not fit in 16 bits",
+            ),
+            (Node::Label("end".into()), "Unknown label: end"),
+        ];
+        for (node, message) in cases {
+            let error = evaluate(node).unwrap_err();
+            assert!(error.starts_with(message), "'{}' != '{}'", error, message);
+        }
+
+        let expression = Expression::at(
+            Node::binary(Operator::Mul, n(0x100), n(0x100)),
+            SourceLocation::new("test.asm", 3, 10),
+        );
+        assert_eq!(
+            expression.evaluate(None).unwrap_err().to_string(),
+            "test.asm:3:10: '0x0100 * 0x0100' = 65536 does not fit in 16 bits"
+        );
+    }
+}
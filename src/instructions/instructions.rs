@@ -1,7 +1,8 @@
 use super::{
     error::Error,
     markers::{Label, Marker, Number, Symbol},
-    IOMode, Instruction, Register, Resolver, NEXTINSTRUCTION,
+    Expression, IOMode, Instruction, Reference, Register, Resolver, SectionKind,
+    CURRENTINSTRUCTION, NEXTINSTRUCTION, STACK_POINTER, STACK_SCRATCH,
 };
 use std::{
     any::{Any, TypeId},
@@ -77,6 +78,14 @@ impl Instruction for LOAD {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.memory_address_reg]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.to_register]
+    }
 }
 
 // STORES
@@ -147,6 +156,10 @@ impl Instruction for STORE {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.from_register, self.to_register]
+    }
 }
 
 // DATA
@@ -166,6 +179,10 @@ impl<T: Marker> DATA<T> {
     pub fn new(to_register: Register, data: T) -> Self {
         Self { to_register, data }
     }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
 }
 
 impl<T: Marker> Display for DATA<T> {
@@ -195,6 +212,15 @@ impl<T: Marker> Instruction for DATA<T> {
                 instruction,
                 self.data.as_any().downcast_ref::<Number>().unwrap().value,
             ])
+        } else if TypeId::of::<Expression>() == self.data.type_id() {
+            Ok(vec![
+                instruction,
+                self.data
+                    .as_any()
+                    .downcast_ref::<Expression>()
+                    .unwrap()
+                    .evaluate(resolver)?,
+            ])
         } else {
             Err(Error::UnknownMarker(self.data.to_string()))
         }
@@ -207,6 +233,20 @@ impl<T: Marker> Instruction for DATA<T> {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.to_register]
+    }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        if let Some(symbol) = self.data.as_any().downcast_ref::<Symbol>() {
+            return vec![(1, Reference::Symbol(symbol.name.clone()))];
+        }
+        match self.data.as_any().downcast_ref::<Expression>() {
+            Some(expression) => expression.reference().map(|r| (1, r)).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
 }
 
 // JR
@@ -249,6 +289,10 @@ impl Instruction for JR {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
 }
 
 // JMP
@@ -287,6 +331,10 @@ impl Instruction for JMP {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        vec![(1, Reference::Label(self.jump_location.name.clone()))]
+    }
 }
 
 // JMP(CAEZ)
@@ -361,6 +409,10 @@ impl Instruction for JMPF {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        vec![(1, Reference::Label(self.jump_location.name.clone()))]
+    }
 }
 
 // CLF (CLEAR FLAGS)
@@ -442,6 +494,10 @@ impl Instruction for IN {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.to_register]
+    }
 }
 
 // OUT
@@ -492,6 +548,10 @@ impl Instruction for OUT {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.to_register]
+    }
 }
 
 // ADDS
@@ -555,6 +615,14 @@ impl Instruction for ADD {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b]
+    }
 }
 
 // SHL
@@ -596,6 +664,14 @@ impl Instruction for SHL {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
 }
 
 // SHR
@@ -637,6 +713,14 @@ impl Instruction for SHR {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
 }
 
 // NOT
@@ -678,6 +762,14 @@ impl Instruction for NOT {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
 }
 
 // ANDS
@@ -741,6 +833,14 @@ impl Instruction for AND {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b]
+    }
 }
 
 // ORS
@@ -804,6 +904,14 @@ impl Instruction for OR {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b]
+    }
 }
 
 // XORS
@@ -847,135 +955,1638 @@ impl Display for XOR {
     }
 }
 
-impl Instruction for XOR {
-    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
-        Ok(vec![
-            match self.register_a {
-                Register::REG0 => Ok(0x00E0),
-                Register::REG1 => Ok(0x00E4),
-                Register::REG2 => Ok(0x00E8),
-                Register::REG3 => Ok(0x00EC),
-            }? as u16
-                + self.register_b as u16,
-        ])
+impl Instruction for XOR {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![
+            match self.register_a {
+                Register::REG0 => Ok(0x00E0),
+                Register::REG1 => Ok(0x00E4),
+                Register::REG2 => Ok(0x00E8),
+                Register::REG3 => Ok(0x00EC),
+            }? as u16
+                + self.register_b as u16,
+        ])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b]
+    }
+}
+
+// CMP
+// ----------------------
+// 0x00F0 = CMP R0, R0
+// 0x00F1 = CMP R0, R1
+// 0x00F2 = CMP R0, R2
+// 0x00F3 = CMP R0, R3
+
+// 0x00F4 = CMP R1, R0
+// 0x00F5 = CMP R1, R1
+// 0x00F6 = CMP R1, R2
+// 0x00F7 = CMP R1, R3
+
+// 0x00F8 = CMP R2, R0
+// 0x00F9 = CMP R2, R1
+// 0x00FA = CMP R2, R2
+// 0x00FB = CMP R2, R3
+
+// 0x00FC = CMP R3, R0
+// 0x00FD = CMP R3, R1
+// 0x00FE = CMP R3, R2
+// 0x00FF = CMP R3, R3
+pub struct CMP {
+    register_a: Register,
+    register_b: Register,
+}
+
+impl CMP {
+    pub fn new(register_a: Register, register_b: Register) -> CMP {
+        CMP {
+            register_a,
+            register_b,
+        }
+    }
+}
+
+impl Display for CMP {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CMP R{}, R{}", self.register_a, self.register_b)
+    }
+}
+
+impl Instruction for CMP {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![
+            match self.register_a {
+                Register::REG0 => Ok(0x00F0),
+                Register::REG1 => Ok(0x00F4),
+                Register::REG2 => Ok(0x00F8),
+                Register::REG3 => Ok(0x00FC),
+            }? as u16
+                + self.register_b as u16,
+        ])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+}
+
+// HPUSH
+// decrement the stack pointer and store the register at its address
+// ----------------------
+// 0x0100 = HPUSH R0
+// 0x0101 = HPUSH R1
+// 0x0102 = HPUSH R2
+// 0x0103 = HPUSH R3
+pub struct HPUSH {
+    register: Register,
+}
+
+impl HPUSH {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for HPUSH {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HPUSH R{}", self.register as u16)
+    }
+}
+
+impl Instruction for HPUSH {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0100 + self.register as u16])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// HPOP
+// load the register from the stack pointer's address and increment it
+// ----------------------
+// 0x0110 = HPOP R0
+// 0x0111 = HPOP R1
+// 0x0112 = HPOP R2
+// 0x0113 = HPOP R3
+pub struct HPOP {
+    register: Register,
+}
+
+impl HPOP {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for HPOP {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HPOP R{}", self.register as u16)
+    }
+}
+
+impl Instruction for HPOP {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0110 + self.register as u16])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// HCALL
+// push the address of the next instruction and jump to the address in the
+// register
+// ----------------------
+// 0x0120 = HCALL R0
+// 0x0121 = HCALL R1
+// 0x0122 = HCALL R2
+// 0x0123 = HCALL R3
+pub struct HCALL {
+    register: Register,
+}
+
+impl HCALL {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for HCALL {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HCALL R{}", self.register as u16)
+    }
+}
+
+impl Instruction for HCALL {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0120 + self.register as u16])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// HRET
+// pop the return address into the instruction address register
+// ----------------------
+// 0x0130 = HRET
+pub struct HRET {}
+
+impl HRET {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for HRET {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HRET")
+    }
+}
+
+impl Instruction for HRET {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0130])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// SETSP
+// set the stack pointer to the value in the register
+// ----------------------
+// 0x0140 = SETSP R0
+// 0x0141 = SETSP R1
+// 0x0142 = SETSP R2
+// 0x0143 = SETSP R3
+pub struct SETSP {
+    register: Register,
+}
+
+impl SETSP {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for SETSP {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SETSP R{}", self.register as u16)
+    }
+}
+
+impl Instruction for SETSP {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0140 + self.register as u16])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// GETSP
+// copy the stack pointer into the register
+// ----------------------
+// 0x0150 = GETSP R0
+// 0x0151 = GETSP R1
+// 0x0152 = GETSP R2
+// 0x0153 = GETSP R3
+pub struct GETSP {
+    register: Register,
+}
+
+impl GETSP {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for GETSP {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GETSP R{}", self.register as u16)
+    }
+}
+
+impl Instruction for GETSP {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0150 + self.register as u16])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// IRET
+// return from an interrupt handler, pop the instruction address register,
+// restore the flags saved on entry and turn interrupts back on
+// ----------------------
+// 0x0160 = IRET
+pub struct IRET {}
+
+impl IRET {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for IRET {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IRET")
+    }
+}
+
+impl Instruction for IRET {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0160])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// EI
+// let the interrupt controller interrupt the CPU
+// ----------------------
+// 0x0164 = EI
+pub struct EI {}
+
+impl EI {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for EI {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EI")
+    }
+}
+
+impl Instruction for EI {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0164])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// DI
+// keep the CPU from taking interrupts
+// ----------------------
+// 0x0168 = DI
+pub struct DI {}
+
+impl DI {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for DI {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DI")
+    }
+}
+
+impl Instruction for DI {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0168])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// SETIV
+// set the interrupt vector, the address interrupts jump to
+// ----------------------
+// 0x0170 = SETIV R0
+// 0x0171 = SETIV R1
+// 0x0172 = SETIV R2
+// 0x0173 = SETIV R3
+pub struct SETIV {
+    register: Register,
+}
+
+impl SETIV {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for SETIV {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SETIV R{}", self.register as u16)
+    }
+}
+
+impl Instruction for SETIV {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0170 + self.register as u16])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// INT
+// interrupt from software, exactly what the CPU does when it takes a
+// hardware interrupt
+// ----------------------
+// 0x0178 = INT
+pub struct INT {}
+
+impl INT {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for INT {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "INT")
+    }
+}
+
+impl Instruction for INT {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0178])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// HALT
+// stop the CPU, R0 is the exit status
+// ----------------------
+// 0x0180 = HALT
+pub struct HALT {}
+
+impl HALT {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for HALT {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HALT")
+    }
+}
+
+impl Instruction for HALT {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![0x0180])
+    }
+
+    fn size(&self) -> u16 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// PSUEDO INSTRUCTIONS - these are  composite instructions that may map to multiple opcodes
+
+// the stack grows down from STACK_TOP and STACK_POINTER holds the address of
+// the last word pushed. The expansions below use R3 as scratch and borrow one
+// more register through STACK_SCRATCH, so they clobber R3 and the flags and
+// leave R0 - R2 as they were
+
+// the words of a sequence of opcodes
+fn emit_all(
+    instructions: Vec<Box<dyn Instruction>>,
+    resolver: Option<Rc<dyn Resolver>>,
+) -> Result<Vec<u16>, Error> {
+    let mut emitted = Vec::new();
+    for i in instructions.iter() {
+        emitted.append(&mut i.emit(resolver.clone())?);
+    }
+    Ok(emitted)
+}
+
+// adds by to the stack pointer, 0xFFFF moves it down a word, with borrowed
+// as scratch. The new stack pointer is left in R3 and in memory
+fn move_stack_pointer(borrowed: Register, by: u16) -> Vec<Box<dyn Instruction>> {
+    vec![
+        Box::new(DATA::new(Register::REG3, Number::new(STACK_POINTER))),
+        Box::new(LOAD::new(Register::REG3, Register::REG3)),
+        Box::new(DATA::new(borrowed, Number::new(by))),
+        Box::new(CLF::new()),
+        Box::new(ADD::new(borrowed, Register::REG3)),
+        Box::new(DATA::new(borrowed, Number::new(STACK_POINTER))),
+        Box::new(STORE::new(borrowed, Register::REG3)),
+    ]
+}
+
+// R3 is the scratch register, so it cannot be an operand itself
+fn scratch_register(instruction: &dyn Display, register: Register) -> Result<(), Error> {
+    match register {
+        Register::REG3 => Err(Error::ScratchRegister(instruction.to_string())),
+        _ => Ok(()),
+    }
+}
+
+// CALL
+// pushes the address of the next instruction and jumps to the routine, R2
+// is borrowed to hold the return address (21 word instruction)
+// ----------------------
+// 0x0023 STACK_SCRATCH, 0x001E = save R2
+// 0x0023 STACK_POINTER, 0x000F, 0x0022 0xFFFF, 0x0060, 0x008B,
+// 0x0022 STACK_POINTER, 0x001B = decrement the stack pointer
+// 0x0022 <next>, 0x001E = store the return address on the stack
+// 0x0022 STACK_SCRATCH, 0x000A = restore R2
+// 0x0040 <routine>
+pub struct CALL {
+    routine: Label,
+}
+
+impl CALL {
+    pub fn new(routine: Label) -> CALL {
+        CALL { routine }
+    }
+}
+
+impl Display for CALL {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CALL {}", self.routine)
+    }
+}
+
+impl Instruction for CALL {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        let next_instruction_address = resolver
+            .clone()
+            .as_ref()
+            .unwrap()
+            .symbol_resolver(&Symbol::new(NEXTINSTRUCTION))?;
+
+        let mut composite_instructions: Vec<Box<dyn Instruction>> = vec![
+            Box::new(DATA::new(Register::REG3, Number::new(STACK_SCRATCH))),
+            Box::new(STORE::new(Register::REG3, Register::REG2)),
+        ];
+        composite_instructions.append(&mut move_stack_pointer(Register::REG2, 0xFFFF));
+        composite_instructions.append(&mut vec![
+            Box::new(DATA::new(
+                Register::REG2,
+                Number::new(next_instruction_address),
+            )),
+            Box::new(STORE::new(Register::REG3, Register::REG2)),
+            Box::new(DATA::new(Register::REG2, Number::new(STACK_SCRATCH))),
+            Box::new(LOAD::new(Register::REG2, Register::REG2)),
+            Box::new(JMP::new(self.routine.clone())),
+        ]);
+
+        emit_all(composite_instructions, resolver)
+    }
+
+    fn size(&self) -> u16 {
+        21
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![Register::REG3]
+    }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        vec![
+            (14, Reference::Symbol(NEXTINSTRUCTION.to_string())),
+            (20, Reference::Label(self.routine.name.clone())),
+        ]
+    }
+}
+
+// RET
+// pops the return address pushed by CALL into R3 and jumps to it, R2 is
+// borrowed to move the stack pointer (22 word instruction)
+// ----------------------
+// 0x0023 STACK_SCRATCH, 0x001E = save R2
+// 0x0023 STACK_POINTER, 0x000F, 0x0022 0x0001, 0x0060, 0x008B,
+// 0x0022 STACK_POINTER, 0x001B = increment the stack pointer
+// 0x0022 0xFFFF, 0x0060, 0x008B, 0x000F = load the return address
+// 0x0022 STACK_SCRATCH, 0x000A = restore R2
+// 0x0033
+pub struct RET {}
+
+impl RET {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Display for RET {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RET")
+    }
+}
+
+impl Instruction for RET {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        let mut composite_instructions: Vec<Box<dyn Instruction>> = vec![
+            Box::new(DATA::new(Register::REG3, Number::new(STACK_SCRATCH))),
+            Box::new(STORE::new(Register::REG3, Register::REG2)),
+        ];
+        composite_instructions.append(&mut move_stack_pointer(Register::REG2, 0x0001));
+        composite_instructions.append(&mut vec![
+            Box::new(DATA::new(Register::REG2, Number::new(0xFFFF))),
+            Box::new(CLF::new()),
+            Box::new(ADD::new(Register::REG2, Register::REG3)),
+            Box::new(LOAD::new(Register::REG3, Register::REG3)),
+            Box::new(DATA::new(Register::REG2, Number::new(STACK_SCRATCH))),
+            Box::new(LOAD::new(Register::REG2, Register::REG2)),
+            Box::new(JR::new(Register::REG3)),
+        ]);
+
+        emit_all(composite_instructions, resolver)
+    }
+
+    fn size(&self) -> u16 {
+        22
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![Register::REG3]
+    }
+}
+
+// PUSH
+// decrements the stack pointer and stores the register there, the register
+// itself is borrowed and reloaded from STACK_SCRATCH (17 word instruction)
+// ----------------------
+// 0x0023 STACK_SCRATCH, ST R3, Rn = save Rn
+// 0x0023 STACK_POINTER, 0x000F, DATA Rn 0xFFFF, 0x0060, ADD Rn, R3,
+// DATA Rn STACK_POINTER, ST Rn, R3 = decrement the stack pointer
+// DATA Rn STACK_SCRATCH, LD Rn, Rn = restore Rn
+// ST R3, Rn
+pub struct PUSH {
+    register: Register,
+}
+
+impl PUSH {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for PUSH {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PUSH R{}", self.register)
+    }
+}
+
+impl Instruction for PUSH {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        scratch_register(self, self.register)?;
+
+        let mut composite_instructions: Vec<Box<dyn Instruction>> = vec![
+            Box::new(DATA::new(Register::REG3, Number::new(STACK_SCRATCH))),
+            Box::new(STORE::new(Register::REG3, self.register)),
+        ];
+        composite_instructions.append(&mut move_stack_pointer(self.register, 0xFFFF));
+        composite_instructions.append(&mut vec![
+            Box::new(DATA::new(self.register, Number::new(STACK_SCRATCH))),
+            Box::new(LOAD::new(self.register, self.register)),
+            Box::new(STORE::new(Register::REG3, self.register)),
+        ]);
+
+        emit_all(composite_instructions, resolver)
+    }
+
+    fn size(&self) -> u16 {
+        17
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![Register::REG3]
+    }
+}
+
+// POP
+// loads the word at the stack pointer into the register and increments the
+// stack pointer (15 word instruction)
+// ----------------------
+// 0x0023 STACK_POINTER, 0x000F, DATA Rn 0x0001, 0x0060, ADD Rn, R3,
+// DATA Rn STACK_POINTER, ST Rn, R3 = increment the stack pointer
+// DATA Rn 0xFFFF, 0x0060, ADD Rn, R3, LD R3, Rn = load the word
+pub struct POP {
+    register: Register,
+}
+
+impl POP {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for POP {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "POP R{}", self.register)
+    }
+}
+
+impl Instruction for POP {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        scratch_register(self, self.register)?;
+
+        let mut composite_instructions = move_stack_pointer(self.register, 0x0001);
+        composite_instructions.append(&mut vec![
+            Box::new(DATA::new(self.register, Number::new(0xFFFF))),
+            Box::new(CLF::new()),
+            Box::new(ADD::new(self.register, Register::REG3)),
+            Box::new(LOAD::new(Register::REG3, self.register)),
+        ]);
+
+        emit_all(composite_instructions, resolver)
+    }
+
+    fn size(&self) -> u16 {
+        15
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register, Register::REG3]
+    }
+}
+
+// the arithmetic pseudo instructions follow the ALU's operand order, Rb is
+// the destination as in ADD Ra, Rb. Unless noted they borrow a register
+// other than their operands and give it back, so only the flags are clobbered
+
+// the first of R0 - R2 not in used, an expansion borrows it and gives it
+// back before it ends
+fn free_register(used: &[Register]) -> Register {
+    [Register::REG0, Register::REG1, Register::REG2]
+        .into_iter()
+        .find(|r| !used.contains(r))
+        .unwrap()
+}
+
+// MUL and DIV keep their loop in one instruction, they need two different
+// registers next to R3
+fn loop_registers(
+    instruction: &dyn Display,
+    register_a: Register,
+    register_b: Register,
+) -> Result<(), Error> {
+    scratch_register(instruction, register_a)?;
+    scratch_register(instruction, register_b)?;
+    match register_a == register_b {
+        true => Err(Error::SameRegister(instruction.to_string())),
+        false => Ok(()),
+    }
+}
+
+// resolves the labels of a loop inside an expansion from the expansion's
+// first word, everything else goes to the assembler
+#[derive(Clone)]
+struct Expansion {
+    start: u16,
+    labels: Vec<(&'static str, u16)>,
+    resolver: Rc<dyn Resolver>,
+}
+
+impl Expansion {
+    fn new(
+        labels: Vec<(&'static str, u16)>,
+        resolver: Option<Rc<dyn Resolver>>,
+    ) -> Result<Self, Error> {
+        let resolver =
+            resolver.ok_or_else(|| Error::UnknownSymbol(CURRENTINSTRUCTION.to_string()))?;
+        Ok(Self {
+            start: resolver.symbol_resolver(&Symbol::new(CURRENTINSTRUCTION))?,
+            labels,
+            resolver,
+        })
+    }
+}
+
+impl Resolver for Expansion {
+    fn label_resolver(&self, label: &Label) -> Result<u16, Error> {
+        match self.labels.iter().find(|(name, _)| *name == label.name) {
+            Some((_, offset)) => Ok(self.start.wrapping_add(*offset)),
+            None => self.resolver.label_resolver(label),
+        }
+    }
+
+    fn symbol_resolver(&self, symbol: &Symbol) -> Result<u16, Error> {
+        self.resolver.symbol_resolver(symbol)
+    }
+}
+
+// MOV
+// copies Ra into Rb, Z is set when the value is 0 (2 word instruction)
+// ----------------------
+// XOR Rb, Rb, OR Ra, Rb
+// MOV Rn, Rn is OR Rn, Rn (1 word)
+pub struct MOV {
+    register_a: Register,
+    register_b: Register,
+}
+
+impl MOV {
+    pub fn new(register_a: Register, register_b: Register) -> Self {
+        Self {
+            register_a,
+            register_b,
+        }
+    }
+
+    fn expand(&self) -> Vec<Box<dyn Instruction>> {
+        if self.register_a == self.register_b {
+            return vec![Box::new(OR::new(self.register_a, self.register_b))];
+        }
+        vec![
+            Box::new(XOR::new(self.register_b, self.register_b)),
+            Box::new(OR::new(self.register_a, self.register_b)),
+        ]
+    }
+}
+
+impl Display for MOV {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MOV R{}, R{}", self.register_a, self.register_b)
+    }
+}
+
+impl Instruction for MOV {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        emit_all(self.expand(), resolver)
+    }
+
+    fn size(&self) -> u16 {
+        self.expand().iter().map(|i| i.size()).sum()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b]
+    }
+}
+
+// SUB
+// subtracts Ra from Rb as ~(~Rb + Ra), Ra is left alone. Z is set when the
+// result is 0, the other flags are clobbered (4 word instruction)
+// ----------------------
+// NOT Rb, CLF, ADD Ra, Rb, NOT Rb
+// SUB Rn, Rn is XOR Rn, Rn (1 word)
+pub struct SUB {
+    register_a: Register,
+    register_b: Register,
+}
+
+impl SUB {
+    pub fn new(register_a: Register, register_b: Register) -> Self {
+        Self {
+            register_a,
+            register_b,
+        }
+    }
+
+    fn expand(&self) -> Vec<Box<dyn Instruction>> {
+        if self.register_a == self.register_b {
+            return vec![Box::new(XOR::new(self.register_a, self.register_b))];
+        }
+        vec![
+            Box::new(NOT::new(self.register_b)),
+            Box::new(CLF::new()),
+            Box::new(ADD::new(self.register_a, self.register_b)),
+            Box::new(NOT::new(self.register_b)),
+        ]
+    }
+}
+
+impl Display for SUB {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SUB R{}, R{}", self.register_a, self.register_b)
+    }
+}
+
+impl Instruction for SUB {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        emit_all(self.expand(), resolver)
+    }
+
+    fn size(&self) -> u16 {
+        self.expand().iter().map(|i| i.size()).sum()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b]
+    }
+}
+
+// DEC
+// subtracts 1 from Rn by adding Rx + ~Rx = 0xFFFF, Rx is R0 or R1 and is
+// given back. Z is set when the result is 0, the other flags are clobbered
+// (6 word instruction)
+// ----------------------
+// NOT Rx, CLF, ADD Rx, Rn, NOT Rx, CLF, ADD Rx, Rn
+pub struct DEC {
+    register: Register,
+}
+
+impl DEC {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+
+    fn expand(&self) -> Vec<Box<dyn Instruction>> {
+        let borrowed = free_register(&[self.register]);
+        vec![
+            Box::new(NOT::new(borrowed)),
+            Box::new(CLF::new()),
+            Box::new(ADD::new(borrowed, self.register)),
+            Box::new(NOT::new(borrowed)),
+            Box::new(CLF::new()),
+            Box::new(ADD::new(borrowed, self.register)),
+        ]
+    }
+}
+
+impl Display for DEC {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DEC R{}", self.register)
+    }
+}
+
+impl Instruction for DEC {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        emit_all(self.expand(), resolver)
+    }
+
+    fn size(&self) -> u16 {
+        6
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// NEG
+// negates Rn as ~(Rn - 1). Z is set when the result is 0, the other flags
+// are clobbered (7 word instruction)
+// ----------------------
+// DEC Rn, NOT Rn
+pub struct NEG {
+    register: Register,
+}
+
+impl NEG {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for NEG {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NEG R{}", self.register)
+    }
+}
+
+impl Instruction for NEG {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        emit_all(
+            vec![
+                Box::new(DEC::new(self.register)),
+                Box::new(NOT::new(self.register)),
+            ],
+            resolver,
+        )
+    }
+
+    fn size(&self) -> u16 {
+        7
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// INC
+// adds 1 to Rn as ~(~Rn - 1). Z is set when the result is 0, the other
+// flags are clobbered (8 word instruction)
+// ----------------------
+// NOT Rn, DEC Rn, NOT Rn
+pub struct INC {
+    register: Register,
+}
+
+impl INC {
+    pub fn new(register: Register) -> Self {
+        Self { register }
+    }
+}
+
+impl Display for INC {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "INC R{}", self.register)
+    }
+}
+
+impl Instruction for INC {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        emit_all(
+            vec![
+                Box::new(NOT::new(self.register)),
+                Box::new(DEC::new(self.register)),
+                Box::new(NOT::new(self.register)),
+            ],
+            resolver,
+        )
+    }
+
+    fn size(&self) -> u16 {
+        8
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+}
+
+// CMPI
+// compares Rn with a value loaded into R3, the flags are set as by
+// CMP Rn, R3 and R3 is clobbered (3 word instruction)
+// ----------------------
+// DATA R3 <value>, CMP Rn, R3
+pub struct CMPI<T: Marker> {
+    register: Register,
+    data: DATA<T>,
+}
+
+impl<T: Marker> CMPI<T> {
+    pub fn new(register: Register, value: T) -> Self {
+        Self {
+            register,
+            data: DATA::new(Register::REG3, value),
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        self.data.data()
+    }
+}
+
+impl<T: Marker> Display for CMPI<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CMPI R{}, {}", self.register, self.value())
+    }
+}
+
+impl<T: Marker> Instruction for CMPI<T> {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        scratch_register(self, self.register)?;
+
+        let mut emitted = self.data.emit(resolver.clone())?;
+        emitted.append(&mut CMP::new(self.register, Register::REG3).emit(resolver)?);
+        Ok(emitted)
+    }
+
+    fn size(&self) -> u16 {
+        3
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![Register::REG3]
+    }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        self.data.references()
+    }
+}
+
+// MUL
+// multiplies Rb by Ra, keeping the low 16 bits. Shift and add, Ra is shifted
+// right until it is 0 and the product built up in R3, Ra is saved in
+// STACK_SCRATCH and reloaded. R3 and the flags are clobbered
+// (24 word instruction)
+// ----------------------
+// DATA R3 STACK_SCRATCH, ST R3, Ra, XOR R3, R3
+// loop:  CLF, SHR Ra, JMPC add, JMP shift
+// add:   CLF, ADD Rb, R3
+// shift: CLF, SHL Rb, OR Ra, Ra, JMPZ done, JMP loop
+// done:  MOV R3, Rb, DATA Ra STACK_SCRATCH, LD Ra, Ra
+pub struct MUL {
+    register_a: Register,
+    register_b: Register,
+}
+
+impl MUL {
+    pub fn new(register_a: Register, register_b: Register) -> Self {
+        Self {
+            register_a,
+            register_b,
+        }
+    }
+}
+
+impl Display for MUL {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MUL R{}, R{}", self.register_a, self.register_b)
+    }
+}
+
+impl Instruction for MUL {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        loop_registers(self, self.register_a, self.register_b)?;
+
+        let (a, b) = (self.register_a, self.register_b);
+        let composite_instructions: Vec<Box<dyn Instruction>> = vec![
+            Box::new(DATA::new(Register::REG3, Number::new(STACK_SCRATCH))),
+            Box::new(STORE::new(Register::REG3, a)),
+            Box::new(XOR::new(Register::REG3, Register::REG3)),
+            Box::new(CLF::new()),
+            Box::new(SHR::new(a)),
+            Box::new(JMPF::new(vec!["C".to_string()], Label::new("add"))),
+            Box::new(JMP::new(Label::new("shift"))),
+            Box::new(CLF::new()),
+            Box::new(ADD::new(b, Register::REG3)),
+            Box::new(CLF::new()),
+            Box::new(SHL::new(b)),
+            Box::new(OR::new(a, a)),
+            Box::new(JMPF::new(vec!["Z".to_string()], Label::new("done"))),
+            Box::new(JMP::new(Label::new("loop"))),
+            Box::new(MOV::new(Register::REG3, b)),
+            Box::new(DATA::new(a, Number::new(STACK_SCRATCH))),
+            Box::new(LOAD::new(a, a)),
+        ];
+        let labels = vec![("loop", 4), ("add", 10), ("shift", 12), ("done", 19)];
+
+        emit_all(
+            composite_instructions,
+            Some(Rc::new(Expansion::new(labels, resolver)?)),
+        )
+    }
+
+    fn size(&self) -> u16 {
+        24
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b, Register::REG3]
+    }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        [7, 9, 16, 18]
+            .into_iter()
+            .map(|offset| (offset, Reference::Symbol(CURRENTINSTRUCTION.to_string())))
+            .collect()
+    }
+}
+
+// DIV
+// divides Rb by Ra, the quotient goes to Rb and the remainder to R3. Long
+// division one bit at a time, Rb shifts the dividend out into R3 and the
+// quotient in, Rx counts the 16 rounds and is saved in STACK_SCRATCH. The
+// remainder stays below Ra, so it never shifts out of R3. Dividing by 0
+// gives 0xFFFF with the dividend as remainder. The flags are clobbered
+// (33 word instruction)
+// ----------------------
+// DATA R3 STACK_SCRATCH, ST R3, Rx, DATA Rx 0x0001, XOR R3, R3
+// loop: CLF, SHL Rb, SHL R3, CMP Ra, R3, JMPA next
+//       SUB Ra, R3, INC Rb
+// next: CLF, SHL Rx, JMPC done, JMP loop
+// done: DATA Rx STACK_SCRATCH, LD Rx, Rx
+pub struct DIV {
+    register_a: Register,
+    register_b: Register,
+}
+
+impl DIV {
+    pub fn new(register_a: Register, register_b: Register) -> Self {
+        Self {
+            register_a,
+            register_b,
+        }
+    }
+}
+
+impl Display for DIV {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DIV R{}, R{}", self.register_a, self.register_b)
+    }
+}
+
+impl Instruction for DIV {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        loop_registers(self, self.register_a, self.register_b)?;
+
+        let (a, b) = (self.register_a, self.register_b);
+        let counter = free_register(&[a, b]);
+        let composite_instructions: Vec<Box<dyn Instruction>> = vec![
+            Box::new(DATA::new(Register::REG3, Number::new(STACK_SCRATCH))),
+            Box::new(STORE::new(Register::REG3, counter)),
+            Box::new(DATA::new(counter, Number::new(0x0001))),
+            Box::new(XOR::new(Register::REG3, Register::REG3)),
+            Box::new(CLF::new()),
+            Box::new(SHL::new(b)),
+            Box::new(SHL::new(Register::REG3)),
+            Box::new(CMP::new(a, Register::REG3)),
+            Box::new(JMPF::new(vec!["A".to_string()], Label::new("next"))),
+            Box::new(SUB::new(a, Register::REG3)),
+            Box::new(INC::new(b)),
+            Box::new(CLF::new()),
+            Box::new(SHL::new(counter)),
+            Box::new(JMPF::new(vec!["C".to_string()], Label::new("done"))),
+            Box::new(JMP::new(Label::new("loop"))),
+            Box::new(DATA::new(counter, Number::new(STACK_SCRATCH))),
+            Box::new(LOAD::new(counter, counter)),
+        ];
+        let labels = vec![("loop", 6), ("next", 24), ("done", 30)];
+
+        emit_all(
+            composite_instructions,
+            Some(Rc::new(Expansion::new(labels, resolver)?)),
+        )
+    }
+
+    fn size(&self) -> u16 {
+        33
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        vec![self.register_a, self.register_b]
+    }
+
+    fn writes(&self) -> Vec<Register> {
+        vec![self.register_b, Register::REG3]
+    }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        [11, 27, 29]
+            .into_iter()
+            .map(|offset| (offset, Reference::Symbol(CURRENTINSTRUCTION.to_string())))
+            .collect()
+    }
+}
+
+// PLACEHOLDER INSTRUCTIONS - these are used by the assembler
+pub struct DEFLABEL {
+    pub name: String,
+}
+
+impl DEFLABEL {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Display for DEFLABEL {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Instruction for DEFLABEL {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![])
+    }
+
+    fn size(&self) -> u16 {
+        0
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// DEFSYMBOL - a named value, either a number or an expression the assembler
+// works out once every label is placed
+pub struct DEFSYMBOL {
+    pub name: String,
+    pub value: u16,
+    pub expression: Option<Expression>,
+}
+
+impl DEFSYMBOL {
+    pub fn new(name: &str, value: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+            expression: None,
+        }
+    }
+
+    pub fn from_expression(name: &str, expression: Expression) -> Self {
+        Self {
+            name: name.to_string(),
+            value: 0,
+            expression: Some(expression),
+        }
+    }
+}
+
+impl Display for DEFSYMBOL {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.expression {
+            Some(expression) => write!(f, "%{} = {}", self.name, expression),
+            None => write!(f, "%{} = 0x{:X}", self.name, self.value),
+        }
+    }
+}
+
+impl Instruction for DEFSYMBOL {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![])
+    }
+
+    fn size(&self) -> u16 {
+        0
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// SECTION - what follows goes to this section of an object file, a flat
+// binary keeps everything in source order
+pub struct SECTION {
+    pub kind: SectionKind,
+}
+
+impl SECTION {
+    pub fn new(kind: SectionKind) -> Self {
+        Self { kind }
+    }
+}
+
+impl Display for SECTION {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ".{}", self.kind)
+    }
+}
+
+impl Instruction for SECTION {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![])
+    }
+
+    fn size(&self) -> u16 {
+        0
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// WORD - .word, the values stored as they are
+pub struct WORD {
+    values: Vec<Expression>,
+}
+
+impl WORD {
+    pub fn new(values: Vec<Expression>) -> Self {
+        Self { values }
+    }
+}
+
+impl Display for WORD {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+        write!(f, ".word {}", values.join(", "))
+    }
+}
+
+impl Instruction for WORD {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        self.values
+            .iter()
+            .map(|v| v.evaluate(resolver.clone()))
+            .collect()
     }
 
     fn size(&self) -> u16 {
-        1
+        self.values.len() as u16
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
-}
-
-// CMP
-// ----------------------
-// 0x00F0 = CMP R0, R0
-// 0x00F1 = CMP R0, R1
-// 0x00F2 = CMP R0, R2
-// 0x00F3 = CMP R0, R3
-
-// 0x00F4 = CMP R1, R0
-// 0x00F5 = CMP R1, R1
-// 0x00F6 = CMP R1, R2
-// 0x00F7 = CMP R1, R3
 
-// 0x00F8 = CMP R2, R0
-// 0x00F9 = CMP R2, R1
-// 0x00FA = CMP R2, R2
-// 0x00FB = CMP R2, R3
+    fn references(&self) -> Vec<(u16, Reference)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| v.reference().map(|r| (i as u16, r)))
+            .collect()
+    }
+}
 
-// 0x00FC = CMP R3, R0
-// 0x00FD = CMP R3, R1
-// 0x00FE = CMP R3, R2
-// 0x00FF = CMP R3, R3
-pub struct CMP {
-    register_a: Register,
-    register_b: Register,
+// FILL - .fill count, value, count copies of one word
+pub struct FILL {
+    count: u16,
+    value: Expression,
 }
 
-impl CMP {
-    pub fn new(register_a: Register, register_b: Register) -> CMP {
-        CMP {
-            register_a,
-            register_b,
-        }
+impl FILL {
+    pub fn new(count: u16, value: Expression) -> Self {
+        Self { count, value }
     }
 }
 
-impl Display for CMP {
+impl Display for FILL {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "CMP R{}, R{}", self.register_a, self.register_b)
+        write!(f, ".fill 0x{:>04X}, {}", self.count, self.value)
     }
 }
 
-impl Instruction for CMP {
-    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
-        Ok(vec![
-            match self.register_a {
-                Register::REG0 => Ok(0x00F0),
-                Register::REG1 => Ok(0x00F4),
-                Register::REG2 => Ok(0x00F8),
-                Register::REG3 => Ok(0x00FC),
-            }? as u16
-                + self.register_b as u16,
-        ])
+impl Instruction for FILL {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![self.value.evaluate(resolver)?; self.count as usize])
     }
 
     fn size(&self) -> u16 {
-        1
+        self.count
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn references(&self) -> Vec<(u16, Reference)> {
+        match self.value.reference() {
+            Some(r) => (0..self.count).map(|i| (i, r.clone())).collect(),
+            None => Vec::new(),
+        }
+    }
 }
 
-// PSUEDO INSTRUCTIONS - these are  composite instructions that may map to multiple opcodes
-pub struct CALL {
-    routine: Label,
+// ASCII - .ascii "text" is one character per word, .string adds a 0 word
+pub struct ASCII {
+    text: String,
+    terminated: bool,
 }
 
-impl CALL {
-    pub fn new(routine: Label) -> CALL {
-        CALL { routine }
+impl ASCII {
+    pub fn new(text: &str, terminated: bool) -> Self {
+        Self {
+            text: text.to_string(),
+            terminated,
+        }
     }
 }
 
-impl Display for CALL {
+impl Display for ASCII {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "CALL {}", self.routine)
+        let directive = if self.terminated { ".string" } else { ".ascii" };
+        write!(f, "{} \"", directive)?;
+        for c in self.text.chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{}", c)?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\0' => write!(f, "\\0")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
     }
 }
 
-impl Instruction for CALL {
-    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
-        let next_instruction_address = resolver
-            .clone()
-            .as_ref()
-            .unwrap()
-            .symbol_resolver(&Symbol::new(NEXTINSTRUCTION))?;
-
-        let composite_instructions: Vec<Box<dyn Instruction>> = vec![
-            Box::new(DATA::new(
-                Register::REG3,
-                Number::new(next_instruction_address),
-            )),
-            Box::new(JMP::new(self.routine.clone())),
-        ];
-
-        let mut emiited = Vec::new();
-        for i in composite_instructions.iter() {
-            let mut e = i.emit(resolver.clone())?;
-            emiited.append(&mut e);
+impl Instruction for ASCII {
+    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        let mut words: Vec<u16> = self.text.chars().map(|c| c as u16).collect();
+        if self.terminated {
+            words.push(0);
         }
-
-        Ok(emiited)
+        Ok(words)
     }
 
     fn size(&self) -> u16 {
-        4
+        self.text.chars().count() as u16 + self.terminated as u16
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -983,28 +2594,30 @@ impl Instruction for CALL {
     }
 }
 
-// PLACEHOLDER INSTRUCTIONS - these are used by the assembler
-pub struct DEFLABEL {
-    pub name: String,
+// ORG - .org address, pads with 0 up to address, which may not be behind
+// the current one
+pub struct ORG {
+    address: u16,
 }
 
-impl DEFLABEL {
-    pub fn new(name: &str) -> Self {
-        Self {
-            name: name.to_string(),
-        }
+impl ORG {
+    pub fn new(address: u16) -> Self {
+        Self { address }
     }
 }
 
-impl Display for DEFLABEL {
+impl Display for ORG {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
+        write!(f, ".org 0x{:>04X}", self.address)
     }
 }
 
-impl Instruction for DEFLABEL {
-    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
-        Ok(vec![])
+impl Instruction for ORG {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![
+            0;
+            self.placed_size(current_address(&resolver)?)? as usize
+        ])
     }
 
     fn size(&self) -> u16 {
@@ -1014,31 +2627,38 @@ impl Instruction for DEFLABEL {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn placed_size(&self, address: u16) -> Result<u16, Error> {
+        match self.address.checked_sub(address) {
+            Some(size) => Ok(size),
+            None => Err(Error::OrgBehind(self.address, address)),
+        }
+    }
 }
 
-pub struct DEFSYMBOL {
-    pub name: String,
-    pub value: u16,
+// ALIGN - .align n, pads with 0 up to the next multiple of n
+pub struct ALIGN {
+    alignment: u16,
 }
 
-impl DEFSYMBOL {
-    pub fn new(name: &str, value: u16) -> Self {
-        Self {
-            name: name.to_string(),
-            value,
-        }
+impl ALIGN {
+    pub fn new(alignment: u16) -> Self {
+        Self { alignment }
     }
 }
 
-impl Display for DEFSYMBOL {
+impl Display for ALIGN {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "%{} = 0x{:X}", self.name, self.value)
+        write!(f, ".align 0x{:>04X}", self.alignment)
     }
 }
 
-impl Instruction for DEFSYMBOL {
-    fn emit(&self, _: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
-        Ok(vec![])
+impl Instruction for ALIGN {
+    fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error> {
+        Ok(vec![
+            0;
+            self.placed_size(current_address(&resolver)?)? as usize
+        ])
     }
 
     fn size(&self) -> u16 {
@@ -1048,6 +2668,23 @@ impl Instruction for DEFSYMBOL {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn placed_size(&self, address: u16) -> Result<u16, Error> {
+        match self.alignment {
+            0 => Err(Error::Expression(
+                "alignment must be at least 1".to_string(),
+            )),
+            alignment => Ok((alignment - address % alignment) % alignment),
+        }
+    }
+}
+
+// where a padding directive lands, without a resolver nothing is placed
+fn current_address(resolver: &Option<Rc<dyn Resolver>>) -> Result<u16, Error> {
+    match resolver {
+        Some(resolver) => resolver.symbol_resolver(&Symbol::new(CURRENTINSTRUCTION)),
+        None => Err(Error::UnknownSymbol(CURRENTINSTRUCTION.to_string())),
+    }
 }
 
 #[cfg(test)]
@@ -2001,6 +3638,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_instruction_hardware_stack_string() {
+        let instructions: Vec<(Box<dyn Instruction>, &str)> = vec![
+            (Box::new(HPUSH::new(Register::REG0)), "HPUSH R0"),
+            (Box::new(HPOP::new(Register::REG1)), "HPOP R1"),
+            (Box::new(HCALL::new(Register::REG2)), "HCALL R2"),
+            (Box::new(HRET::new()), "HRET"),
+            (Box::new(SETSP::new(Register::REG3)), "SETSP R3"),
+            (Box::new(GETSP::new(Register::REG0)), "GETSP R0"),
+            (Box::new(IRET::new()), "IRET"),
+            (Box::new(EI::new()), "EI"),
+            (Box::new(DI::new()), "DI"),
+            (Box::new(SETIV::new(Register::REG1)), "SETIV R1"),
+            (Box::new(INT::new()), "INT"),
+            (Box::new(HALT::new()), "HALT"),
+        ];
+
+        for i in instructions {
+            assert_eq!(i.0.to_string(), i.1);
+        }
+    }
+
+    #[test]
+    fn test_instruction_hardware_stack() {
+        let instructions: Vec<(Box<dyn Instruction>, u16)> = vec![
+            (Box::new(HPUSH::new(Register::REG0)), 0x0100),
+            (Box::new(HPUSH::new(Register::REG3)), 0x0103),
+            (Box::new(HPOP::new(Register::REG1)), 0x0111),
+            (Box::new(HPOP::new(Register::REG2)), 0x0112),
+            (Box::new(HCALL::new(Register::REG2)), 0x0122),
+            (Box::new(HRET::new()), 0x0130),
+            (Box::new(SETSP::new(Register::REG3)), 0x0143),
+            (Box::new(GETSP::new(Register::REG0)), 0x0150),
+            (Box::new(IRET::new()), 0x0160),
+            (Box::new(EI::new()), 0x0164),
+            (Box::new(DI::new()), 0x0168),
+            (Box::new(SETIV::new(Register::REG1)), 0x0171),
+            (Box::new(INT::new()), 0x0178),
+            (Box::new(HALT::new()), 0x0180),
+        ];
+
+        for i in instructions {
+            assert_eq!(i.0.emit(None).unwrap(), vec![i.1]);
+        }
+    }
+
     #[test]
     fn test_instruction_call_string() {
         let instructions: Vec<(Box<dyn Instruction>, &str)> = vec![
@@ -2018,11 +3701,19 @@ mod tests {
         let instructions: Vec<(Box<dyn Instruction>, Vec<u16>)> = vec![
             (
                 Box::new(CALL::new(Label::new("foo"))),
-                vec![0x0023, 0x1234, 0x0040, 0x0001],
+                vec![
+                    0x0023, 0x0403, 0x001E, 0x0023, 0x0402, 0x000F, 0x0022, 0xFFFF, 0x0060, 0x008B,
+                    0x0022, 0x0402, 0x001B, 0x0022, 0x1234, 0x001E, 0x0022, 0x0403, 0x000A, 0x0040,
+                    0x0001,
+                ],
             ),
             (
                 Box::new(CALL::new(Label::new("bar"))),
-                vec![0x0023, 0x1234, 0x0040, 0x0002],
+                vec![
+                    0x0023, 0x0403, 0x001E, 0x0023, 0x0402, 0x000F, 0x0022, 0xFFFF, 0x0060, 0x008B,
+                    0x0022, 0x0402, 0x001B, 0x0022, 0x1234, 0x001E, 0x0022, 0x0403, 0x000A, 0x0040,
+                    0x0002,
+                ],
             ),
         ];
 
@@ -2050,6 +3741,194 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_instruction_stack_string() {
+        let instructions: Vec<(Box<dyn Instruction>, &str)> = vec![
+            (Box::new(RET::new()), "RET"),
+            (Box::new(PUSH::new(Register::REG1)), "PUSH R1"),
+            (Box::new(POP::new(Register::REG2)), "POP R2"),
+        ];
+
+        for i in instructions {
+            assert_eq!(i.0.to_string(), i.1);
+        }
+    }
+
+    #[test]
+    fn test_instruction_stack() {
+        let instructions: Vec<(Box<dyn Instruction>, Vec<u16>)> = vec![
+            (
+                Box::new(RET::new()),
+                vec![
+                    0x0023, 0x0403, 0x001E, 0x0023, 0x0402, 0x000F, 0x0022, 0x0001, 0x0060, 0x008B,
+                    0x0022, 0x0402, 0x001B, 0x0022, 0xFFFF, 0x0060, 0x008B, 0x000F, 0x0022, 0x0403,
+                    0x000A, 0x0033,
+                ],
+            ),
+            (
+                Box::new(PUSH::new(Register::REG1)),
+                vec![
+                    0x0023, 0x0403, 0x001D, 0x0023, 0x0402, 0x000F, 0x0021, 0xFFFF, 0x0060, 0x0087,
+                    0x0021, 0x0402, 0x0017, 0x0021, 0x0403, 0x0005, 0x001D,
+                ],
+            ),
+            (
+                Box::new(POP::new(Register::REG0)),
+                vec![
+                    0x0023, 0x0402, 0x000F, 0x0020, 0x0001, 0x0060, 0x0083, 0x0020, 0x0402, 0x0013,
+                    0x0020, 0xFFFF, 0x0060, 0x0083, 0x000C,
+                ],
+            ),
+        ];
+
+        for i in instructions {
+            assert_eq!(i.0.size() as usize, i.1.len(), "{}", i.0);
+            assert_eq!(i.0.emit(None).unwrap(), i.1);
+        }
+
+        assert!(matches!(
+            PUSH::new(Register::REG3).emit(None),
+            Err(Error::ScratchRegister(_))
+        ));
+        assert!(matches!(
+            POP::new(Register::REG3).emit(None),
+            Err(Error::ScratchRegister(_))
+        ));
+    }
+
+    #[test]
+    fn test_instruction_arithmetic_string() {
+        let instructions: Vec<(Box<dyn Instruction>, &str)> = vec![
+            (
+                Box::new(MOV::new(Register::REG0, Register::REG1)),
+                "MOV R0, R1",
+            ),
+            (
+                Box::new(SUB::new(Register::REG2, Register::REG3)),
+                "SUB R2, R3",
+            ),
+            (Box::new(NEG::new(Register::REG1)), "NEG R1"),
+            (Box::new(INC::new(Register::REG2)), "INC R2"),
+            (Box::new(DEC::new(Register::REG3)), "DEC R3"),
+            (
+                Box::new(MUL::new(Register::REG0, Register::REG1)),
+                "MUL R0, R1",
+            ),
+            (
+                Box::new(DIV::new(Register::REG1, Register::REG2)),
+                "DIV R1, R2",
+            ),
+            (
+                Box::new(CMPI::new(Register::REG0, Number::new(0x0010))),
+                "CMPI R0, 0x0010",
+            ),
+            (
+                Box::new(CMPI::new(Register::REG2, Symbol::new("LIMIT"))),
+                "CMPI R2, %LIMIT",
+            ),
+        ];
+
+        for i in instructions {
+            assert_eq!(i.0.to_string(), i.1);
+        }
+    }
+
+    #[test]
+    fn test_instruction_arithmetic() {
+        let instructions: Vec<(Box<dyn Instruction>, Vec<u16>)> = vec![
+            (
+                Box::new(MOV::new(Register::REG0, Register::REG1)),
+                vec![0x00E5, 0x00D1],
+            ),
+            (
+                Box::new(MOV::new(Register::REG2, Register::REG2)),
+                vec![0x00DA],
+            ),
+            (
+                Box::new(SUB::new(Register::REG0, Register::REG1)),
+                vec![0x00B5, 0x0060, 0x0081, 0x00B5],
+            ),
+            (
+                Box::new(SUB::new(Register::REG3, Register::REG3)),
+                vec![0x00EF],
+            ),
+            (
+                Box::new(DEC::new(Register::REG0)),
+                vec![0x00B5, 0x0060, 0x0084, 0x00B5, 0x0060, 0x0084],
+            ),
+            (
+                Box::new(NEG::new(Register::REG2)),
+                vec![0x00B0, 0x0060, 0x0082, 0x00B0, 0x0060, 0x0082, 0x00BA],
+            ),
+            (
+                Box::new(INC::new(Register::REG3)),
+                vec![
+                    0x00BF, 0x00B0, 0x0060, 0x0083, 0x00B0, 0x0060, 0x0083, 0x00BF,
+                ],
+            ),
+            (
+                Box::new(CMPI::new(Register::REG1, Number::new(0x000A))),
+                vec![0x0023, 0x000A, 0x00F7],
+            ),
+            (
+                Box::new(CMPI::new(Register::REG1, Symbol::new("LIMIT"))),
+                vec![0x0023, 0x0064, 0x00F7],
+            ),
+            (
+                Box::new(MUL::new(Register::REG0, Register::REG1)),
+                vec![
+                    0x0023, 0x0403, 0x001C, 0x00EF, 0x0060, 0x00A0, 0x0058, 0x100A, 0x0040, 0x100C,
+                    0x0060, 0x0087, 0x0060, 0x0095, 0x00D0, 0x0051, 0x1013, 0x0040, 0x1004, 0x00E5,
+                    0x00DD, 0x0020, 0x0403, 0x0000,
+                ],
+            ),
+            (
+                Box::new(DIV::new(Register::REG1, Register::REG0)),
+                vec![
+                    0x0023, 0x0403, 0x001E, 0x0022, 0x0001, 0x00EF, 0x0060, 0x0090, 0x009F, 0x00F7,
+                    0x0054, 0x1018, 0x00BF, 0x0060, 0x0087, 0x00BF, 0x00B0, 0x00B5, 0x0060, 0x0084,
+                    0x00B5, 0x0060, 0x0084, 0x00B0, 0x0060, 0x009A, 0x0058, 0x101E, 0x0040, 0x1006,
+                    0x0022, 0x0403, 0x000A,
+                ],
+            ),
+        ];
+
+        #[derive(Clone)]
+        struct DummyResolver;
+        impl Resolver for DummyResolver {
+            fn symbol_resolver(&self, symbol: &Symbol) -> Result<u16, Error> {
+                match symbol.name.as_str() {
+                    "CURRENTINSTRUCTION" => Ok(0x1000),
+                    "LIMIT" => Ok(0x0064),
+                    _ => Err(Error::UnknownSymbol(symbol.name.clone())),
+                }
+            }
+        }
+
+        for i in instructions {
+            assert_eq!(i.0.size() as usize, i.1.len(), "{}", i.0);
+            assert_eq!(i.0.emit(Some(Rc::new(DummyResolver))).unwrap(), i.1);
+        }
+
+        let resolver: Option<Rc<dyn Resolver>> = Some(Rc::new(DummyResolver));
+        assert!(matches!(
+            MUL::new(Register::REG1, Register::REG1).emit(resolver.clone()),
+            Err(Error::SameRegister(_))
+        ));
+        assert!(matches!(
+            DIV::new(Register::REG3, Register::REG0).emit(resolver.clone()),
+            Err(Error::ScratchRegister(_))
+        ));
+        assert!(matches!(
+            CMPI::new(Register::REG3, Number::new(0x0001)).emit(resolver),
+            Err(Error::ScratchRegister(_))
+        ));
+        assert!(matches!(
+            MUL::new(Register::REG0, Register::REG1).emit(None),
+            Err(Error::UnknownSymbol(_))
+        ));
+    }
+
     #[test]
     fn test_instruction_jmp_flag_string() {
         let instructions: Vec<(JMPF, &str)> = vec![
@@ -2254,4 +4133,41 @@ mod tests {
             assert_eq!(i.0.emit(Some(Rc::new(DummyResolver))).unwrap(), i.1);
         }
     }
+
+    #[test]
+    fn test_instruction_padding() {
+        #[derive(Clone)]
+        struct DummyResolver;
+        impl Resolver for DummyResolver {
+            fn symbol_resolver(&self, symbol: &Symbol) -> Result<u16, Error> {
+                match symbol.name.as_str() {
+                    "CURRENTINSTRUCTION" => Ok(0x0503),
+                    _ => Err(Error::UnknownSymbol(symbol.name.clone())),
+                }
+            }
+
+            fn label_resolver(&self, label: &Label) -> Result<u16, Error> {
+                Err(Error::UnknownLabel(label.name.clone()))
+            }
+        }
+
+        let resolver: Option<Rc<dyn Resolver>> = Some(Rc::new(DummyResolver));
+        assert_eq!(ORG::new(0x0505).emit(resolver.clone()).unwrap(), vec![0; 2]);
+        assert_eq!(ALIGN::new(4).emit(resolver.clone()).unwrap(), vec![0]);
+
+        // nothing to pad from without a resolver, and no multiple of 0
+        assert!(matches!(
+            ORG::new(0x0505).emit(None),
+            Err(Error::UnknownSymbol(_))
+        ));
+        assert!(matches!(
+            ALIGN::new(4).emit(None),
+            Err(Error::UnknownSymbol(_))
+        ));
+        assert_eq!(
+            ALIGN::new(0).emit(resolver).unwrap_err().to_string(),
+            "alignment must be at least 1"
+        );
+        assert!(ALIGN::new(0).placed_size(0x0500).is_err());
+    }
 }
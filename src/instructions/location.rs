@@ -0,0 +1,25 @@
+use std::fmt::Display;
+
+// SourceLocation - a position inside an assembly source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    pub fn new(file: &str, line: usize, column: usize) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+        }
+    }
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
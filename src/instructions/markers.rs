@@ -96,3 +96,24 @@ impl Display for Number {
         write!(f, "0x{:>04X}", self.value)
     }
 }
+
+// labels starting with '.' belong to the last global label before them, so
+// main.loop is written .loop anywhere between main: and the next global label
+pub fn is_local_label(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+// anonymous labels are only digits and may be defined any number of times
+pub fn is_anonymous_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+}
+
+// 1b refers to the nearest 1: before, 1f to the nearest 1: after, gives the
+// anonymous label and whether it is forward
+pub fn anonymous_reference(name: &str) -> Option<(&str, bool)> {
+    let (label, forward) = match name.strip_suffix('f') {
+        Some(label) => (label, true),
+        None => (name.strip_suffix('b')?, false),
+    };
+    is_anonymous_label(label).then_some((label, forward))
+}
@@ -1,16 +1,38 @@
-use std::{any::Any, fmt::Display, rc::Rc};
+use std::{any::Any, collections::HashMap, fmt::Display, rc::Rc};
 
+mod diagnostic;
 mod error;
+mod expression;
 mod instructions;
+mod location;
 mod markers;
 
+pub use diagnostic::{Diagnostic, Severity};
 pub use error::Error;
+pub use expression::{Expression, Function, Node, Operator};
 pub use instructions::*;
-pub use markers::{Label, Marker, Number, Symbol};
+pub use location::SourceLocation;
+pub use markers::{
+    anonymous_reference, is_anonymous_label, is_local_label, Label, Marker, Number, Symbol,
+};
 
 pub const CURRENTINSTRUCTION: &'static str = "CURRENTINSTRUCTION";
 pub const NEXTINSTRUCTION: &'static str = "NEXTINSTRUCTION";
 
+// the software stack used by PUSH, POP, CALL and RET lives in the system
+// region below user code, STACK_POINTER holds the address of the last word
+// pushed and starts at STACK_TOP when the stack is empty
+// interrupt handlers must leave it alone, see INTERRUPTS in computer::cpu
+pub const STACK_POINTER: u16 = 0x0402;
+pub const STACK_SCRATCH: u16 = 0x0403;
+pub const STACK_BOTTOM: u16 = 0x0404;
+pub const STACK_TOP: u16 = 0x0500;
+
+// the HPUSH, HPOP, HCALL and HRET opcodes keep their own stack pointer in the
+// CPU, boot points it here so the stack grows down from below the jump at
+// the end of the code region
+pub const HARDWARE_STACK_TOP: u16 = 0xFEFE;
+
 pub trait Resolver: ResolverClone {
     fn label_resolver(&self, _: &Label) -> Result<u16, Error> {
         Ok(0)
@@ -43,6 +65,34 @@ pub trait Instruction: Any + Display {
     fn emit(&self, resolver: Option<Rc<dyn Resolver>>) -> Result<Vec<u16>, Error>;
     fn size(&self) -> u16;
     fn as_any(&self) -> &dyn Any;
+    // the emitted words that hold an address, as (word offset, reference),
+    // object files need them to relocate the instruction
+    fn references(&self) -> Vec<(u16, Reference)> {
+        Vec::new()
+    }
+    // the size once placed at address, padding directives depend on where
+    // they land
+    fn placed_size(&self, _address: u16) -> Result<u16, Error> {
+        Ok(self.size())
+    }
+    // the registers read and written, for the assembler's warnings, reads
+    // are in operand order
+    fn reads(&self) -> Vec<Register> {
+        Vec::new()
+    }
+    fn writes(&self) -> Vec<Register> {
+        Vec::new()
+    }
+}
+
+// Reference - a label or symbol an operand word was resolved from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reference {
+    Label(String),
+    Symbol(String),
+    // worked out from these names in a way no relocation can follow, like
+    // hi(label) or label * 2
+    Fixed(Vec<Reference>),
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -85,17 +135,35 @@ impl Display for IOMode {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum SectionKind {
+    Code = 0,
+    Data = 1,
+}
+
+impl Display for SectionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SectionKind::Code => write!(f, "code"),
+            SectionKind::Data => write!(f, "data"),
+        }
+    }
+}
+
 // Instructions - useful list data structure for convienience
 pub type SafeInstruction = Rc<dyn Instruction>;
 #[derive(Clone)]
 pub struct Instructions {
     pub instructions: Vec<SafeInstruction>,
+    // source locations by instruction index, for parsed instructions
+    locations: HashMap<usize, SourceLocation>,
 }
 
 impl Instructions {
     pub fn new() -> Self {
         Self {
             instructions: Vec::new(),
+            locations: HashMap::new(),
         }
     }
 
@@ -105,6 +173,16 @@ impl Instructions {
         }
     }
 
+    // adds an instruction and remembers where it came from
+    pub fn add_at(&mut self, ins: SafeInstruction, location: SourceLocation) {
+        self.locations.insert(self.instructions.len(), location);
+        self.instructions.push(ins);
+    }
+
+    pub fn location(&self, index: usize) -> Option<&SourceLocation> {
+        self.locations.get(&index)
+    }
+
     pub fn add_blocks(&mut self, blocks: Vec<Vec<SafeInstruction>>) {
         for block in blocks {
             self.add(block);
@@ -11,11 +11,35 @@ mod instructions;
 
 mod computer;
 
+mod debugger;
+
 mod glfw;
 
-pub use assembler::Assembler;
-pub use computer::{Computer, Keyboard, PrintStateConfig};
+pub use assembler::{
+    bytes_from_words, intel_hex, is_intel_hex, is_logisim_image, logisim_image, parse_intel_hex,
+    parse_logisim_image, read_binary, words_from_bytes, write_binary, AsmParser, Assembler,
+    Disassembler, HexError, Image, LinkError, LinkedSymbol, Linker, Listing, ListingLine, MapEntry,
+    MapKind, MemoryRegion, Object, ObjectError, ObjectSymbol, PlacedSection, Relocation,
+    RelocationTarget, Section, MEMORY_MAP,
+};
+pub use computer::{
+    Computer, CoreKind, CpuState, Divergence, Flags, HeadlessConfig, Keyboard, LoadError, Lockstep,
+    PrintStateConfig, RunLimit, RunReport, SerialHost, Snapshot, SnapshotError, StopReason,
+    WatchKind,
+};
+pub use debugger::Debugger;
 pub use generator::get_instructions;
 pub use glfw::glfw_run;
+pub use instructions::{Diagnostic, SectionKind, Severity};
 
 pub const USER_CODE_START: u16 = 0x0500;
+
+// a 16 bit number, hex with a 0x prefix or decimal, as addresses and counts
+// are written on command lines
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    }
+    .map_err(|_| format!("invalid number '{}'", s))
+}